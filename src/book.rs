@@ -0,0 +1,508 @@
+use std::collections::BTreeMap;
+
+use binance::model::{DepthOrderBookEvent, OrderBook};
+use serde::{Deserialize, Serialize};
+
+// levels are keyed in cents so prices order and match exactly
+fn price_key(price: f64) -> i64 {
+    (price * 100.0).round() as i64
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Level {
+    pub price: f64,
+    // in contracts
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BookLevel {
+    pub is_ask: bool,
+    pub price: f64,
+    pub size: u64,
+}
+
+// LedgerX book-state and book-delta messages for a single contract
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind")]
+pub enum BookUpdate {
+    // every resting level as of `clock`
+    Snapshot { contract_id: u64, clock: u64, levels: Vec<BookLevel> },
+    // the new total size at one level; zero removes it
+    Delta { contract_id: u64, clock: u64, level: BookLevel },
+}
+
+impl BookUpdate {
+    pub fn contract_id(&self) -> u64 {
+        match self {
+            BookUpdate::Snapshot { contract_id, .. } | BookUpdate::Delta { contract_id, .. } => *contract_id,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BookError {
+    // a delta arrived without a snapshot to apply it to
+    NotSynced,
+    // deltas were missed; the book needs a fresh snapshot
+    Gap { expected: u64, got: u64 },
+}
+
+// L2 book for one LedgerX contract. Without a snapshot only the top of book is known,
+// and deeper levels are never trusted.
+#[derive(Debug, Default)]
+pub struct LedgerXOrderbook {
+    bids: BTreeMap<i64, Level>,
+    asks: BTreeMap<i64, Level>,
+    // last snapshot or delta applied; deltas must follow it without a gap
+    clock: Option<u64>,
+    // last BookTop applied, which runs on its own sequence
+    top_clock: Option<u64>,
+    // (bid, bid size, ask, ask size) as of `top_clock`
+    top: Option<(f64, u64, f64, u64)>,
+    synced: bool,
+}
+
+impl LedgerXOrderbook {
+    pub fn is_synced(&self) -> bool {
+        self.synced
+    }
+
+    pub fn clock(&self) -> Option<u64> {
+        self.clock
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    fn side_mut(&mut self, is_ask: bool) -> &mut BTreeMap<i64, Level> {
+        if is_ask { &mut self.asks } else { &mut self.bids }
+    }
+
+    fn set_level(&mut self, is_ask: bool, price: f64, size: u64) {
+        let side = self.side_mut(is_ask);
+        if size == 0 {
+            side.remove(&price_key(price));
+        } else {
+            side.insert(price_key(price), Level { price, size });
+        }
+    }
+
+    pub fn apply(&mut self, update: &BookUpdate) -> Result<(), BookError> {
+        match update {
+            BookUpdate::Snapshot { clock, levels, .. } => {
+                self.bids.clear();
+                self.asks.clear();
+                for level in levels.iter() {
+                    self.set_level(level.is_ask, level.price, level.size);
+                }
+                self.clock = Some(*clock);
+                self.synced = true;
+                // a snapshot is polled and can be seconds old; the streamed top stays authoritative
+                self.reapply_top();
+                Ok(())
+            },
+            BookUpdate::Delta { clock, level, .. } => {
+                let have = match (self.synced, self.clock) {
+                    (true, Some(have)) => have,
+                    _ => return Err(BookError::NotSynced),
+                };
+                if *clock <= have {
+                    // already reflected in the snapshot
+                    return Ok(());
+                }
+                if *clock != have + 1 {
+                    self.synced = false;
+                    return Err(BookError::Gap { expected: have + 1, got: *clock });
+                }
+                self.set_level(level.is_ask, level.price, level.size);
+                self.clock = Some(*clock);
+                Ok(())
+            },
+        }
+    }
+
+    // a BookTop is authoritative for the best level on each side; a zero price or size
+    // means the side is empty
+    pub fn apply_top(&mut self, bid: f64, bid_size: u64, ask: f64, ask_size: u64, clock: u64) {
+        if matches!(self.top_clock, Some(have) if clock < have) {
+            return;
+        }
+        self.top = Some((bid, bid_size, ask, ask_size));
+        self.top_clock = Some(clock);
+        self.reapply_top();
+    }
+
+    fn reapply_top(&mut self) {
+        let (bid, bid_size, ask, ask_size) = match self.top {
+            Some(top) => top,
+            None => return,
+        };
+        for (is_ask, price, size) in [(false, bid, bid_size), (true, ask, ask_size)] {
+            let empty = price <= 0.0 || size == 0;
+            let synced = self.synced;
+            let side = self.side_mut(is_ask);
+            if !synced || empty {
+                side.clear();
+            } else {
+                // anything better than the new top has gone
+                let key = price_key(price);
+                side.retain(|&k, _| if is_ask { k >= key } else { k <= key });
+            }
+            if !empty {
+                self.set_level(is_ask, price, size);
+            }
+        }
+    }
+
+    // best first
+    pub fn levels(&self, is_ask: bool) -> Vec<Level> {
+        if is_ask {
+            self.asks.values().cloned().collect()
+        } else {
+            self.bids.values().rev().cloned().collect()
+        }
+    }
+
+    pub fn best_bid(&self) -> Option<Level> {
+        self.bids.values().next_back().cloned()
+    }
+
+    pub fn best_ask(&self) -> Option<Level> {
+        self.asks.values().next().cloned()
+    }
+}
+
+// contracts two books can trade against each other level by level, up to `limit`, while
+// `clears` holds for the running total and the marginal pair of prices; returns the size
+// and the worst price reached on each
+pub fn sweep_pair<F>(a: &[Level], b: &[Level], limit: u64, clears: F) -> Option<(u64, f64, f64)>
+where
+    F: Fn(u64, f64, f64) -> bool,
+{
+    let (mut i, mut j) = (0, 0);
+    let (mut a_left, mut b_left) = (a.first()?.size, b.first()?.size);
+    let mut out: Option<(u64, f64, f64)> = None;
+
+    let mut total = 0;
+
+    while i < a.len() && j < b.len() && total < limit {
+        let take = a_left.min(b_left).min(limit - total);
+        if !clears(total + take, a[i].price, b[j].price) {
+            // whatever else `clears` depends on may run out part way through the level;
+            // it's assumed to only get worse with size
+            let (mut lo, mut hi) = (0, take);
+            while hi - lo > 1 {
+                let mid = (lo + hi) / 2;
+                if clears(total + mid, a[i].price, b[j].price) { lo = mid } else { hi = mid }
+            }
+            if lo > 0 {
+                total += lo;
+                out = Some((total, a[i].price, b[j].price));
+            }
+            break;
+        }
+        total += take;
+        out = Some((total, a[i].price, b[j].price));
+
+        a_left -= take;
+        b_left -= take;
+        if a_left == 0 {
+            i += 1;
+            a_left = a.get(i).map(|l| l.size).unwrap_or(0);
+        }
+        if b_left == 0 {
+            j += 1;
+            b_left = b.get(j).map(|l| l.size).unwrap_or(0);
+        }
+    }
+    out
+}
+
+// the price of the level the `size`th contract is taken at, best first; None past the
+// end of the book
+pub fn price_at(levels: &[Level], size: u64) -> Option<f64> {
+    let mut total = 0;
+    for level in levels.iter() {
+        total += level.size;
+        if total >= size {
+            return Some(level.price);
+        }
+    }
+    None
+}
+
+// Binance prices go to 8 decimals
+fn spot_price_key(price: f64) -> i64 {
+    (price * 1e8).round() as i64
+}
+
+// a Binance depth snapshot from the REST endpoint, which diffs are applied on top of
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpotDepthSnapshot {
+    pub symbol: String,
+    pub last_update_id: u64,
+    // (price, qty)
+    pub bids: Vec<(f64, f64)>,
+    pub asks: Vec<(f64, f64)>,
+}
+
+impl SpotDepthSnapshot {
+    pub fn from_rest(symbol: &str, book: OrderBook) -> Self {
+        Self {
+            symbol: symbol.to_owned(),
+            last_update_id: book.last_update_id,
+            bids: book.bids.iter().map(|l| (l.price, l.qty)).collect(),
+            asks: book.asks.iter().map(|l| (l.price, l.qty)).collect(),
+        }
+    }
+}
+
+// local copy of a Binance spot book, kept from a snapshot plus diff-depth events
+#[derive(Debug, Default)]
+pub struct SpotOrderbook {
+    bids: BTreeMap<i64, (f64, f64)>,
+    asks: BTreeMap<i64, (f64, f64)>,
+    last_update_id: Option<u64>,
+}
+
+impl SpotOrderbook {
+    pub fn is_synced(&self) -> bool {
+        self.last_update_id.is_some()
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    fn set_level(side: &mut BTreeMap<i64, (f64, f64)>, price: f64, qty: f64) {
+        if qty <= 0.0 {
+            side.remove(&spot_price_key(price));
+        } else {
+            side.insert(spot_price_key(price), (price, qty));
+        }
+    }
+
+    pub fn apply_snapshot(&mut self, snapshot: &SpotDepthSnapshot) {
+        self.clear();
+        for &(price, qty) in snapshot.bids.iter() {
+            Self::set_level(&mut self.bids, price, qty);
+        }
+        for &(price, qty) in snapshot.asks.iter() {
+            Self::set_level(&mut self.asks, price, qty);
+        }
+        self.last_update_id = Some(snapshot.last_update_id);
+    }
+
+    // per Binance: drop events the snapshot already covers, and the first one applied
+    // must straddle it; a gap after that empties the book until the next snapshot
+    pub fn apply_diff(&mut self, event: &DepthOrderBookEvent) -> Result<(), BookError> {
+        let have = self.last_update_id.ok_or(BookError::NotSynced)?;
+        if event.final_update_id <= have {
+            return Ok(());
+        }
+        if event.first_update_id > have + 1 {
+            self.clear();
+            return Err(BookError::Gap { expected: have + 1, got: event.first_update_id });
+        }
+        for level in event.bids.iter() {
+            Self::set_level(&mut self.bids, level.price, level.qty);
+        }
+        for level in event.asks.iter() {
+            Self::set_level(&mut self.asks, level.price, level.qty);
+        }
+        self.last_update_id = Some(event.final_update_id);
+        Ok(())
+    }
+
+    // levels a market order on this side would take from, best first
+    fn takes_from(&self, is_buy: bool) -> Box<dyn Iterator<Item = &(f64, f64)> + '_> {
+        if is_buy {
+            Box::new(self.asks.values())
+        } else {
+            Box::new(self.bids.values().rev())
+        }
+    }
+
+    pub fn depth(&self, is_buy: bool) -> f64 {
+        self.takes_from(is_buy).map(|&(_, qty)| qty).sum()
+    }
+
+    // price of the last unit a market order for `qty` would take
+    pub fn marginal(&self, is_buy: bool, qty: f64) -> Option<f64> {
+        let mut left = qty;
+        for &(price, level_qty) in self.takes_from(is_buy) {
+            left -= level_qty;
+            if left <= 0.0 {
+                return Some(price);
+            }
+        }
+        None
+    }
+
+    // average fill of a market order for `qty`; None if the book isn't that deep
+    pub fn vwap(&self, is_buy: bool, qty: f64) -> Option<f64> {
+        if qty <= 0.0 {
+            return None;
+        }
+        let (mut left, mut notional) = (qty, 0.0);
+        for &(price, level_qty) in self.takes_from(is_buy) {
+            let take = left.min(level_qty);
+            notional += take * price;
+            left -= take;
+            if left <= 0.0 {
+                return Some(notional / qty);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use binance::model::{Asks, Bids, DepthOrderBookEvent};
+
+    use super::{price_at, sweep_pair, BookError, BookLevel, BookUpdate, LedgerXOrderbook, Level, SpotDepthSnapshot, SpotOrderbook};
+
+    fn level(is_ask: bool, price: f64, size: u64) -> BookLevel {
+        BookLevel { is_ask, price, size }
+    }
+
+    #[test]
+    fn snapshot_deltas_and_gaps() {
+        let mut book = LedgerXOrderbook::default();
+        let delta = |clock, level| BookUpdate::Delta { contract_id: 1, clock, level };
+        assert_eq!(book.apply(&delta(1, level(false, 500.0, 3))), Err(BookError::NotSynced));
+
+        book.apply(&BookUpdate::Snapshot {
+            contract_id: 1,
+            clock: 10,
+            levels: vec![level(false, 500.0, 3), level(false, 490.0, 7), level(true, 580.0, 2), level(true, 600.0, 9)],
+        }).unwrap();
+        assert_eq!(book.best_bid(), Some(Level { price: 500.0, size: 3 }));
+        assert_eq!(book.levels(true).iter().map(|l| l.price).collect::<Vec<_>>(), vec![580.0, 600.0]);
+
+        // stale, then in sequence, then one missed
+        book.apply(&delta(9, level(false, 510.0, 1))).unwrap();
+        book.apply(&delta(11, level(true, 580.0, 0))).unwrap();
+        assert_eq!(book.best_ask(), Some(Level { price: 600.0, size: 9 }));
+        assert_eq!(book.apply(&delta(13, level(true, 590.0, 1))), Err(BookError::Gap { expected: 12, got: 13 }));
+        assert!(!book.is_synced());
+    }
+
+    #[test]
+    fn top_of_book_reconciles() {
+        let mut book = LedgerXOrderbook::default();
+
+        // without a snapshot a top replaces the whole side
+        book.apply_top(500.0, 3, 580.0, 2, 1);
+        book.apply_top(495.0, 1, 580.0, 2, 2);
+        assert_eq!(book.levels(false), vec![Level { price: 495.0, size: 1 }]);
+
+        book.apply(&BookUpdate::Snapshot {
+            contract_id: 1,
+            clock: 3,
+            levels: vec![level(false, 495.0, 1), level(false, 490.0, 7), level(true, 580.0, 2), level(true, 600.0, 9)],
+        }).unwrap();
+        // the 580 offer was lifted: deeper levels survive, the empty bid side clears
+        book.apply_top(0.0, 0, 600.0, 4, 4);
+        assert_eq!(book.best_bid(), None);
+        assert_eq!(book.levels(true), vec![Level { price: 600.0, size: 4 }]);
+
+        // tops don't move the delta sequence on, nor do deltas hold back a top
+        book.apply_top(0.0, 0, 600.0, 4, 100);
+        book.apply(&BookUpdate::Delta { contract_id: 1, clock: 4, level: level(false, 485.0, 2) }).unwrap();
+        assert_eq!(book.clock(), Some(4));
+        assert_eq!(book.best_bid(), Some(Level { price: 485.0, size: 2 }));
+        book.apply_top(480.0, 1, 600.0, 4, 50);
+        assert_eq!(book.best_bid(), Some(Level { price: 485.0, size: 2 }));
+    }
+
+    #[test]
+    fn snapshot_keeps_newer_top() {
+        let mut book = LedgerXOrderbook::default();
+        book.apply_top(500.0, 3, 580.0, 2, 1);
+
+        // polled before the 505 bid was hit and the 580 offer grew
+        book.apply(&BookUpdate::Snapshot {
+            contract_id: 1,
+            clock: 7,
+            levels: vec![level(false, 505.0, 4), level(false, 500.0, 3), level(false, 490.0, 7), level(true, 580.0, 1), level(true, 600.0, 9)],
+        }).unwrap();
+        assert_eq!(book.levels(false), vec![Level { price: 500.0, size: 3 }, Level { price: 490.0, size: 7 }]);
+        assert_eq!(book.levels(true), vec![Level { price: 580.0, size: 2 }, Level { price: 600.0, size: 9 }]);
+    }
+
+    #[test]
+    fn sweep_stops_when_edge_runs_out() {
+        let bids = [Level { price: 100.0, size: 5 }, Level { price: 98.0, size: 5 }, Level { price: 95.0, size: 5 }];
+        let asks = [Level { price: 10.0, size: 3 }, Level { price: 11.0, size: 20 }];
+
+        // sell into bids, buy from asks while the spread is at least 86
+        let swept = sweep_pair(&bids, &asks, u64::MAX, |_, bid, ask| bid - ask >= 86.0);
+        assert_eq!(swept, Some((10, 98.0, 11.0)));
+        assert_eq!(sweep_pair(&bids, &asks, 4, |_, bid, ask| bid - ask >= 86.0), Some((4, 100.0, 11.0)));
+        assert_eq!(sweep_pair(&bids, &asks, u64::MAX, |_, bid, ask| bid - ask >= 95.0), None);
+        assert_eq!(sweep_pair(&[], &asks, u64::MAX, |_, _, _| true), None);
+        // something else running out part way through a level, e.g. the hedge
+        assert_eq!(sweep_pair(&bids, &asks, u64::MAX, |total, _, _| total <= 8), Some((8, 98.0, 11.0)));
+
+        assert_eq!(price_at(&bids, 5), Some(100.0));
+        assert_eq!(price_at(&bids, 6), Some(98.0));
+        assert_eq!(price_at(&bids, 16), None);
+    }
+
+    fn diff(first_update_id: u64, final_update_id: u64, bids: &[(f64, f64)], asks: &[(f64, f64)]) -> DepthOrderBookEvent {
+        DepthOrderBookEvent {
+            event_type: "depthUpdate".to_string(),
+            event_time: 0,
+            symbol: "BTCUSDT".to_string(),
+            first_update_id,
+            final_update_id,
+            bids: bids.iter().map(|&(price, qty)| Bids { price, qty }).collect(),
+            asks: asks.iter().map(|&(price, qty)| Asks { price, qty }).collect(),
+        }
+    }
+
+    #[test]
+    fn spot_diffs_follow_sequence() {
+        let mut book = SpotOrderbook::default();
+        assert_eq!(book.apply_diff(&diff(1, 2, &[], &[])), Err(BookError::NotSynced));
+
+        book.apply_snapshot(&SpotDepthSnapshot {
+            symbol: "BTCUSDT".to_string(),
+            last_update_id: 100,
+            bids: vec![(20449.0, 1.0), (20448.0, 2.0)],
+            asks: vec![(20450.0, 0.5), (20451.0, 1.0), (20455.0, 4.0)],
+        });
+
+        // covered by the snapshot, then straddling it, then in sequence
+        book.apply_diff(&diff(90, 100, &[(20449.0, 0.0)], &[])).unwrap();
+        assert_eq!(book.depth(false), 3.0);
+        book.apply_diff(&diff(95, 105, &[], &[(20450.0, 0.25)])).unwrap();
+        book.apply_diff(&diff(106, 110, &[(20448.0, 0.0)], &[])).unwrap();
+        assert_eq!(book.depth(false), 1.0);
+        assert_eq!(book.depth(true), 5.25);
+
+        assert_eq!(book.apply_diff(&diff(112, 115, &[], &[])), Err(BookError::Gap { expected: 111, got: 112 }));
+        assert!(!book.is_synced());
+    }
+
+    #[test]
+    fn spot_vwap_walks_levels() {
+        let mut book = SpotOrderbook::default();
+        book.apply_snapshot(&SpotDepthSnapshot {
+            symbol: "BTCUSDT".to_string(),
+            last_update_id: 1,
+            bids: vec![(99.0, 1.0), (98.0, 1.0)],
+            asks: vec![(100.0, 1.0), (102.0, 1.0)],
+        });
+        assert_eq!(book.vwap(true, 0.5), Some(100.0));
+        assert_eq!(book.vwap(true, 2.0), Some(101.0));
+        assert_eq!(book.vwap(false, 1.5), Some((99.0 + 49.0) / 1.5));
+        assert_eq!(book.marginal(true, 1.5), Some(102.0));
+        assert_eq!(book.vwap(true, 2.5), None);
+        assert_eq!(book.marginal(false, 3.0), None);
+    }
+}
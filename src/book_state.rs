@@ -0,0 +1,103 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::book::{BookLevel, BookUpdate};
+
+// a slow book-state request shouldn't hold up the rest of the pass
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Deserialize)]
+struct BookStateResponse {
+    data: BookState,
+}
+
+#[derive(Debug, Deserialize)]
+struct BookState {
+    contract_id: u64,
+    clock: u64,
+    // one entry per resting order, not per level
+    book_states: Vec<RestingOrder>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RestingOrder {
+    is_ask: bool,
+    // in cents
+    price: u64,
+    size: u64,
+}
+
+impl BookState {
+    fn into_snapshot(self) -> BookUpdate {
+        let mut levels: BTreeMap<(bool, u64), u64> = BTreeMap::new();
+        for order in self.book_states.iter().filter(|o| o.size > 0) {
+            *levels.entry((order.is_ask, order.price)).or_default() += order.size;
+        }
+        BookUpdate::Snapshot {
+            contract_id: self.contract_id,
+            clock: self.clock,
+            levels: levels.into_iter()
+                .map(|((is_ask, price), size)| BookLevel { is_ask, price: price as f64 / 100.0, size })
+                .collect(),
+        }
+    }
+}
+
+// full depth of a LedgerX contract from the REST book-state endpoint; the websocket
+// only ever sends the top of book
+pub struct BookStateClient {
+    http: reqwest::blocking::Client,
+    base_url: String,
+    api_key: String,
+}
+
+impl BookStateClient {
+    pub fn new(base_url: &str, api_key: &str) -> Self {
+        Self {
+            http: reqwest::blocking::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_owned(),
+            api_key: api_key.to_owned(),
+        }
+    }
+
+    pub fn snapshot(&self, contract_id: u64) -> Result<BookUpdate, reqwest::Error> {
+        let url = format!("{}/trading/contracts/{}/book-state", self.base_url, contract_id);
+        let resp: BookStateResponse = self.http.get(url)
+            .header("Authorization", format!("JWT {}", self.api_key))
+            .timeout(REQUEST_TIMEOUT)
+            .send()?
+            .error_for_status()?
+            .json()?;
+        Ok(resp.data.into_snapshot())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::book::{BookLevel, BookUpdate};
+    use super::BookStateResponse;
+
+    #[test]
+    fn orders_aggregate_into_levels() {
+        let raw = r#"{"data": {"contract_id": 22248027, "clock": 41, "book_states": [
+            {"contract_id": 22248027, "price": 1107000, "size": 30, "is_ask": false, "clock": 40, "mid": "a"},
+            {"contract_id": 22248027, "price": 1107000, "size": 20, "is_ask": false, "clock": 41, "mid": "b"},
+            {"contract_id": 22248027, "price": 1100000, "size": 5, "is_ask": false, "clock": 12, "mid": "c"},
+            {"contract_id": 22248027, "price": 1118000, "size": 0, "is_ask": true, "clock": 33, "mid": "d"},
+            {"contract_id": 22248027, "price": 1120000, "size": 7, "is_ask": true, "clock": 35, "mid": "e"}
+        ]}}"#;
+        let resp: BookStateResponse = serde_json::from_str(raw).unwrap();
+        let snapshot = resp.data.into_snapshot();
+        assert_eq!(snapshot, BookUpdate::Snapshot {
+            contract_id: 22248027,
+            clock: 41,
+            levels: vec![
+                BookLevel { is_ask: false, price: 11000.0, size: 5 },
+                BookLevel { is_ask: false, price: 11070.0, size: 50 },
+                BookLevel { is_ask: true, price: 11200.0, size: 7 },
+            ],
+        });
+    }
+}
@@ -0,0 +1,262 @@
+use serde::{Deserialize, Serialize};
+
+use crate::symbols::SymbolMapping;
+
+// Binance perpetuals fund every 8 hours; margin interest is quoted per day
+const FUNDING_PER_YEAR: f64 = 3.0 * 365.0;
+const DAYS_PER_YEAR: f64 = 365.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CarrySide {
+    // long spot against a short synthetic (conversions), paying for the cash it ties up
+    Long,
+    // short spot against a long synthetic (reversals), paying to borrow the coin
+    Short,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FundingKind {
+    // a USD-M perpetual's funding rate, per funding interval
+    Perpetual,
+    // what Binance margin charges per day to borrow an asset
+    MarginInterest,
+}
+
+// a rate as Binance publishes it; `symbol` is the perpetual (BTCUSDT) for funding and the
+// asset (BTC, USDT) for margin interest
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FundingUpdate {
+    pub kind: FundingKind,
+    pub symbol: String,
+    pub rate: f64,
+}
+
+impl FundingUpdate {
+    pub fn annualised(&self) -> f64 {
+        match self.kind {
+            FundingKind::Perpetual => self.rate * FUNDING_PER_YEAR,
+            FundingKind::MarginInterest => self.rate * DAYS_PER_YEAR,
+        }
+    }
+}
+
+// What holding the spot leg of a combo to expiry costs, as the annual rate its strike is
+// discounted at. Conversions and reversals hold spot opposite ways, so each asks for its side.
+pub trait CarryModel {
+    // the cash tied up in long spot
+    fn funding_rate(&self, tte: f64) -> f64;
+    // borrowing the coin to short it
+    fn borrow_rate(&self, tte: f64) -> f64;
+    // a short's proceeds stand in for cash, less what borrowing the coin costs
+    fn rate(&self, side: CarrySide, tte: f64) -> f64 {
+        match side {
+            CarrySide::Long => self.funding_rate(tte),
+            CarrySide::Short => self.funding_rate(tte) - self.borrow_rate(tte),
+        }
+    }
+    // rates from the feed; true if any this model prices with changed
+    fn on_funding(&mut self, _update: &FundingUpdate) -> bool {
+        false
+    }
+}
+
+// the same rates at every expiry
+pub struct ConstantCarry {
+    pub funding: f64,
+    pub borrow: f64,
+}
+
+impl CarryModel for ConstantCarry {
+    fn funding_rate(&self, _tte: f64) -> f64 {
+        self.funding
+    }
+    fn borrow_rate(&self, _tte: f64) -> f64 {
+        self.borrow
+    }
+}
+
+// funding at fixed tenors, linear between them and flat past either end
+pub struct TermStructureCarry {
+    // (years, rate), by tenor
+    tenors: Vec<(f64, f64)>,
+    borrow: f64,
+}
+
+impl TermStructureCarry {
+    pub fn new(tenors: &[TenorRate], borrow: f64) -> Self {
+        let mut tenors: Vec<(f64, f64)> = tenors.iter().map(|t| (t.days / DAYS_PER_YEAR, t.rate)).collect();
+        tenors.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Self { tenors, borrow }
+    }
+}
+
+impl CarryModel for TermStructureCarry {
+    fn borrow_rate(&self, _tte: f64) -> f64 {
+        self.borrow
+    }
+    fn funding_rate(&self, tte: f64) -> f64 {
+        let above = self.tenors.partition_point(|&(t, _)| t < tte);
+        match (above.checked_sub(1).and_then(|i| self.tenors.get(i)), self.tenors.get(above)) {
+            (Some(&(t0, r0)), Some(&(t1, r1))) => r0 + (r1 - r0) * (tte - t0) / (t1 - t0),
+            (Some(&(_, r)), None) | (None, Some(&(_, r))) => r,
+            (None, None) => 0.0,
+        }
+    }
+}
+
+// Binance rates for one pair. Holding the coin costs what borrowing the cash does, or the
+// perpetual's funding until margin interest is known; borrowing it is its margin interest.
+// The configured rates cover the time before the feed has said anything.
+pub struct FundingCarry {
+    perpetual: String,
+    coin: String,
+    cash: String,
+    fallback: ConstantCarry,
+    funding: Option<f64>,
+    coin_borrow: Option<f64>,
+    cash_borrow: Option<f64>,
+}
+
+impl FundingCarry {
+    pub fn new(perpetual: &str, coin: &str, cash: &str, fallback: ConstantCarry) -> Self {
+        Self {
+            perpetual: perpetual.to_uppercase(),
+            coin: coin.to_uppercase(),
+            cash: cash.to_uppercase(),
+            fallback,
+            funding: None,
+            coin_borrow: None,
+            cash_borrow: None,
+        }
+    }
+}
+
+impl CarryModel for FundingCarry {
+    fn funding_rate(&self, _tte: f64) -> f64 {
+        self.cash_borrow.or(self.funding).unwrap_or(self.fallback.funding)
+    }
+    fn borrow_rate(&self, _tte: f64) -> f64 {
+        self.coin_borrow.unwrap_or(self.fallback.borrow)
+    }
+
+    fn on_funding(&mut self, update: &FundingUpdate) -> bool {
+        let slot = match update.kind {
+            FundingKind::Perpetual if update.symbol == self.perpetual => &mut self.funding,
+            FundingKind::MarginInterest if update.symbol == self.coin => &mut self.coin_borrow,
+            FundingKind::MarginInterest if update.symbol == self.cash => &mut self.cash_borrow,
+            _ => return false,
+        };
+        let rate = Some(update.annualised());
+        let changed = *slot != rate;
+        *slot = rate;
+        changed
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TenorRate {
+    pub days: f64,
+    pub rate: f64,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(tag = "model", rename_all = "snake_case")]
+pub enum CarryConfig {
+    // the strategy's long_funding_rate and short_borrow_rate
+    #[default]
+    Constant,
+    // funding by tenor, short_borrow_rate to borrow
+    TermStructure { tenors: Vec<TenorRate> },
+    // the pair's perpetual funding and margin interest, the configured rates until they arrive
+    Funding { coin: String, cash: String },
+}
+
+impl CarryConfig {
+    pub fn check(&self) -> Result<(), String> {
+        if let CarryConfig::TermStructure { tenors } = self {
+            if tenors.is_empty() {
+                return Err("carry.tenors must list at least one rate".to_string());
+            }
+            for t in tenors.iter() {
+                if !t.days.is_finite() || t.days < 0.0 || !t.rate.is_finite() || t.rate.abs() >= 1.0 {
+                    return Err(format!("carry.tenors: bad tenor {} days at {}", t.days, t.rate));
+                }
+            }
+            let mut days: Vec<f64> = tenors.iter().map(|t| t.days).collect();
+            days.sort_by(|a, b| a.total_cmp(b));
+            if days.windows(2).any(|w| w[0] == w[1]) {
+                return Err("carry.tenors lists a tenor more than once".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    pub fn build(&self, symbols: &SymbolMapping, funding: f64, borrow: f64) -> Box<dyn CarryModel> {
+        let constant = ConstantCarry { funding, borrow };
+        match self {
+            CarryConfig::Constant => Box::new(constant),
+            CarryConfig::TermStructure { tenors } => Box::new(TermStructureCarry::new(tenors, borrow)),
+            CarryConfig::Funding { coin, cash } => Box::new(FundingCarry::new(&symbols.binance, coin, cash, constant)),
+        }
+    }
+
+    // the markPrice stream the model needs funding from, if any
+    pub fn funding_stream(&self, symbols: &SymbolMapping) -> Option<String> {
+        match self {
+            CarryConfig::Funding { .. } => Some(symbols.binance_stream("markPrice")),
+            _ => None,
+        }
+    }
+
+    // the assets whose margin interest the model needs polled
+    pub fn margin_assets(&self) -> Vec<String> {
+        match self {
+            CarryConfig::Funding { coin, cash } => vec![coin.to_uppercase(), cash.to_uppercase()],
+            _ => vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::symbols::SymbolMapping;
+
+    use super::{CarryConfig, CarryModel, CarrySide, FundingKind, FundingUpdate, TenorRate, TermStructureCarry};
+
+    #[test]
+    fn term_structure_interpolates_by_tenor() {
+        let carry = TermStructureCarry::new(&[
+            TenorRate { days: 365.0, rate: 0.05 },
+            TenorRate { days: 36.5, rate: 0.03 },
+        ], 0.01);
+        assert_eq!(carry.rate(CarrySide::Long, 0.01), 0.03);
+        assert!((carry.rate(CarrySide::Long, 0.55) - 0.04).abs() < 1e-12);
+        assert_eq!(carry.rate(CarrySide::Long, 2.0), 0.05);
+        // shorts pay the coin's borrow on top, at every tenor
+        assert!((carry.rate(CarrySide::Short, 0.55) - 0.03).abs() < 1e-12);
+    }
+
+    #[test]
+    fn funding_prices_each_side() {
+        let cfg = CarryConfig::Funding { coin: "BTC".to_string(), cash: "USDT".to_string() };
+        let mut carry = cfg.build(&SymbolMapping::new("CBTC", "BTCUSDT"), 0.03, 0.01);
+        assert_eq!(carry.rate(CarrySide::Long, 0.5), 0.03);
+        assert!((carry.rate(CarrySide::Short, 0.5) - 0.02).abs() < 1e-12);
+
+        let update = |kind, symbol: &str, rate| FundingUpdate { kind, symbol: symbol.to_string(), rate };
+        // 0.01% every 8h
+        assert!(carry.on_funding(&update(FundingKind::Perpetual, "BTCUSDT", 0.0001)));
+        assert!((carry.rate(CarrySide::Long, 0.5) - 0.1095).abs() < 1e-12);
+        assert!((carry.rate(CarrySide::Short, 0.5) - 0.0995).abs() < 1e-12);
+        assert!(!carry.on_funding(&update(FundingKind::Perpetual, "BTCUSDT", 0.0001)));
+        assert!(!carry.on_funding(&update(FundingKind::Perpetual, "ETHUSDT", 0.0005)));
+
+        // margin interest, once known, takes over from funding for the cash, and borrowing
+        // the coin comes off the short side only
+        carry.on_funding(&update(FundingKind::MarginInterest, "USDT", 0.0002));
+        carry.on_funding(&update(FundingKind::MarginInterest, "BTC", 0.00005));
+        assert!((carry.rate(CarrySide::Long, 0.5) - 0.073).abs() < 1e-12);
+        assert!((carry.rate(CarrySide::Short, 0.5) - (0.073 - 0.01825)).abs() < 1e-12);
+    }
+}
@@ -0,0 +1,413 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::feeds::FeedConfig;
+use crate::fillsim::BacktestConfig;
+use crate::recorder::RecordingConfig;
+use crate::legging::LeggingConfig;
+use crate::latency::LatencyConfig;
+use crate::metrics::MetricsConfig;
+use crate::logging::LoggingConfig;
+use crate::risk::RiskConfig;
+use crate::strat::ComboStratConfig;
+
+pub const DEFAULT_CONFIG_PATH: &str = "config.toml";
+
+// secrets are never required to live in the config file; these take precedence
+pub const ENV_LEDGERX_API_KEY: &str = "LEDGERX_API_KEY";
+pub const ENV_BINANCE_API_KEY: &str = "BINANCE_API_KEY";
+pub const ENV_BINANCE_API_SECRET: &str = "BINANCE_API_SECRET";
+
+#[derive(Debug, Deserialize)]
+pub struct Config {
+    // one entry per underlying, all run in the same process
+    pub strat: Vec<ComboStratConfig>,
+    pub ledgerx: LedgerXConfig,
+    pub binance: BinanceConfig,
+    pub execution: ExecutionConfig,
+    pub risk: RiskConfig,
+    #[serde(default)]
+    pub feeds: FeedConfig,
+    #[serde(default)]
+    pub recording: RecordingConfig,
+    #[serde(default)]
+    pub backtest: BacktestConfig,
+    #[serde(default)]
+    pub latency: LatencyConfig,
+    #[serde(default)]
+    pub metrics: MetricsConfig,
+    #[serde(default)]
+    pub logging: LoggingConfig,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LedgerXConfig {
+    pub base_url: String,
+    pub wss_url: String,
+    #[serde(default)]
+    pub api_key: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BinanceConfig {
+    #[serde(default)]
+    pub api_key: String,
+    #[serde(default)]
+    pub api_secret: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExecutionConfig {
+    pub legging_timeout_ms: u64,
+    pub opts_unwind_slippage: f64,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(String, std::io::Error),
+    Toml(toml::de::Error),
+    Json(serde_json::Error),
+    UnknownFormat(String),
+    Invalid(Vec<String>),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, err) => write!(f, "could not read config file {}: {}", path, err),
+            ConfigError::Toml(err) => write!(f, "malformed TOML config: {}", err),
+            ConfigError::Json(err) => write!(f, "malformed JSON config: {}", err),
+            ConfigError::UnknownFormat(path) => write!(f, "config file {} must end in .toml or .json", path),
+            ConfigError::Invalid(problems) => write!(f, "invalid config:\n  {}", problems.join("\n  ")),
+        }
+    }
+}
+
+impl Config {
+    // read, apply environment overrides, and validate
+    pub fn load(path: &str) -> Result<Self, ConfigError> {
+        let cfg = Self::read(path)?;
+        cfg.validate()?;
+        Ok(cfg)
+    }
+
+    // for backtests, which never connect and so don't need credentials
+    pub fn load_offline(path: &str) -> Result<Self, ConfigError> {
+        let cfg = Self::read(path)?;
+        cfg.validate_offline()?;
+        Ok(cfg)
+    }
+
+    fn read(path: &str) -> Result<Self, ConfigError> {
+        let raw = fs::read_to_string(path).map_err(|x| ConfigError::Io(path.to_owned(), x))?;
+
+        let mut cfg = match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => Self::from_toml(&raw)?,
+            Some("json") => Self::from_json(&raw)?,
+            _ => return Err(ConfigError::UnknownFormat(path.to_owned())),
+        };
+        cfg.apply_env_overrides(|key| std::env::var(key).ok());
+        Ok(cfg)
+    }
+
+    pub fn from_toml(raw: &str) -> Result<Self, ConfigError> {
+        toml::from_str(raw).map_err(ConfigError::Toml)
+    }
+
+    pub fn from_json(raw: &str) -> Result<Self, ConfigError> {
+        serde_json::from_str(raw).map_err(ConfigError::Json)
+    }
+
+    pub fn apply_env_overrides<F: Fn(&str) -> Option<String>>(&mut self, get_var: F) {
+        if let Some(key) = get_var(ENV_LEDGERX_API_KEY) {
+            self.ledgerx.api_key = key;
+        }
+        if let Some(key) = get_var(ENV_BINANCE_API_KEY) {
+            self.binance.api_key = key;
+        }
+        if let Some(secret) = get_var(ENV_BINANCE_API_SECRET) {
+            self.binance.api_secret = secret;
+        }
+    }
+
+    // collects every problem rather than stopping at the first one
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut problems = self.problems();
+        if self.ledgerx.api_key.is_empty() {
+            problems.push(format!("ledgerx.api_key is not set (config file or ${})", ENV_LEDGERX_API_KEY));
+        }
+        if self.binance.api_key.is_empty() {
+            problems.push(format!("binance.api_key is not set (config file or ${})", ENV_BINANCE_API_KEY));
+        }
+        if self.binance.api_secret.is_empty() {
+            problems.push(format!("binance.api_secret is not set (config file or ${})", ENV_BINANCE_API_SECRET));
+        }
+        Self::invalid_if_any(problems)
+    }
+
+    pub fn validate_offline(&self) -> Result<(), ConfigError> {
+        Self::invalid_if_any(self.problems())
+    }
+
+    fn invalid_if_any(problems: Vec<String>) -> Result<(), ConfigError> {
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError::Invalid(problems))
+        }
+    }
+
+    // everything but credentials
+    fn problems(&self) -> Vec<String> {
+        let mut problems = vec![];
+
+        if self.strat.is_empty() {
+            problems.push("at least one [[strat]] section is required".to_string());
+        }
+        let mut seen = HashSet::new();
+        for strat in self.strat.iter() {
+            let name = &strat.symbols.ledgerx;
+            if strat.symbols.ledgerx.is_empty() || strat.symbols.binance.is_empty() {
+                problems.push("strat.symbols must name both a LedgerX underlying and a Binance pair".to_string());
+            }
+            if !seen.insert(name) {
+                problems.push(format!("strat {}: underlying configured more than once", name));
+            }
+            if !strat.ann_borrow_rate.is_finite() || strat.ann_borrow_rate.abs() >= 1.0 {
+                problems.push(format!("strat {}: ann_borrow_rate must be a fraction in (-1, 1), got {}", name, strat.ann_borrow_rate));
+            }
+            if let Some(rate) = strat.long_funding_rate {
+                if !rate.is_finite() || rate.abs() >= 1.0 {
+                    problems.push(format!("strat {}: long_funding_rate must be a fraction in (-1, 1), got {}", name, rate));
+                }
+            }
+            if !strat.short_borrow_rate.is_finite() || !(0.0..1.0).contains(&strat.short_borrow_rate) {
+                problems.push(format!("strat {}: short_borrow_rate must be a fraction in [0, 1), got {}", name, strat.short_borrow_rate));
+            }
+            if strat.opts_tc.is_nan() || strat.opts_tc < 0.0 {
+                problems.push(format!("strat {}: opts_tc must be non-negative, got {}", name, strat.opts_tc));
+            }
+            if strat.spot_tc.is_nan() || strat.spot_tc < 0.0 {
+                problems.push(format!("strat {}: spot_tc must be non-negative, got {}", name, strat.spot_tc));
+            }
+            if strat.max_level_exposure.is_nan() || strat.max_level_exposure <= 0.0 {
+                problems.push(format!("strat {}: max_level_exposure must be positive, got {}", name, strat.max_level_exposure));
+            }
+            if strat.max_opts_age_ms == 0 || strat.max_spot_age_ms == 0 {
+                problems.push(format!("strat {}: max_opts_age_ms and max_spot_age_ms must be positive", name));
+            }
+            if let Err(e) = strat.parity.check() {
+                problems.push(format!("strat {}: {}", name, e));
+            }
+            if let Err(e) = strat.carry.check() {
+                problems.push(format!("strat {}: {}", name, e));
+            }
+        }
+
+        if let Err(e) = self.logging.check() {
+            problems.push(format!("logging.filter {:?} is not a valid filter: {}", self.logging.filter, e));
+        }
+
+        if self.ledgerx.base_url.is_empty() {
+            problems.push("ledgerx.base_url must not be empty".to_string());
+        }
+        if !self.ledgerx.wss_url.starts_with("wss://") && !self.ledgerx.wss_url.starts_with("ws://") {
+            problems.push(format!("ledgerx.wss_url must be a websocket url, got {:?}", self.ledgerx.wss_url));
+        }
+
+        if self.execution.legging_timeout_ms == 0 {
+            problems.push("execution.legging_timeout_ms must be positive".to_string());
+        }
+        if !(0.0..1.0).contains(&self.execution.opts_unwind_slippage) {
+            problems.push(format!("execution.opts_unwind_slippage must be in [0, 1), got {}", self.execution.opts_unwind_slippage));
+        }
+
+        if self.risk.max_notional_per_trade.is_nan() || self.risk.max_notional_per_trade <= 0.0 {
+            problems.push(format!("risk.max_notional_per_trade must be positive, got {}", self.risk.max_notional_per_trade));
+        }
+        if self.risk.max_net_delta.is_nan() || self.risk.max_net_delta < 0.0 {
+            problems.push(format!("risk.max_net_delta must be non-negative, got {}", self.risk.max_net_delta));
+        }
+        if self.risk.max_open_combos_per_expiry == 0 {
+            problems.push("risk.max_open_combos_per_expiry must be positive".to_string());
+        }
+        // boxes and rolls are four LedgerX legs, combos three
+        let combo_legs = if self.strat.iter().any(|s| s.box_spreads || s.jelly_rolls) { 4 } else { 3 };
+        if self.risk.max_orders_per_sec < combo_legs {
+            problems.push(format!("risk.max_orders_per_sec must allow at least one {}-leg combo, got {}", combo_legs, self.risk.max_orders_per_sec));
+        }
+        for (asset, limit) in self.risk.max_borrow.iter() {
+            if limit.is_nan() || *limit < 0.0 {
+                problems.push(format!("risk.max_borrow.{} must be non-negative, got {}", asset, limit));
+            }
+        }
+
+        if self.feeds.ledgerx_stale_after_ms == 0 || self.feeds.binance_stale_after_ms == 0 {
+            problems.push("feeds.*_stale_after_ms must be positive".to_string());
+        }
+        if self.feeds.reconnect_min_ms == 0 || self.feeds.reconnect_min_ms > self.feeds.reconnect_max_ms {
+            problems.push(format!(
+                "feeds.reconnect_min_ms must be positive and at most reconnect_max_ms, got {}..{}",
+                self.feeds.reconnect_min_ms, self.feeds.reconnect_max_ms,
+            ));
+        }
+        if self.feeds.max_consecutive_errors == 0 {
+            problems.push("feeds.max_consecutive_errors must be positive".to_string());
+        }
+
+        if self.backtest.spot_slippage_bps.is_nan() || self.backtest.spot_slippage_bps < 0.0 {
+            problems.push(format!("backtest.spot_slippage_bps must be non-negative, got {}", self.backtest.spot_slippage_bps));
+        }
+        if self.backtest.ledgerx_order_ttl_ms == 0 {
+            problems.push("backtest.ledgerx_order_ttl_ms must be positive".to_string());
+        }
+
+        problems
+    }
+
+    pub fn legging_config(&self) -> LeggingConfig {
+        LeggingConfig {
+            timeout: Duration::from_millis(self.execution.legging_timeout_ms),
+            opts_unwind_slippage: self.execution.opts_unwind_slippage,
+        }
+    }
+}
+
+// `--config <path>` or `--config=<path>`, falling back to DEFAULT_CONFIG_PATH
+pub fn config_path_from_args<I: Iterator<Item = String>>(mut args: I) -> Result<String, String> {
+    let mut path = None;
+    while let Some(arg) = args.next() {
+        if arg == "--config" || arg == "-c" {
+            path = Some(args.next().ok_or_else(|| format!("{} expects a path", arg))?);
+        } else if let Some(p) = arg.strip_prefix("--config=") {
+            path = Some(p.to_owned());
+        } else {
+            return Err(format!("unrecognized argument {:?}", arg));
+        }
+    }
+    Ok(path.unwrap_or_else(|| DEFAULT_CONFIG_PATH.to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Config, ConfigError, config_path_from_args, DEFAULT_CONFIG_PATH};
+
+    const TOML_CFG: &str = r#"
+        [[strat]]
+        ann_borrow_rate = 0.03
+        opts_tc = 0.001
+        spot_tc = 0.001
+        symbols = { ledgerx = "CBTC" }
+
+        [[strat]]
+        ann_borrow_rate = 0.03
+        opts_tc = 0.001
+        spot_tc = 0.001
+        symbols = { ledgerx = "CETH" }
+
+        [ledgerx]
+        base_url = "https://api.ledgerx.com"
+        wss_url = "wss://api.ledgerx.com/ws"
+        api_key = "from-file"
+
+        [binance]
+
+        [execution]
+        legging_timeout_ms = 2000
+        opts_unwind_slippage = 0.02
+
+        [risk]
+        max_notional_per_trade = 50000.0
+        max_open_combos_per_expiry = 5
+        max_net_delta = 0.5
+        max_orders_per_sec = 10
+    "#;
+
+    #[test]
+    fn parse_toml_and_override() {
+        let mut cfg = Config::from_toml(TOML_CFG).unwrap();
+        assert_eq!(cfg.strat.len(), 2);
+        assert_eq!(cfg.strat[0].symbols.binance, "BTCUSDT");
+        assert_eq!(cfg.strat[1].symbols.binance, "ETHUSDT");
+        assert_eq!(cfg.ledgerx.api_key, "from-file");
+
+        // secrets missing from the file
+        assert!(matches!(cfg.validate(), Err(ConfigError::Invalid(p)) if p.len() == 2));
+
+        cfg.apply_env_overrides(|key| Some(format!("env-{}", key)));
+        assert_eq!(cfg.ledgerx.api_key, "env-LEDGERX_API_KEY");
+        assert_eq!(cfg.binance.api_secret, "env-BINANCE_API_SECRET");
+        assert!(cfg.validate().is_ok());
+        assert_eq!(cfg.legging_config().timeout.as_millis(), 2000);
+    }
+
+    #[test]
+    fn parse_json() {
+        let raw = r#"{
+            "strat": [{"symbols": {"ledgerx": "CETH"}, "ann_borrow_rate": 0.05, "opts_tc": 0.0, "spot_tc": 0.0}],
+            "ledgerx": {"base_url": "https://api.ledgerx.com", "wss_url": "wss://api.ledgerx.com/ws", "api_key": "k"},
+            "binance": {"api_key": "k", "api_secret": "s"},
+            "execution": {"legging_timeout_ms": 500, "opts_unwind_slippage": 0.0},
+            "risk": {"max_notional_per_trade": 1e4, "max_open_combos_per_expiry": 1, "max_net_delta": 0.1, "max_orders_per_sec": 3, "kill_file": "/tmp/stop"}
+        }"#;
+        let cfg = Config::from_json(raw).unwrap();
+        assert_eq!(cfg.strat[0].symbols.binance, "ETHUSDT");
+        assert_eq!(cfg.strat[0].max_level_exposure, 1.0);
+        assert!(cfg.validate().is_ok());
+    }
+
+    #[test]
+    fn example_config_parses() {
+        let cfg = Config::from_toml(include_str!("../config.example.toml")).unwrap();
+        assert_eq!(cfg.strat.len(), 2);
+
+        // keys are left blank in the example, which only a backtest can live with
+        assert!(cfg.validate_offline().is_ok());
+        assert!(matches!(cfg.validate(), Err(ConfigError::Invalid(problems)) if problems.len() == 3));
+    }
+
+    #[test]
+    fn invalid_values() {
+        let mut cfg = Config::from_toml(TOML_CFG).unwrap();
+        cfg.apply_env_overrides(|_| Some("x".to_string()));
+        cfg.strat[0].opts_tc = -1.0;
+        cfg.strat[1].ann_borrow_rate = f64::NAN;
+        cfg.strat[1].symbols = cfg.strat[0].symbols.clone();
+        cfg.ledgerx.wss_url = "https://api.ledgerx.com/ws".to_string();
+
+        match cfg.validate() {
+            Err(ConfigError::Invalid(problems)) => assert_eq!(problems.len(), 4),
+            other => panic!("expected validation failure, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn spreads_need_four_orders_per_sec() {
+        let mut cfg = Config::from_toml(TOML_CFG).unwrap();
+        cfg.apply_env_overrides(|_| Some("x".to_string()));
+        cfg.risk.max_orders_per_sec = 3;
+        assert!(cfg.validate().is_ok());
+
+        cfg.strat[1].jelly_rolls = true;
+        match cfg.validate() {
+            Err(ConfigError::Invalid(problems)) => assert_eq!(problems, vec!["risk.max_orders_per_sec must allow at least one 4-leg combo, got 3"]),
+            other => panic!("expected validation failure, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn cli_args() {
+        let args = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>().into_iter();
+
+        assert_eq!(config_path_from_args(args(&[])).unwrap(), DEFAULT_CONFIG_PATH);
+        assert_eq!(config_path_from_args(args(&["--config", "paper.toml"])).unwrap(), "paper.toml");
+        assert_eq!(config_path_from_args(args(&["--config=eth.json"])).unwrap(), "eth.json");
+        assert!(config_path_from_args(args(&["--config"])).is_err());
+        assert!(config_path_from_args(args(&["--verbose"])).is_err());
+    }
+}
@@ -0,0 +1,474 @@
+use std::collections::HashMap;
+
+use binance::model::DepthOrderBookEvent;
+use binance::websockets::WebsocketEvent;
+use chrono::{offset::Utc, DateTime};
+use ftx_us_derivs::order::Order;
+use ftx_us_derivs::table::{ContractSpecTable, OptionContractSpec};
+use ftx_us_derivs::ws::WebSocketMsg;
+use tracing::warn;
+
+use crate::UniversalMsgWrapper;
+use crate::book::{BookError, BookUpdate};
+use crate::feeds::FeedVenue;
+use crate::legging::ComboExecution;
+use crate::positions::PositionBook;
+use crate::strat::{ArbCounts, ComboStrat, ComboStratConfig, Trade};
+
+// hosts one ComboStrat per underlying and routes feed messages to the right one(s)
+pub struct StratDispatcher {
+    pub strats: Vec<ComboStrat>,
+    by_contract: HashMap<u64, usize>,
+    // several underlyings could in principle hedge against the same spot pair
+    by_binance: HashMap<String, Vec<usize>>,
+    // a diff-depth gap; the Binance feed has to reconnect to get a fresh snapshot
+    depth_resync: bool,
+}
+
+impl StratDispatcher {
+    pub fn startup(configs: Vec<ComboStratConfig>) -> Self {
+        let spec_table = ContractSpecTable::build().expect("Failed to Build Contract Table!");
+        Self::from_spec_table(configs, spec_table)
+    }
+
+    pub fn from_spec_table(configs: Vec<ComboStratConfig>, spec_table: ContractSpecTable) -> Self {
+        let strats = configs.into_iter()
+            .map(|cfg| ComboStrat::from_spec_table(cfg, spec_table.clone()))
+            .collect();
+        Self::new(strats)
+    }
+
+    pub fn new(strats: Vec<ComboStrat>) -> Self {
+        let mut by_contract = HashMap::new();
+        let mut by_binance: HashMap<String, Vec<usize>> = HashMap::new();
+
+        for (i, strat) in strats.iter().enumerate() {
+            for id in strat.contract_ids() {
+                let prev = by_contract.insert(id, i);
+                debug_assert!(prev.is_none(), "contract {} claimed by two strategies", id);
+            }
+            by_binance.entry(strat.config.symbols.binance.to_owned()).or_default().push(i);
+        }
+
+        Self { strats, by_contract, by_binance, depth_resync: false }
+    }
+
+    pub fn contract_ids(&self) -> impl Iterator<Item = u64> + '_ {
+        self.by_contract.keys().cloned()
+    }
+
+    pub fn binance_symbols(&self) -> impl Iterator<Item = &str> {
+        self.by_binance.keys().map(|s| s.as_str())
+    }
+
+    pub fn contract_specs(&self) -> Vec<OptionContractSpec> {
+        self.strats.iter().flat_map(|s| s.contract_specs()).collect()
+    }
+
+    pub fn process(&mut self, msg: UniversalMsgWrapper, recv_time: DateTime<Utc>) -> Option<Trade> {
+        match msg {
+            UniversalMsgWrapper::Binance(spot) => self.process_spot_update(spot, recv_time),
+            UniversalMsgWrapper::LedgerX(opts) => self.process_opts_update(opts, recv_time),
+            UniversalMsgWrapper::BinanceDepth(snapshot) => {
+                for &i in self.by_binance.get(&snapshot.symbol).into_iter().flatten() {
+                    self.strats[i].process_depth_snapshot(&snapshot);
+                }
+                None
+            },
+            UniversalMsgWrapper::LedgerXBook(update) => {
+                self.process_book_update(&update);
+                None
+            },
+            UniversalMsgWrapper::FeedReset(venue) => {
+                self.reset_feed(venue);
+                None
+            },
+            // each strategy's carry model picks out the rates it prices with
+            UniversalMsgWrapper::Funding(update) => {
+                for strat in self.strats.iter_mut() {
+                    strat.process_funding(&update);
+                }
+                None
+            },
+        }
+    }
+
+    pub fn reset_feed(&mut self, venue: FeedVenue) {
+        for strat in self.strats.iter_mut() {
+            match venue {
+                FeedVenue::LedgerX => strat.reset_opts(),
+                FeedVenue::Binance => strat.reset_spot(),
+            }
+        }
+    }
+
+    // a gap leaves that contract's book at top-of-book until the next snapshot
+    pub fn process_book_update(&mut self, update: &BookUpdate) {
+        if let Some(&i) = self.by_contract.get(&update.contract_id()) {
+            if let Err(e) = self.strats[i].process_book_update(update) {
+                warn!(contract_id = update.contract_id(), error = ?e, "LedgerX book out of sync");
+            }
+        }
+    }
+
+    fn process_depth_update(&mut self, event: &DepthOrderBookEvent) {
+        for &i in self.by_binance.get(&event.symbol).into_iter().flatten() {
+            match self.strats[i].process_depth_update(event) {
+                Err(e @ BookError::Gap { .. }) => {
+                    warn!(symbol = %event.symbol, error = ?e, "Binance depth out of sync");
+                    self.depth_resync = true;
+                },
+                // diffs between a gap and the next snapshot
+                Err(BookError::NotSynced) | Ok(()) => {},
+            }
+        }
+    }
+
+    // true once per gap
+    pub fn take_depth_resync(&mut self) -> bool {
+        std::mem::take(&mut self.depth_resync)
+    }
+
+    pub fn process_spot_update(&mut self, msg: WebsocketEvent, recv_time: DateTime<Utc>) -> Option<Trade> {
+        let spot_bt = match msg {
+            WebsocketEvent::BookTicker(bt) => bt,
+            WebsocketEvent::DepthOrderBook(depth) => {
+                self.process_depth_update(&depth);
+                return None;
+            },
+            _ => return None,
+        };
+        let targets = self.by_binance.get(&spot_bt.symbol)?;
+
+        let mut out = Trade::empty();
+        for &i in targets.iter() {
+            let msg = WebsocketEvent::BookTicker(spot_bt.clone());
+            if let Some(t) = self.strats[i].process_spot_update(msg, recv_time) {
+                out.merge(t);
+            }
+        }
+
+        if out.is_empty() {
+            return None;
+        }
+        out.net_out();
+        Some(out)
+    }
+
+    // book the outcome of a finished execution into the owning strategies' positions
+    pub fn settle(&mut self, exec: &ComboExecution) {
+        for leg in exec.spot.iter() {
+            let unfilled = (leg.order.qty - leg.filled_qty).max(0.0);
+            self.route_binance(&leg.order.symbol, leg.order.is_buy, leg.filled_qty, unfilled);
+        }
+        for leg in exec.opts.iter() {
+            if let Some(&i) = self.by_contract.get(&leg.order.contract_id) {
+                let positions = &mut self.strats[i].positions;
+                positions.fill_ledgerx(leg.order.contract_id, leg.order.is_ask, leg.filled_size);
+                positions.cancel_ledgerx(leg.order.contract_id, leg.order.is_ask, leg.order.size - leg.filled_size);
+            }
+        }
+
+        // the unwind was never working in the books; only what of it actually filled counts,
+        // and it goes to the strat that owns the combo
+        let owner = exec.opts.iter().chain(exec.unwind_opts.iter())
+            .find_map(|leg| self.by_contract.get(&leg.order.contract_id).copied());
+        if let Some(i) = owner {
+            let positions = &mut self.strats[i].positions;
+            for leg in exec.unwind_spot.iter() {
+                positions.book_binance(&leg.order.symbol, leg.order.is_buy, leg.filled_qty);
+            }
+        }
+        for leg in exec.unwind_opts.iter() {
+            if let Some(&i) = self.by_contract.get(&leg.order.contract_id) {
+                self.strats[i].positions.book_ledgerx(leg.order.contract_id, leg.order.is_ask, leg.filled_size);
+            }
+        }
+    }
+
+    // a fill on an order that was pulled, which nothing else will book
+    pub fn book_late_fill(&mut self, fill: &Order) {
+        if let Some(&i) = self.by_contract.get(&fill.contract_id) {
+            self.strats[i].positions.book_ledgerx(fill.contract_id, fill.is_ask, fill.size);
+        }
+    }
+
+    // backtests without a fill model: every leg fills in full at its quoted price
+    pub fn fill(&mut self, t: &Trade) {
+        for order in t.binance.iter() {
+            self.route_binance(&order.symbol, order.is_buy, order.qty, 0.0);
+        }
+        for order in t.ledgerx.iter() {
+            if let Some(&i) = self.by_contract.get(&order.contract_id) {
+                self.strats[i].positions.fill_ledgerx(order.contract_id, order.is_ask, order.size);
+            }
+        }
+    }
+
+    // a generated trade that will never be sent; drop it from the working positions
+    pub fn release(&mut self, t: &Trade) {
+        for order in t.binance.iter() {
+            self.route_binance(&order.symbol, order.is_buy, 0.0, order.qty);
+        }
+        for order in t.ledgerx.iter() {
+            if let Some(&i) = self.by_contract.get(&order.contract_id) {
+                self.strats[i].positions.cancel_ledgerx(order.contract_id, order.is_ask, order.size);
+            }
+        }
+    }
+
+    pub fn arb_counts(&self) -> ArbCounts {
+        let mut total = ArbCounts::default();
+        for strat in self.strats.iter() {
+            total.merge(strat.arbs);
+        }
+        total
+    }
+
+    pub fn books(&self) -> Vec<&PositionBook> {
+        self.strats.iter().map(|s| &s.positions).collect()
+    }
+
+    // spot orders may have been netted across strategies sharing a pair, so hand
+    // the fill to whichever of them has that much working, first come first served
+    fn route_binance(&mut self, symbol: &str, is_buy: bool, filled: f64, unfilled: f64) {
+        let targets = match self.by_binance.get(symbol) {
+            Some(targets) => targets,
+            None => return,
+        };
+        let sign: f64 = if is_buy { 1.0 } else { -1.0 };
+
+        let mut filled_left = filled;
+        let mut unfilled_left = unfilled;
+        for (n, &i) in targets.iter().enumerate() {
+            let positions = &mut self.strats[i].positions;
+            let is_last = n + 1 == targets.len();
+            let working = (sign * positions.working_binance(symbol)).max(0.0);
+
+            let fill = if is_last { filled_left } else { filled_left.min(working) };
+            positions.fill_binance(symbol, is_buy, fill);
+            filled_left -= fill;
+
+            let cancel = if is_last { unfilled_left } else { unfilled_left.min(working - fill) };
+            positions.cancel_binance(symbol, is_buy, cancel);
+            unfilled_left -= cancel;
+        }
+    }
+
+    pub fn process_opts_update(&mut self, msg: WebSocketMsg, recv_time: DateTime<Utc>) -> Option<Trade> {
+        let i = match &msg {
+            WebSocketMsg::BookTop(bt) => *self.by_contract.get(&bt.contract_id)?,
+            _ => return None,
+        };
+        self.strats[i].process_opts_update(msg, recv_time)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::rc::Rc;
+    use std::time::Instant;
+
+    use binance::model::{BookTickerEvent, DepthOrderBookEvent};
+    use binance::websockets::WebsocketEvent;
+    use chrono::{Utc, DateTime};
+    use ftx_us_derivs::order::Order;
+    use ftx_us_derivs::table::{ContractSpecTable, OptionContractSpec, ContractSpec};
+    use ftx_us_derivs::ws::{WebSocketMsg, BookTop};
+
+    use crate::UniversalMsgWrapper;
+    use crate::book::SpotDepthSnapshot;
+    use crate::execution::{route_trade, MockVenue};
+    use crate::feeds::FeedVenue;
+    use crate::legging::ComboExecution;
+    use crate::positions::PositionView;
+    use crate::strat::{BinanceMarketOrder, ComboStratConfig};
+    use crate::strat::tests::mock_config;
+    use crate::symbols::SymbolMapping;
+
+    use super::StratDispatcher;
+
+    fn mock_option(id: u64, underlying: &str, is_call: bool) -> (u64, Rc<ContractSpec>) {
+        let spec = OptionContractSpec {
+            id,
+            label: format!("{}-30JUN2023-10000-{}", underlying, if is_call { "Call" } else { "Put" }),
+            underlying: underlying.to_string(),
+            strike_price: 10000,
+            is_call,
+            tte: 0.6600180575256285,
+            open_interest: 0,
+            multiplier: 100.0,
+            min_increment: 1.0,
+            active: true,
+            date_live: DateTime::<Utc>::MIN_UTC,
+            date_expires: DateTime::<Utc>::MIN_UTC,
+            collateral_asset: "USD".to_string(),
+            is_ecp_only: false,
+        };
+        (id, Rc::new(ContractSpec::Option(spec)))
+    }
+
+    fn mock_dispatcher() -> StratDispatcher {
+        let table = ContractSpecTable {
+            id_table: HashMap::from_iter([
+                mock_option(1, "CBTC", true),
+                mock_option(2, "CBTC", false),
+                mock_option(3, "CETH", true),
+                mock_option(4, "CETH", false),
+            ]),
+            label_table: HashMap::new(),
+        };
+        let cfg = |lx: &str| ComboStratConfig { symbols: SymbolMapping::from_ledgerx(lx).unwrap(), ..mock_config() };
+        StratDispatcher::from_spec_table(vec![cfg("CBTC"), cfg("CETH")], table)
+    }
+
+    // every quote in these tests arrives at the same instant, so none are stale
+    fn t0() -> DateTime<Utc> {
+        DateTime::<Utc>::MIN_UTC
+    }
+
+    fn spot(symbol: &str, bid: &str, ask: &str) -> UniversalMsgWrapper {
+        UniversalMsgWrapper::Binance(WebsocketEvent::BookTicker(BookTickerEvent {
+            update_id: 0,
+            symbol: symbol.to_string(),
+            best_bid: bid.to_string(),
+            best_bid_qty: "1.0".to_string(),
+            best_ask: ask.to_string(),
+            best_ask_qty: "1.0".to_string(),
+        }))
+    }
+
+    fn book_top(contract_id: u64, bid: f64, ask: f64) -> UniversalMsgWrapper {
+        UniversalMsgWrapper::LedgerX(WebSocketMsg::BookTop(
+            BookTop { bid, bid_size: 100, ask, ask_size: 100, contract_id, contract_type: 0, clock: 0 }
+        ))
+    }
+
+    #[test]
+    fn routing_tables() {
+        let dispatch = mock_dispatcher();
+        let mut ids: Vec<u64> = dispatch.contract_ids().collect();
+        ids.sort();
+        assert_eq!(ids, vec![1, 2, 3, 4]);
+
+        let mut symbols: Vec<&str> = dispatch.binance_symbols().collect();
+        symbols.sort();
+        assert_eq!(symbols, vec!["BTCUSDT", "ETHUSDT"]);
+    }
+
+    #[test]
+    fn routes_by_underlying() {
+        let mut dispatch = mock_dispatcher();
+
+        // seed both chains and both spots with quotes that do not arb
+        let quiet = vec![
+            spot("BTCUSDT", "20449.0", "20450.0"),
+            spot("ETHUSDT", "20449.0", "20450.0"),
+            book_top(1, 11070.0, 11180.0),
+            book_top(2, 500.0, 580.0),
+            book_top(3, 11070.0, 11180.0),
+            book_top(4, 500.0, 580.0),
+        ];
+        for msg in quiet {
+            assert!(dispatch.process(msg, t0()).is_none());
+        }
+
+        // an unknown contract or pair is dropped rather than panicking
+        assert!(dispatch.process(book_top(99, 1.0, 2.0), t0()).is_none());
+        assert!(dispatch.process(spot("SOLUSDT", "1.0", "2.0"), t0()).is_none());
+
+        // only the ETH strategy should see this spot move
+        let t = dispatch.process(spot("ETHUSDT", "20299.0", "20300.0"), t0()).expect("expected an ETH conversion");
+        assert_eq!(t.binance.len(), 1);
+        assert_eq!(t.binance[0].symbol, "ETHUSDT");
+        assert!((t.binance[0].qty - 0.5).abs() < 1e-9);
+        let legs: Vec<(u64, bool, u64)> = t.ledgerx.iter().map(|o| (o.contract_id, o.is_ask, o.size)).collect();
+        assert_eq!(legs, vec![(3, true, 50), (4, false, 50)]);
+    }
+
+    #[test]
+    fn depth_gap_requests_resync() {
+        let mut dispatch = mock_dispatcher();
+        let diff = |symbol: &str, first_update_id, final_update_id| UniversalMsgWrapper::Binance(WebsocketEvent::DepthOrderBook(
+            DepthOrderBookEvent {
+                event_type: "depthUpdate".to_string(),
+                event_time: 0,
+                symbol: symbol.to_string(),
+                first_update_id,
+                final_update_id,
+                bids: vec![],
+                asks: vec![],
+            }
+        ));
+
+        // diffs ahead of any snapshot are dropped quietly
+        dispatch.process(diff("BTCUSDT", 1, 5), t0());
+        assert!(!dispatch.take_depth_resync());
+
+        dispatch.process(UniversalMsgWrapper::BinanceDepth(SpotDepthSnapshot {
+            symbol: "BTCUSDT".to_string(),
+            last_update_id: 10,
+            bids: vec![(20449.0, 1.0)],
+            asks: vec![(20450.0, 1.0)],
+        }), t0());
+        dispatch.process(diff("BTCUSDT", 8, 12), t0());
+        dispatch.process(diff("ETHUSDT", 50, 60), t0());
+        assert!(!dispatch.take_depth_resync());
+
+        dispatch.process(diff("BTCUSDT", 14, 15), t0());
+        assert!(dispatch.take_depth_resync());
+        assert!(!dispatch.take_depth_resync());
+    }
+
+    #[test]
+    fn feed_reset_invalidates_quotes() {
+        let mut dispatch = mock_dispatcher();
+        for msg in [spot("BTCUSDT", "20449.0", "20450.0"), book_top(1, 11070.0, 11180.0), book_top(2, 500.0, 580.0)] {
+            dispatch.process(msg, t0());
+        }
+
+        // options quotes are gone, so the arb-able tick finds nothing
+        assert!(dispatch.process(UniversalMsgWrapper::FeedReset(FeedVenue::LedgerX), t0()).is_none());
+        assert!(dispatch.process(spot("BTCUSDT", "20299.0", "20300.0"), t0()).is_none());
+
+        // spot is gone, so a fresh option quote has nothing to compare against
+        assert!(dispatch.process(book_top(1, 11070.0, 11180.0), t0()).is_none());
+        dispatch.process(UniversalMsgWrapper::FeedReset(FeedVenue::Binance), t0());
+        assert!(dispatch.process(book_top(2, 500.0, 580.0), t0()).is_none());
+
+        // and everything works again once both feeds are back
+        assert!(dispatch.process(spot("BTCUSDT", "20299.0", "20300.0"), t0()).is_some());
+    }
+
+    #[test]
+    fn settle_books_fills() {
+        let mut dispatch = mock_dispatcher();
+        for msg in [spot("ETHUSDT", "20449.0", "20450.0"), book_top(3, 11070.0, 11180.0), book_top(4, 500.0, 580.0)] {
+            dispatch.process(msg, t0());
+        }
+        let t = dispatch.process(spot("ETHUSDT", "20299.0", "20300.0"), t0()).unwrap();
+        let eth = &dispatch.strats[1].positions;
+        assert!(eth.net_synthetic(PositionView::Committed) < 0.0);
+        assert_eq!(eth.net_synthetic(PositionView::Filled), 0.0);
+
+        // every leg fills in full
+        let mut bn = MockVenue::<BinanceMarketOrder>::new("binance");
+        let mut lx = MockVenue::<Order>::new("ledgerx");
+        let report = route_trade(&t, &mut bn, &mut lx);
+        let mut exec = ComboExecution::new(t, report, Instant::now());
+        for (order_id, qty) in exec.spot.iter().map(|l| (l.order_id.clone().unwrap(), l.order.qty)).collect::<Vec<_>>() {
+            exec.on_binance_fill(&order_id, qty);
+        }
+        for (order_id, size) in exec.opts.iter().map(|l| (l.order_id.clone().unwrap(), l.order.size)).collect::<Vec<_>>() {
+            exec.on_ledgerx_fill(&order_id, size);
+        }
+        dispatch.settle(&exec);
+
+        let eth = &dispatch.strats[1].positions;
+        assert_eq!(eth.net_delta(PositionView::Filled), 0.0);
+        assert_eq!(eth.net_synthetic(PositionView::Filled), eth.net_synthetic(PositionView::Committed));
+        assert_eq!(eth.working_binance("ETHUSDT"), 0.0);
+        assert_eq!(dispatch.strats[0].positions.net_delta(PositionView::Committed), 0.0);
+    }
+}
@@ -0,0 +1,251 @@
+use std::fmt::Debug;
+use std::marker::PhantomData;
+
+use binance::account::Account;
+use ftx_us_derivs::order::{Order, OrderMngr};
+
+use crate::strat::{BinanceMarketOrder, Trade};
+
+// acknowledgement of a single leg by the venue it was routed to
+#[derive(Debug, Clone)]
+pub struct LegAck {
+    pub venue: &'static str,
+    pub order_id: String,
+    // quantity the venue reported as filled at ack time; LedgerX fills arrive
+    // asynchronously over the websocket so this is zero there
+    pub filled_qty: f64,
+}
+
+#[derive(Debug)]
+pub enum ExecutionError {
+    Binance(binance::errors::Error),
+    LedgerX(String),
+    Mock(String),
+}
+
+pub type LegResult = Result<LegAck, ExecutionError>;
+
+pub trait ExecutionVenue {
+    type Order;
+    fn name(&self) -> &'static str;
+    fn submit(&mut self, order: &Self::Order) -> LegResult;
+    // pull an order that's still resting, by the id it was acked with
+    fn cancel(&mut self, order: &Self::Order, order_id: &str) -> Result<(), ExecutionError>;
+}
+
+pub struct BinanceVenue {
+    account: Account,
+}
+impl BinanceVenue {
+    pub fn new(account: Account) -> Self {
+        Self { account }
+    }
+}
+impl ExecutionVenue for BinanceVenue {
+    type Order = BinanceMarketOrder;
+
+    fn name(&self) -> &'static str {
+        "Binance"
+    }
+
+    fn submit(&mut self, order: &BinanceMarketOrder) -> LegResult {
+        let res = if order.is_buy {
+            self.account.market_buy(order.symbol.as_str(), order.qty)
+        } else {
+            self.account.market_sell(order.symbol.as_str(), order.qty)
+        };
+
+        res.map(|tx| LegAck {
+                venue: self.name(),
+                order_id: tx.order_id.to_string(),
+                filled_qty: tx.executed_qty,
+            })
+            .map_err(ExecutionError::Binance)
+    }
+
+    // market orders fill or expire on arrival, nothing is left resting
+    fn cancel(&mut self, _order: &BinanceMarketOrder, _order_id: &str) -> Result<(), ExecutionError> {
+        Ok(())
+    }
+}
+
+pub struct LedgerXVenue {
+    om: OrderMngr,
+}
+impl LedgerXVenue {
+    pub fn new(om: OrderMngr) -> Self {
+        Self { om }
+    }
+}
+impl ExecutionVenue for LedgerXVenue {
+    type Order = Order;
+
+    fn name(&self) -> &'static str {
+        "LedgerX"
+    }
+
+    fn submit(&mut self, order: &Order) -> LegResult {
+        match self.om.send_order(order) {
+            Ok(resp) => Ok(LegAck {
+                venue: self.name(),
+                order_id: resp.mid,
+                filled_qty: 0.0,
+            }),
+            Err(err) => Err(ExecutionError::LedgerX(format!("{:?}", err))),
+        }
+    }
+
+    fn cancel(&mut self, order: &Order, order_id: &str) -> Result<(), ExecutionError> {
+        self.om.cancel_order(order_id, order.contract_id)
+            .map_err(|err| ExecutionError::LedgerX(format!("{:?}", err)))
+    }
+}
+
+// offline venue: acks everything except the submissions listed in `reject_on`
+pub struct MockVenue<O> {
+    name: &'static str,
+    pub sent: Vec<String>,
+    // ids of the orders cancelled, in order
+    pub cancelled: Vec<String>,
+    pub reject_on: Vec<usize>,
+    _order: PhantomData<O>,
+}
+impl<O> MockVenue<O> {
+    pub fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            sent: vec![],
+            cancelled: vec![],
+            reject_on: vec![],
+            _order: PhantomData,
+        }
+    }
+    pub fn rejecting(name: &'static str, reject_on: Vec<usize>) -> Self {
+        Self { reject_on, ..Self::new(name) }
+    }
+}
+impl<O: Debug> ExecutionVenue for MockVenue<O> {
+    type Order = O;
+
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn submit(&mut self, order: &O) -> LegResult {
+        let n = self.sent.len();
+        self.sent.push(format!("{:?}", order));
+
+        if self.reject_on.contains(&n) {
+            return Err(ExecutionError::Mock(format!("{} rejected order #{}", self.name, n)));
+        }
+        Ok(LegAck {
+            venue: self.name,
+            order_id: format!("{}-{}", self.name, n),
+            filled_qty: 0.0,
+        })
+    }
+
+    fn cancel(&mut self, _order: &O, order_id: &str) -> Result<(), ExecutionError> {
+        self.cancelled.push(order_id.to_owned());
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TradeStatus {
+    Complete,
+    Partial,
+    Rejected,
+}
+
+// per-leg outcome of routing a `Trade`, in the same order as the trade's legs
+#[derive(Debug)]
+pub struct TradeReport {
+    pub binance: Vec<LegResult>,
+    pub ledgerx: Vec<LegResult>,
+}
+impl TradeReport {
+    pub fn legs(&self) -> impl Iterator<Item = &LegResult> {
+        self.binance.iter().chain(self.ledgerx.iter())
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &ExecutionError> {
+        self.legs().filter_map(|leg| leg.as_ref().err())
+    }
+
+    pub fn status(&self) -> TradeStatus {
+        let n_ok = self.legs().filter(|leg| leg.is_ok()).count();
+        if n_ok == self.binance.len() + self.ledgerx.len() {
+            TradeStatus::Complete
+        } else if n_ok == 0 {
+            TradeStatus::Rejected
+        } else {
+            TradeStatus::Partial
+        }
+    }
+}
+
+pub fn route_trade<B, L>(t: &Trade, binance: &mut B, ledgerx: &mut L) -> TradeReport
+where
+    B: ExecutionVenue<Order = BinanceMarketOrder>,
+    L: ExecutionVenue<Order = Order>,
+{
+    // every leg is attempted even if an earlier one fails; unwinding a
+    // partially executed combo is left to the caller
+    TradeReport {
+        binance: t.binance.iter().map(|o| binance.submit(o)).collect(),
+        ledgerx: t.ledgerx.iter().map(|o| ledgerx.submit(o)).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ftx_us_derivs::order::Order;
+
+    use crate::strat::{BinanceMarketOrder, Trade};
+    use super::{route_trade, MockVenue, TradeStatus};
+
+    fn mock_trade() -> Trade {
+        Trade {
+            binance: vec![
+                BinanceMarketOrder { symbol: "BTCUSDT".to_string(), is_buy: false, qty: 0.5, price: 20299.0 },
+            ],
+            ledgerx: vec![
+                Order::new(22248027, false, 11180.0, 50),
+                Order::new(22248028, true, 500.0, 50),
+            ],
+        }
+    }
+
+    #[test]
+    fn route_complete() {
+        let mut bn = MockVenue::<BinanceMarketOrder>::new("binance");
+        let mut lx = MockVenue::<Order>::new("ledgerx");
+
+        let report = route_trade(&mock_trade(), &mut bn, &mut lx);
+        assert_eq!(report.status(), TradeStatus::Complete);
+        assert_eq!(bn.sent.len(), 1);
+        assert_eq!(lx.sent.len(), 2);
+    }
+
+    #[test]
+    fn route_partial() {
+        let mut bn = MockVenue::<BinanceMarketOrder>::new("binance");
+        let mut lx = MockVenue::<Order>::rejecting("ledgerx", vec![1]);
+
+        let report = route_trade(&mock_trade(), &mut bn, &mut lx);
+        assert_eq!(report.status(), TradeStatus::Partial);
+        assert_eq!(report.errors().count(), 1);
+        assert!(report.ledgerx[0].is_ok());
+        assert!(report.ledgerx[1].is_err());
+    }
+
+    #[test]
+    fn route_rejected() {
+        let mut bn = MockVenue::<BinanceMarketOrder>::rejecting("binance", vec![0]);
+        let mut lx = MockVenue::<Order>::rejecting("ledgerx", vec![0, 1]);
+
+        let report = route_trade(&mock_trade(), &mut bn, &mut lx);
+        assert_eq!(report.status(), TradeStatus::Rejected);
+    }
+}
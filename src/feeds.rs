@@ -0,0 +1,282 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum FeedVenue {
+    LedgerX,
+    Binance,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct FeedConfig {
+    // no message for this long and the feed is considered dead
+    pub ledgerx_stale_after_ms: u64,
+    pub binance_stale_after_ms: u64,
+    // reconnect backoff, doubling from min to max
+    pub reconnect_min_ms: u64,
+    pub reconnect_max_ms: u64,
+    // LedgerX parse errors in a row before we assume the socket is gone
+    pub max_consecutive_errors: usize,
+    // also subscribe to Binance diff-depth and keep a local book for sizing the spot leg
+    pub binance_depth: bool,
+    // also poll LedgerX book-state for the full depth of every contract traded
+    pub ledgerx_depth: bool,
+    // time between the starts of two book-state passes over all the contracts
+    pub ledgerx_depth_poll_ms: u64,
+    // how often margin interest is polled for the funding carry model; Binance moves it hourly at most
+    pub margin_interest_poll_ms: u64,
+}
+
+impl Default for FeedConfig {
+    fn default() -> Self {
+        Self {
+            ledgerx_stale_after_ms: 30_000,
+            binance_stale_after_ms: 5_000,
+            reconnect_min_ms: 250,
+            reconnect_max_ms: 30_000,
+            max_consecutive_errors: 10,
+            binance_depth: false,
+            ledgerx_depth: false,
+            ledgerx_depth_poll_ms: 5_000,
+            margin_interest_poll_ms: 60_000,
+        }
+    }
+}
+
+impl FeedConfig {
+    pub fn stale_after(&self, venue: FeedVenue) -> Duration {
+        match venue {
+            FeedVenue::LedgerX => Duration::from_millis(self.ledgerx_stale_after_ms),
+            FeedVenue::Binance => Duration::from_millis(self.binance_stale_after_ms),
+        }
+    }
+}
+
+pub struct Backoff {
+    min: Duration,
+    max: Duration,
+    next: Duration,
+}
+
+impl Backoff {
+    pub fn new(cfg: &FeedConfig) -> Self {
+        let min = Duration::from_millis(cfg.reconnect_min_ms);
+        Self {
+            min,
+            max: Duration::from_millis(cfg.reconnect_max_ms),
+            next: min,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.next = self.min;
+    }
+
+    // the delay to use now, doubling the one after it
+    pub fn step(&mut self) -> Duration {
+        let delay = self.next;
+        self.next = (self.next * 2).min(self.max);
+        delay
+    }
+
+    // sleep in small slices so a shutdown isn't held up by a long backoff
+    pub fn wait(&mut self, run_flag: &AtomicBool) {
+        let until = Instant::now() + self.step();
+        while run_flag.load(Ordering::Relaxed) && Instant::now() < until {
+            std::thread::sleep(Duration::from_millis(50).min(until - Instant::now()));
+        }
+    }
+}
+
+// messages read but not yet taken by the generator
+const READER_BUFFER: usize = 1024;
+// how often a generator waiting on its reader rechecks the flags
+const READER_POLL: Duration = Duration::from_millis(50);
+// reader threads, abandoned or not, past which a generator holds off reconnecting
+pub const MAX_DETACHED_READERS: usize = 8;
+
+static DETACHED_READERS: AtomicUsize = AtomicUsize::new(0);
+
+// reader threads still running, including those abandoned on a dead socket
+pub fn detached_readers() -> usize {
+    DETACHED_READERS.load(Ordering::Relaxed)
+}
+
+// a blocking read moved onto its own thread, so the generator can walk away from a
+// socket that went silent instead of waiting on a read that may never return. The
+// abandoned thread keeps its socket until its next read, once the dead connection
+// finally errors out, which can take as long as the OS's TCP timeouts; generators
+// check `detached_readers` against MAX_DETACHED_READERS before opening another.
+pub struct DetachedReader<T> {
+    rx: Receiver<T>,
+}
+
+impl<T: Send + 'static> DetachedReader<T> {
+    pub fn spawn<F>(mut read: F) -> Self
+    where
+        F: FnMut() -> T + Send + 'static,
+    {
+        let (tx, rx) = sync_channel(READER_BUFFER);
+        DETACHED_READERS.fetch_add(1, Ordering::Relaxed);
+        std::thread::spawn(move || {
+            while tx.send(read()).is_ok() {}
+            DETACHED_READERS.fetch_sub(1, Ordering::Relaxed);
+        });
+        Self { rx }
+    }
+
+    // the next message, or None once either flag is cleared, however long the read blocks
+    pub fn next(&self, run_flag: &AtomicBool, alive: &AtomicBool) -> Option<T> {
+        while run_flag.load(Ordering::Relaxed) && alive.load(Ordering::Relaxed) {
+            match self.rx.recv_timeout(READER_POLL) {
+                Ok(msg) => return Some(msg),
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => return None,
+            }
+        }
+        None
+    }
+}
+
+// last time each feed was heard from, as seen by the event loop
+pub struct FeedMonitor {
+    cfg: FeedConfig,
+    ledgerx: FeedHealth,
+    binance: FeedHealth,
+}
+
+#[derive(Default)]
+struct FeedHealth {
+    last_seen: Option<Instant>,
+    stale: bool,
+    // when the feed was last reported stale, while it still is
+    reported: Option<Instant>,
+}
+
+impl FeedMonitor {
+    pub fn new(cfg: FeedConfig, now: Instant) -> Self {
+        // give both feeds a full window to connect before calling them stale
+        Self {
+            cfg,
+            ledgerx: FeedHealth { last_seen: Some(now), ..FeedHealth::default() },
+            binance: FeedHealth { last_seen: Some(now), ..FeedHealth::default() },
+        }
+    }
+
+    fn health(&mut self, venue: FeedVenue) -> &mut FeedHealth {
+        match venue {
+            FeedVenue::LedgerX => &mut self.ledgerx,
+            FeedVenue::Binance => &mut self.binance,
+        }
+    }
+
+    pub fn on_msg(&mut self, venue: FeedVenue, now: Instant) {
+        let health = self.health(venue);
+        health.last_seen = Some(now);
+        health.stale = false;
+        health.reported = None;
+    }
+
+    pub fn is_stale(&self, venue: FeedVenue) -> bool {
+        match venue {
+            FeedVenue::LedgerX => self.ledgerx.stale,
+            FeedVenue::Binance => self.binance.stale,
+        }
+    }
+
+    // time since the feed was last heard from
+    pub fn silent_for(&self, venue: FeedVenue, now: Instant) -> Option<Duration> {
+        let health = match venue {
+            FeedVenue::LedgerX => &self.ledgerx,
+            FeedVenue::Binance => &self.binance,
+        };
+        health.last_seen.map(|seen| now.saturating_duration_since(seen))
+    }
+
+    // feeds that went stale since the last check, and again every `stale_after` for as
+    // long as they stay silent, so a reconnect that never delivers is retried too
+    pub fn check(&mut self, now: Instant) -> Vec<FeedVenue> {
+        let mut out = vec![];
+        for venue in [FeedVenue::LedgerX, FeedVenue::Binance] {
+            let stale_after = self.cfg.stale_after(venue);
+            let health = self.health(venue);
+            let expired = !matches!(health.last_seen, Some(seen) if now.duration_since(seen) <= stale_after);
+            let due = !matches!(health.reported, Some(reported) if now.duration_since(reported) < stale_after);
+            if expired && due {
+                health.stale = true;
+                health.reported = Some(now);
+                out.push(venue);
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::{Duration, Instant};
+
+    use super::{Backoff, DetachedReader, FeedConfig, FeedMonitor, FeedVenue};
+
+    #[test]
+    fn backoff_doubles_and_caps() {
+        let cfg = FeedConfig { reconnect_min_ms: 100, reconnect_max_ms: 350, ..FeedConfig::default() };
+        let mut backoff = Backoff::new(&cfg);
+        let steps: Vec<u128> = (0..4).map(|_| backoff.step().as_millis()).collect();
+        assert_eq!(steps, vec![100, 200, 350, 350]);
+
+        backoff.reset();
+        assert_eq!(backoff.step().as_millis(), 100);
+    }
+
+    #[test]
+    fn stale_flag_interrupts_blocked_read() {
+        // one message, then a socket that never says anything again
+        let mut sent = false;
+        let reader = DetachedReader::spawn(move || {
+            if !std::mem::replace(&mut sent, true) {
+                return 1;
+            }
+            loop {
+                std::thread::park();
+            }
+        });
+        let run_flag = AtomicBool::new(true);
+        let alive = Arc::new(AtomicBool::new(true));
+        assert_eq!(reader.next(&run_flag, &alive), Some(1));
+
+        // the event loop calls the feed stale from another thread
+        let stale = alive.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(100));
+            stale.store(false, Ordering::Relaxed);
+        });
+        let t0 = Instant::now();
+        assert_eq!(reader.next(&run_flag, &alive), None);
+        assert!(t0.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn stale_reported_while_silent() {
+        let cfg = FeedConfig { ledgerx_stale_after_ms: 1000, binance_stale_after_ms: 100, ..FeedConfig::default() };
+        let t0 = Instant::now();
+        let mut monitor = FeedMonitor::new(cfg, t0);
+
+        assert!(monitor.check(t0 + Duration::from_millis(50)).is_empty());
+        assert_eq!(monitor.check(t0 + Duration::from_millis(150)), vec![FeedVenue::Binance]);
+        assert!(monitor.check(t0 + Duration::from_millis(200)).is_empty());
+        assert!(monitor.is_stale(FeedVenue::Binance));
+        // the reconnect never delivered, so it's called stale again
+        assert_eq!(monitor.check(t0 + Duration::from_millis(260)), vec![FeedVenue::Binance]);
+
+        monitor.on_msg(FeedVenue::Binance, t0 + Duration::from_millis(1050));
+        assert!(!monitor.is_stale(FeedVenue::Binance));
+        assert_eq!(monitor.check(t0 + Duration::from_millis(1100)), vec![FeedVenue::LedgerX]);
+    }
+}
@@ -0,0 +1,445 @@
+use std::collections::{HashMap, VecDeque};
+use std::str::FromStr;
+
+use binance::websockets::WebsocketEvent;
+use chrono::{offset::Utc, DateTime, Duration};
+use ftx_us_derivs::order::Order;
+use ftx_us_derivs::ws::WebSocketMsg;
+use serde::Deserialize;
+use tracing::warn;
+
+use crate::UniversalMsgWrapper;
+use crate::execution::{ExecutionError, ExecutionVenue, LegAck, LegResult};
+use crate::strat::BinanceMarketOrder;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FillModel {
+    // every leg fills in full at the price the strategy quoted
+    Quoted,
+    // legs go through SimBinance / SimLedgerX
+    Simulated,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct BacktestConfig {
+    pub fills: FillModel,
+    // from deciding to trade to the order being live at the venue
+    pub binance_latency_ms: u64,
+    pub ledgerx_latency_ms: u64,
+    // unfilled LedgerX remainders are pulled after this long
+    pub ledgerx_order_ttl_ms: u64,
+    // price concession on spot quantity beyond the top of book, in basis points
+    pub spot_slippage_bps: f64,
+}
+
+impl Default for BacktestConfig {
+    fn default() -> Self {
+        Self {
+            fills: FillModel::Simulated,
+            binance_latency_ms: 50,
+            ledgerx_latency_ms: 100,
+            ledgerx_order_ttl_ms: 2_000,
+            spot_slippage_bps: 5.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpotFill {
+    pub time: DateTime<Utc>,
+    // of the order that filled, as acked
+    pub order_id: String,
+    pub symbol: String,
+    pub is_buy: bool,
+    pub qty: f64,
+    pub price: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OptionFill {
+    pub time: DateTime<Utc>,
+    // of the order that filled, as acked
+    pub order_id: String,
+    pub contract_id: u64,
+    pub is_ask: bool,
+    pub size: u64,
+    pub price: f64,
+}
+
+// the venue sims only see what was recorded, so they're fed the same messages as the
+// strategy; an order is matched against the book as it stood when the order arrived
+
+#[derive(Debug, Clone, Copy, Default)]
+struct SpotTop {
+    bid: f64,
+    bid_qty: f64,
+    ask: f64,
+    ask_qty: f64,
+}
+
+// market orders against the recorded book ticker; quantity past the top of book is
+// assumed to fill `spot_slippage_bps` worse
+pub struct SimBinance {
+    latency: Duration,
+    slippage: f64,
+    now: DateTime<Utc>,
+    tops: HashMap<String, SpotTop>,
+    pending: VecDeque<(DateTime<Utc>, String, BinanceMarketOrder)>,
+    sent: u64,
+}
+
+impl SimBinance {
+    pub fn new(cfg: &BacktestConfig) -> Self {
+        Self {
+            latency: Duration::milliseconds(cfg.binance_latency_ms as i64),
+            slippage: cfg.spot_slippage_bps / 10_000.0,
+            now: DateTime::<Utc>::MIN_UTC,
+            tops: HashMap::new(),
+            pending: VecDeque::new(),
+            sent: 0,
+        }
+    }
+
+    // orders due by `now` execute first, then the book takes in `msg`
+    pub fn on_market(&mut self, msg: &UniversalMsgWrapper, now: DateTime<Utc>) -> Vec<SpotFill> {
+        let fills = self.advance(now);
+        if let UniversalMsgWrapper::Binance(WebsocketEvent::BookTicker(bt)) = msg {
+            let parse = |s: &str| f64::from_str(s).unwrap_or(0.0);
+            self.tops.insert(bt.symbol.to_owned(), SpotTop {
+                bid: parse(&bt.best_bid),
+                bid_qty: parse(&bt.best_bid_qty),
+                ask: parse(&bt.best_ask),
+                ask_qty: parse(&bt.best_ask_qty),
+            });
+        }
+        fills
+    }
+
+    pub fn advance(&mut self, now: DateTime<Utc>) -> Vec<SpotFill> {
+        self.now = self.now.max(now);
+        let mut fills = vec![];
+        while matches!(self.pending.front(), Some((arrival, _, _)) if *arrival <= self.now) {
+            let (arrival, order_id, order) = self.pending.pop_front().unwrap();
+            let top = match self.tops.get(&order.symbol) {
+                Some(top) => *top,
+                None => {
+                    warn!(symbol = %order.symbol, "SimBinance has no quote, dropping order");
+                    continue;
+                },
+            };
+            let (price, top_qty) = if order.is_buy { (top.ask, top.ask_qty) } else { (top.bid, top.bid_qty) };
+            if price <= 0.0 {
+                continue;
+            }
+            let at_top = order.qty.min(top_qty);
+            let beyond = order.qty - at_top;
+            let worse = if order.is_buy { price * (1.0 + self.slippage) } else { price * (1.0 - self.slippage) };
+            fills.push(SpotFill {
+                time: arrival,
+                order_id,
+                symbol: order.symbol,
+                is_buy: order.is_buy,
+                qty: order.qty,
+                price: (at_top * price + beyond * worse) / order.qty,
+            });
+        }
+        fills
+    }
+}
+
+impl ExecutionVenue for SimBinance {
+    type Order = BinanceMarketOrder;
+
+    fn name(&self) -> &'static str {
+        "SimBinance"
+    }
+
+    fn submit(&mut self, order: &BinanceMarketOrder) -> LegResult {
+        self.sent += 1;
+        let id = format!("sim-bn-{}", self.sent);
+        self.pending.push_back((self.now + self.latency, id.clone(), order.clone()));
+        Ok(LegAck { venue: self.name(), order_id: id, filled_qty: 0.0 })
+    }
+
+    // market orders don't rest
+    fn cancel(&mut self, _order: &BinanceMarketOrder, _order_id: &str) -> Result<(), ExecutionError> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct OptionTop {
+    bid: f64,
+    bid_size: u64,
+    ask: f64,
+    ask_size: u64,
+}
+
+impl OptionTop {
+    // best price and size on the side an order rests on, and on the side it trades against
+    fn same_side(&self, is_ask: bool) -> (f64, u64) {
+        if is_ask { (self.ask, self.ask_size) } else { (self.bid, self.bid_size) }
+    }
+    fn opposite_side(&self, is_ask: bool) -> (f64, u64) {
+        if is_ask { (self.bid, self.bid_size) } else { (self.ask, self.ask_size) }
+    }
+}
+
+fn crosses(is_ask: bool, limit: f64, opposite: f64) -> bool {
+    opposite > 0.0 && if is_ask { opposite >= limit } else { opposite <= limit }
+}
+
+struct SimOrder {
+    // as acked, "sim-lx-<n>"
+    id: String,
+    contract_id: u64,
+    is_ask: bool,
+    price: f64,
+    remaining: u64,
+    // displayed size at our price that was there first; None if we're behind the top of book
+    queue_ahead: Option<u64>,
+    expires: DateTime<Utc>,
+}
+
+// limit orders against the recorded top of book: anything marketable on arrival takes the
+// opposite side, the rest rests and fills when the other side crosses it or when the
+// displayed size at its price shrinks by more than what was queued ahead of it
+pub struct SimLedgerX {
+    latency: Duration,
+    ttl: Duration,
+    now: DateTime<Utc>,
+    tops: HashMap<u64, OptionTop>,
+    pending: VecDeque<(DateTime<Utc>, SimOrder)>,
+    resting: Vec<SimOrder>,
+    sent: u64,
+}
+
+impl SimLedgerX {
+    pub fn new(cfg: &BacktestConfig) -> Self {
+        Self {
+            latency: Duration::milliseconds(cfg.ledgerx_latency_ms as i64),
+            ttl: Duration::milliseconds(cfg.ledgerx_order_ttl_ms as i64),
+            now: DateTime::<Utc>::MIN_UTC,
+            tops: HashMap::new(),
+            pending: VecDeque::new(),
+            resting: vec![],
+            sent: 0,
+        }
+    }
+
+    pub fn resting(&self) -> usize {
+        self.resting.len()
+    }
+
+    pub fn on_market(&mut self, msg: &UniversalMsgWrapper, now: DateTime<Utc>) -> Vec<OptionFill> {
+        let mut fills = self.advance(now);
+        if let UniversalMsgWrapper::LedgerX(WebSocketMsg::BookTop(bt)) = msg {
+            let old = self.tops.get(&bt.contract_id).cloned().unwrap_or_default();
+            let new = OptionTop { bid: bt.bid, bid_size: bt.bid_size, ask: bt.ask, ask_size: bt.ask_size };
+            self.tops.insert(bt.contract_id, new);
+
+            let now = self.now;
+            for order in self.resting.iter_mut().filter(|o| o.contract_id == bt.contract_id) {
+                let size = Self::match_update(order, &old, &new);
+                if size > 0 {
+                    order.remaining -= size;
+                    fills.push(OptionFill { time: now, order_id: order.id.clone(), contract_id: order.contract_id, is_ask: order.is_ask, size, price: order.price });
+                }
+            }
+            self.resting.retain(|o| o.remaining > 0);
+        }
+        fills
+    }
+
+    pub fn advance(&mut self, now: DateTime<Utc>) -> Vec<OptionFill> {
+        self.now = self.now.max(now);
+        let mut fills = vec![];
+
+        while matches!(self.pending.front(), Some((arrival, _)) if *arrival <= self.now) {
+            let (arrival, mut order) = self.pending.pop_front().unwrap();
+            let top = self.tops.get(&order.contract_id).cloned().unwrap_or_default();
+
+            let (opposite, opposite_size) = top.opposite_side(order.is_ask);
+            if crosses(order.is_ask, order.price, opposite) {
+                let size = order.remaining.min(opposite_size);
+                if size > 0 {
+                    order.remaining -= size;
+                    fills.push(OptionFill { time: arrival, order_id: order.id.clone(), contract_id: order.contract_id, is_ask: order.is_ask, size, price: opposite });
+                }
+            }
+            if order.remaining > 0 {
+                let (best, best_size) = top.same_side(order.is_ask);
+                let improves = if order.is_ask { order.price < best } else { order.price > best };
+                order.queue_ahead = if best <= 0.0 || improves {
+                    // improves on (or sets) the best price, nobody is ahead
+                    Some(0)
+                } else if best == order.price {
+                    Some(best_size)
+                } else {
+                    None
+                };
+                order.expires = arrival + self.ttl;
+                self.resting.push(order);
+            }
+        }
+
+        let now = self.now;
+        self.resting.retain(|o| o.expires > now);
+        fills
+    }
+
+    fn match_update(order: &mut SimOrder, old: &OptionTop, new: &OptionTop) -> u64 {
+        let (opposite, opposite_size) = new.opposite_side(order.is_ask);
+        if crosses(order.is_ask, order.price, opposite) {
+            return order.remaining.min(opposite_size);
+        }
+
+        let (old_best, old_size) = old.same_side(order.is_ask);
+        let (new_best, new_size) = new.same_side(order.is_ask);
+        if new_best != order.price {
+            return 0;
+        }
+        match order.queue_ahead {
+            // the level we're on shrank: whatever's ahead goes first, the rest is us
+            Some(ahead) if old_best == order.price && new_size < old_size => {
+                let traded = old_size - new_size;
+                let consumed = traded.min(ahead);
+                order.queue_ahead = Some(ahead - consumed);
+                order.remaining.min(traded - consumed)
+            },
+            // the top came down to our price; assume the whole level was there before us
+            None => {
+                order.queue_ahead = Some(new_size);
+                0
+            },
+            _ => 0,
+        }
+    }
+}
+
+impl ExecutionVenue for SimLedgerX {
+    type Order = Order;
+
+    fn name(&self) -> &'static str {
+        "SimLedgerX"
+    }
+
+    fn submit(&mut self, order: &Order) -> LegResult {
+        self.sent += 1;
+        let id = format!("sim-lx-{}", self.sent);
+        self.pending.push_back((self.now + self.latency, SimOrder {
+            id: id.clone(),
+            contract_id: order.contract_id,
+            is_ask: order.is_ask,
+            price: order.price,
+            remaining: order.size,
+            queue_ahead: None,
+            expires: DateTime::<Utc>::MAX_UTC,
+        }));
+        Ok(LegAck { venue: self.name(), order_id: id, filled_qty: 0.0 })
+    }
+
+    // takes effect at once; a cancel racing a fill always wins here
+    fn cancel(&mut self, _order: &Order, order_id: &str) -> Result<(), ExecutionError> {
+        self.pending.retain(|(_, o)| o.id != order_id);
+        self.resting.retain(|o| o.id != order_id);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use binance::model::BookTickerEvent;
+    use binance::websockets::WebsocketEvent;
+    use chrono::{DateTime, Duration, Utc};
+    use ftx_us_derivs::order::Order;
+    use ftx_us_derivs::ws::{BookTop, WebSocketMsg};
+
+    use crate::UniversalMsgWrapper;
+    use crate::execution::ExecutionVenue;
+    use crate::strat::BinanceMarketOrder;
+    use super::{BacktestConfig, SimBinance, SimLedgerX};
+
+    fn book_top(bid: f64, bid_size: u64, ask: f64, ask_size: u64) -> UniversalMsgWrapper {
+        UniversalMsgWrapper::LedgerX(WebSocketMsg::BookTop(
+            BookTop { bid, bid_size, ask, ask_size, contract_id: 1, contract_type: 0, clock: 0 }
+        ))
+    }
+
+    fn spot(bid: &str, ask: &str, qty: &str) -> UniversalMsgWrapper {
+        UniversalMsgWrapper::Binance(WebsocketEvent::BookTicker(BookTickerEvent {
+            update_id: 0,
+            symbol: "BTCUSDT".to_string(),
+            best_bid: bid.to_string(),
+            best_bid_qty: qty.to_string(),
+            best_ask: ask.to_string(),
+            best_ask_qty: qty.to_string(),
+        }))
+    }
+
+    #[test]
+    fn spot_latency_and_slippage() {
+        let cfg = BacktestConfig { binance_latency_ms: 50, spot_slippage_bps: 10.0, ..BacktestConfig::default() };
+        let mut bn = SimBinance::new(&cfg);
+        let t0 = DateTime::<Utc>::MIN_UTC + Duration::days(1);
+        let ms = Duration::milliseconds;
+
+        assert!(bn.on_market(&spot("19999.0", "20000.0", "1.0"), t0).is_empty());
+        bn.submit(&BinanceMarketOrder { symbol: "BTCUSDT".to_string(), is_buy: true, qty: 2.0, price: 20000.0 }).unwrap();
+
+        // still in flight when the ask lifts, so it pays the new price
+        assert!(bn.on_market(&spot("20009.0", "20010.0", "1.0"), t0 + ms(40)).is_empty());
+        let fills = bn.on_market(&spot("20019.0", "20020.0", "1.0"), t0 + ms(60));
+        assert_eq!(fills.len(), 1);
+        assert_eq!(fills[0].time, t0 + ms(50));
+        assert_eq!(fills[0].qty, 2.0);
+        // one coin at the top of book, one 10bps through it
+        assert!((fills[0].price - (20010.0 + 20010.0 * 1.001) / 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn options_partial_then_queue() {
+        let cfg = BacktestConfig { ledgerx_latency_ms: 100, ledgerx_order_ttl_ms: 1_000, ..BacktestConfig::default() };
+        let mut lx = SimLedgerX::new(&cfg);
+        let t0 = DateTime::<Utc>::MIN_UTC + Duration::days(1);
+        let ms = Duration::milliseconds;
+
+        lx.on_market(&book_top(500.0, 5, 580.0, 3), t0);
+        // buy 10 at 580: takes the 3 offered, the other 7 join nobody at 580
+        lx.submit(&Order::new(1, false, 580.0, 10)).unwrap();
+        let fills = lx.advance(t0 + ms(100));
+        assert_eq!(fills.len(), 1);
+        assert_eq!((fills[0].size, fills[0].price), (3, 580.0));
+        assert_eq!(lx.resting(), 1);
+
+        // a seller hits 4 into our bid
+        let fills = lx.on_market(&book_top(500.0, 5, 570.0, 4), t0 + ms(200));
+        assert_eq!((fills[0].size, fills[0].price), (4, 580.0));
+
+        // the rest is pulled once the order's time is up
+        assert!(lx.advance(t0 + ms(1_100)).is_empty());
+        assert_eq!(lx.resting(), 0);
+    }
+
+    #[test]
+    fn options_queue_ahead() {
+        let cfg = BacktestConfig { ledgerx_latency_ms: 0, ..BacktestConfig::default() };
+        let mut lx = SimLedgerX::new(&cfg);
+        let t0 = DateTime::<Utc>::MIN_UTC + Duration::days(1);
+        let ms = Duration::milliseconds;
+
+        lx.on_market(&book_top(500.0, 5, 580.0, 3), t0);
+        // join the 5 already bid at 500
+        lx.submit(&Order::new(1, false, 500.0, 4)).unwrap();
+        assert!(lx.advance(t0).is_empty());
+
+        // 3 trade at 500: all of it queued ahead of us
+        assert!(lx.on_market(&book_top(500.0, 2, 580.0, 3), t0 + ms(1)).is_empty());
+        // others join behind us, then 3 more trade: 2 ahead, then 1 of ours
+        assert!(lx.on_market(&book_top(500.0, 6, 580.0, 3), t0 + ms(2)).is_empty());
+        let fills = lx.on_market(&book_top(500.0, 3, 580.0, 3), t0 + ms(3));
+        assert_eq!(fills[0].size, 1);
+    }
+}
@@ -0,0 +1,217 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+use crate::feeds::FeedVenue;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LatencyConfig {
+    // how often the histograms are printed; 0 turns the dump off
+    pub dump_interval_ms: u64,
+}
+
+impl Default for LatencyConfig {
+    fn default() -> Self {
+        Self { dump_interval_ms: 60_000 }
+    }
+}
+
+// where a tick's time goes on its way to an order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Stage {
+    // generator receipt to dequeue in the event loop: time spent in the mpsc channel
+    Channel,
+    // dequeue to trade decision: book updates and arb checks
+    Strategy,
+    // trade decision to both venues answering
+    Execution,
+    // generator receipt to both venues answering
+    WireToOrder,
+}
+
+impl Stage {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Stage::Channel => "channel",
+            Stage::Strategy => "strategy",
+            Stage::Execution => "execution",
+            Stage::WireToOrder => "wire_to_order",
+        }
+    }
+}
+
+// upper bounds in microseconds, 1-2-5 steps from 1us to 10s; anything slower overflows
+pub const BUCKET_BOUNDS_US: [u64; 22] = [
+    1, 2, 5, 10, 20, 50, 100, 200, 500,
+    1_000, 2_000, 5_000, 10_000, 20_000, 50_000, 100_000, 200_000, 500_000,
+    1_000_000, 2_000_000, 5_000_000, 10_000_000,
+];
+
+#[derive(Debug, Clone, Default)]
+pub struct Histogram {
+    // one per bound plus the overflow
+    counts: [u64; BUCKET_BOUNDS_US.len() + 1],
+    count: u64,
+    sum: Duration,
+    max: Duration,
+}
+
+impl Histogram {
+    pub fn record(&mut self, d: Duration) {
+        let us = d.as_micros() as u64;
+        let bucket = BUCKET_BOUNDS_US.partition_point(|&bound| bound < us);
+        self.counts[bucket] += 1;
+        self.count += 1;
+        self.sum += d;
+        self.max = self.max.max(d);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn sum(&self) -> Duration {
+        self.sum
+    }
+
+    pub fn max(&self) -> Duration {
+        self.max
+    }
+
+    pub fn mean(&self) -> Option<Duration> {
+        (self.count > 0).then(|| self.sum / self.count as u32)
+    }
+
+    // per-bucket counts, the last being the overflow
+    pub fn buckets(&self) -> &[u64] {
+        &self.counts
+    }
+
+    // upper bound of the bucket holding the q-th sample; the max if it overflowed
+    pub fn quantile(&self, q: f64) -> Option<Duration> {
+        if self.count == 0 {
+            return None;
+        }
+        let rank = ((q.clamp(0.0, 1.0) * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (i, n) in self.counts.iter().enumerate() {
+            seen += n;
+            if seen >= rank {
+                return Some(match BUCKET_BOUNDS_US.get(i) {
+                    Some(&us) => Duration::from_micros(us).min(self.max),
+                    None => self.max,
+                });
+            }
+        }
+        Some(self.max)
+    }
+}
+
+pub struct LatencyStats {
+    hists: BTreeMap<(Stage, FeedVenue), Histogram>,
+    dump_interval: Option<Duration>,
+    last_dump: Instant,
+}
+
+impl LatencyStats {
+    pub fn new(cfg: &LatencyConfig, now: Instant) -> Self {
+        Self {
+            hists: BTreeMap::new(),
+            dump_interval: (cfg.dump_interval_ms > 0).then(|| Duration::from_millis(cfg.dump_interval_ms)),
+            last_dump: now,
+        }
+    }
+
+    // `venue` is the feed whose message started it all
+    pub fn record(&mut self, stage: Stage, venue: FeedVenue, d: Duration) {
+        self.hists.entry((stage, venue)).or_default().record(d);
+    }
+
+    pub fn histogram(&self, stage: Stage, venue: FeedVenue) -> Option<&Histogram> {
+        self.hists.get(&(stage, venue))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Stage, FeedVenue, &Histogram)> + '_ {
+        self.hists.iter().map(|(&(stage, venue), h)| (stage, venue, h))
+    }
+
+    // the stats, if a dump is due; histograms keep accumulating across dumps
+    pub fn dump_due(&mut self, now: Instant) -> Option<&Self> {
+        let interval = self.dump_interval?;
+        if now.duration_since(self.last_dump) < interval {
+            return None;
+        }
+        self.last_dump = now;
+        Some(self)
+    }
+}
+
+fn us(d: Option<Duration>) -> u128 {
+    d.map(|d| d.as_micros()).unwrap_or(0)
+}
+
+impl fmt::Display for LatencyStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{:<14} {:<8} {:>9} {:>9} {:>9} {:>9} {:>9}", "stage", "venue", "count", "mean_us", "p50_us", "p99_us", "max_us")?;
+        for (stage, venue, h) in self.iter() {
+            writeln!(
+                f,
+                "{:<14} {:<8} {:>9} {:>9} {:>9} {:>9} {:>9}",
+                stage.name(),
+                format!("{:?}", venue),
+                h.count(),
+                us(h.mean()),
+                us(h.quantile(0.5)),
+                us(h.quantile(0.99)),
+                h.max().as_micros(),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::feeds::FeedVenue;
+    use super::{Histogram, LatencyConfig, LatencyStats, Stage};
+
+    #[test]
+    fn histogram_quantiles() {
+        let mut h = Histogram::default();
+        assert_eq!(h.quantile(0.5), None);
+
+        for us in [3, 4, 4, 8, 15, 30, 45, 90, 400, 12_000_000] {
+            h.record(Duration::from_micros(us));
+        }
+        assert_eq!(h.count(), 10);
+        assert_eq!(h.quantile(0.0), Some(Duration::from_micros(5)));
+        assert_eq!(h.quantile(0.5), Some(Duration::from_micros(20)));
+        assert_eq!(h.quantile(0.9), Some(Duration::from_micros(500)));
+        // the overflow bucket reports the max
+        assert_eq!(h.quantile(1.0), Some(Duration::from_secs(12)));
+        assert_eq!(h.buckets().last(), Some(&1));
+    }
+
+    #[test]
+    fn per_stage_and_venue() {
+        let t0 = Instant::now();
+        let mut stats = LatencyStats::new(&LatencyConfig { dump_interval_ms: 1_000 }, t0);
+        stats.record(Stage::Channel, FeedVenue::Binance, Duration::from_micros(40));
+        stats.record(Stage::Channel, FeedVenue::Binance, Duration::from_micros(60));
+        stats.record(Stage::Strategy, FeedVenue::LedgerX, Duration::from_micros(7));
+
+        assert_eq!(stats.histogram(Stage::Channel, FeedVenue::Binance).unwrap().mean(), Some(Duration::from_micros(50)));
+        assert!(stats.histogram(Stage::Channel, FeedVenue::LedgerX).is_none());
+        assert_eq!(stats.iter().count(), 2);
+
+        assert!(stats.dump_due(t0 + Duration::from_millis(500)).is_none());
+        let report = stats.dump_due(t0 + Duration::from_millis(1_000)).unwrap().to_string();
+        assert!(report.contains("strategy"));
+        assert!(stats.dump_due(t0 + Duration::from_millis(1_500)).is_none());
+    }
+}
@@ -0,0 +1,599 @@
+use std::time::{Duration, Instant};
+
+use ftx_us_derivs::order::Order;
+
+use crate::execution::{ExecutionVenue, LegResult, TradeReport, route_trade};
+use crate::strat::{BinanceMarketOrder, Trade};
+
+// fills below this are treated as nothing, spot quantities are floats
+const QTY_EPSILON: f64 = 1e-9;
+// how long a cancelled option leg is still watched for a fill that raced the cancel
+const LATE_FILL_WINDOW: Duration = Duration::from_secs(60);
+
+pub struct LeggingConfig {
+    // how long legs may stay unfilled before the residual exposure is unwound, and how
+    // long the unwind itself may take before it's pulled and left to a human
+    pub timeout: Duration,
+    // price concession when unwinding LedgerX legs, as a fraction of the original price
+    pub opts_unwind_slippage: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LegStatus {
+    Working,
+    Filled,
+    Failed,
+    // pulled from the book at the timeout; a fill may still race the cancel
+    Cancelled,
+}
+
+#[derive(Debug)]
+pub struct SpotLeg {
+    pub order: BinanceMarketOrder,
+    // as acked by the venue; fills are matched on it
+    pub order_id: Option<String>,
+    pub filled_qty: f64,
+    pub status: LegStatus,
+}
+impl SpotLeg {
+    fn fill_frac(&self) -> f64 {
+        self.filled_qty / self.order.qty
+    }
+}
+
+#[derive(Debug)]
+pub struct OptionLeg {
+    pub order: Order,
+    // as acked by the venue; fills are matched on it, and it's needed to cancel
+    pub order_id: Option<String>,
+    pub filled_size: u64,
+    pub status: LegStatus,
+}
+impl OptionLeg {
+    fn fill_frac(&self) -> f64 {
+        self.filled_size as f64 / self.order.size as f64
+    }
+}
+
+fn spot_legs(orders: Vec<BinanceMarketOrder>, results: Vec<LegResult>) -> Vec<SpotLeg> {
+    debug_assert!(orders.len() == results.len());
+    orders.into_iter().zip(results).map(|(order, res)| {
+        match res {
+            Ok(ack) => SpotLeg {
+                status: if ack.filled_qty + QTY_EPSILON >= order.qty { LegStatus::Filled } else { LegStatus::Working },
+                filled_qty: ack.filled_qty,
+                order_id: Some(ack.order_id),
+                order,
+            },
+            Err(_) => SpotLeg { order, order_id: None, filled_qty: 0.0, status: LegStatus::Failed },
+        }
+    }).collect()
+}
+
+fn option_legs(orders: Vec<Order>, results: Vec<LegResult>) -> Vec<OptionLeg> {
+    debug_assert!(orders.len() == results.len());
+    orders.into_iter().zip(results).map(|(order, res)| {
+        match res {
+            Ok(ack) => OptionLeg { order, order_id: Some(ack.order_id), filled_size: 0, status: LegStatus::Working },
+            Err(_) => OptionLeg { order, order_id: None, filled_size: 0, status: LegStatus::Failed },
+        }
+    }).collect()
+}
+
+fn fill_spot(legs: &mut [SpotLeg], order_id: &str, qty: f64) -> bool {
+    let leg = legs.iter_mut()
+        .find(|l| l.status == LegStatus::Working && l.order_id.as_deref() == Some(order_id));
+    if let Some(leg) = leg {
+        leg.filled_qty += qty;
+        if leg.filled_qty + QTY_EPSILON >= leg.order.qty {
+            leg.status = LegStatus::Filled;
+        }
+        return true;
+    }
+    false
+}
+
+fn fill_option(legs: &mut [OptionLeg], order_id: &str, size: u64) -> bool {
+    let leg = legs.iter_mut()
+        .find(|l| l.status == LegStatus::Working && l.order_id.as_deref() == Some(order_id));
+    if let Some(leg) = leg {
+        leg.filled_size += size;
+        if leg.filled_size >= leg.order.size {
+            leg.status = LegStatus::Filled;
+        }
+        return true;
+    }
+    false
+}
+
+// the opposite side of `order`; we were the ask, so now we bid, giving up some edge to get out
+fn unwind_order(order: &Order, size: u64, cfg: &LeggingConfig) -> Order {
+    let price = if order.is_ask {
+        order.price * (1.0 + cfg.opts_unwind_slippage)
+    } else {
+        order.price * (1.0 - cfg.opts_unwind_slippage)
+    };
+    Order::new(order.contract_id, !order.is_ask, price, size)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComboState {
+    // legs are still working and the timeout has not elapsed
+    Working,
+    // every leg filled in full
+    Filled,
+    // some leg failed or timed out; the orders flattening the residual are working
+    Unwinding,
+    // the residual exposure was flattened in full
+    Hedged,
+    // the hedge was rejected or didn't fill in time, or a leg couldn't be pulled; needs a human
+    HedgeFailed,
+}
+
+// what's left of an option order cancelled at a timeout
+#[derive(Debug)]
+pub struct CancelledLeg {
+    pub order_id: String,
+    pub order: Order,
+    pub unfilled: u64,
+    pub cancelled: Instant,
+    // a combo leg's late fill is new exposure to flatten; an unwind's only takes some off
+    pub flatten: bool,
+}
+
+// execution state of a single multi-leg `Trade`
+#[derive(Debug)]
+pub struct ComboExecution {
+    pub started: Instant,
+    pub spot: Vec<SpotLeg>,
+    pub opts: Vec<OptionLeg>,
+    pub state: ComboState,
+    // orders sent to flatten the residual exposure, followed like the legs until they fill
+    pub unwind_started: Option<Instant>,
+    pub unwind_spot: Vec<SpotLeg>,
+    pub unwind_opts: Vec<OptionLeg>,
+    // a working option leg we couldn't pull; it can still fill for its full size
+    cancel_failed: bool,
+    // options pulled since the manager last looked, for it to watch for late fills
+    cancelled: Vec<CancelledLeg>,
+}
+
+impl ComboExecution {
+    pub fn new(t: Trade, report: TradeReport, now: Instant) -> Self {
+        Self {
+            started: now,
+            spot: spot_legs(t.binance, report.binance),
+            opts: option_legs(t.ledgerx, report.ledgerx),
+            state: ComboState::Working,
+            unwind_started: None,
+            unwind_spot: vec![],
+            unwind_opts: vec![],
+            cancel_failed: false,
+            cancelled: vec![],
+        }
+    }
+
+    // nothing but a hedge to follow, for a fill that turned up after its combo was unwound
+    pub fn unwinding(unwind: Trade, report: TradeReport, now: Instant) -> Self {
+        let mut exec = Self::new(Trade::empty(), TradeReport { binance: vec![], ledgerx: vec![] }, now);
+        exec.state = ComboState::Unwinding;
+        exec.unwind_started = Some(now);
+        exec.unwind_spot = spot_legs(unwind.binance, report.binance);
+        exec.unwind_opts = option_legs(unwind.ledgerx, report.ledgerx);
+        exec
+    }
+
+    fn legs_status(&self) -> impl Iterator<Item = LegStatus> + '_ {
+        self.spot.iter().map(|l| l.status).chain(self.opts.iter().map(|l| l.status))
+    }
+
+    fn unwind_status(&self) -> impl Iterator<Item = LegStatus> + '_ {
+        self.unwind_spot.iter().map(|l| l.status).chain(self.unwind_opts.iter().map(|l| l.status))
+    }
+
+    pub fn on_binance_fill(&mut self, order_id: &str, qty: f64) -> bool {
+        fill_spot(&mut self.spot, order_id, qty) || fill_spot(&mut self.unwind_spot, order_id, qty)
+    }
+
+    pub fn on_ledgerx_fill(&mut self, order_id: &str, size: u64) -> bool {
+        fill_option(&mut self.opts, order_id, size) || fill_option(&mut self.unwind_opts, order_id, size)
+    }
+
+    // true if some leg has (partially) filled while another is behind it
+    pub fn is_one_sided(&self) -> bool {
+        let fracs: Vec<f64> = self.spot.iter().map(|l| l.fill_frac())
+            .chain(self.opts.iter().map(|l| l.fill_frac()))
+            .collect();
+        let max = fracs.iter().cloned().fold(0.0, f64::max);
+        let min = fracs.iter().cloned().fold(1.0, f64::min);
+        max - min > QTY_EPSILON
+    }
+
+    // orders that take every leg back down to the fraction filled on all legs
+    pub fn unwind_trade(&self, cfg: &LeggingConfig) -> Trade {
+        let matched = self.spot.iter().map(|l| l.fill_frac())
+            .chain(self.opts.iter().map(|l| l.fill_frac()))
+            .fold(1.0, f64::min);
+
+        let mut out = Trade::empty();
+        for leg in self.spot.iter() {
+            let excess = leg.filled_qty - matched * leg.order.qty;
+            if excess > QTY_EPSILON {
+                out.binance.push(BinanceMarketOrder {
+                    symbol: leg.order.symbol.to_owned(),
+                    is_buy: !leg.order.is_buy,
+                    qty: excess,
+                    price: leg.order.price,
+                });
+            }
+        }
+        for leg in self.opts.iter() {
+            let excess = leg.filled_size.saturating_sub((matched * leg.order.size as f64).round() as u64);
+            if excess > 0 {
+                out.ledgerx.push(unwind_order(&leg.order, excess, cfg));
+            }
+        }
+        out
+    }
+
+    // stop counting on whatever is still working; options are pulled from the book
+    fn cancel_working<L>(legs: &mut [OptionLeg], now: Instant, flatten: bool, ledgerx: &mut L, cancelled: &mut Vec<CancelledLeg>) -> bool
+    where
+        L: ExecutionVenue<Order = Order>,
+    {
+        let mut ok = true;
+        for leg in legs.iter_mut().filter(|l| l.status == LegStatus::Working) {
+            leg.status = LegStatus::Cancelled;
+            let order_id = match &leg.order_id {
+                Some(order_id) => order_id,
+                None => continue,
+            };
+            ok &= ledgerx.cancel(&leg.order, order_id).is_ok();
+            let o = &leg.order;
+            cancelled.push(CancelledLeg {
+                order_id: order_id.to_owned(),
+                order: Order::new(o.contract_id, o.is_ask, o.price, o.size),
+                unfilled: o.size - leg.filled_size.min(o.size),
+                cancelled: now,
+                flatten,
+            });
+        }
+        ok
+    }
+
+    // advance the state machine; unwinds once a leg fails or the timeout elapses, and
+    // follows the unwind until it has filled or its own timeout elapses
+    pub fn poll<B, L>(&mut self, now: Instant, cfg: &LeggingConfig, binance: &mut B, ledgerx: &mut L) -> ComboState
+    where
+        B: ExecutionVenue<Order = BinanceMarketOrder>,
+        L: ExecutionVenue<Order = Order>,
+    {
+        match self.state {
+            ComboState::Working => {},
+            ComboState::Unwinding => return self.poll_unwind(now, cfg, ledgerx),
+            _ => return self.state,
+        }
+
+        if self.legs_status().all(|s| s == LegStatus::Filled) {
+            self.state = ComboState::Filled;
+            return self.state;
+        }
+
+        let any_failed = self.legs_status().any(|s| s == LegStatus::Failed);
+        let timed_out = now.duration_since(self.started) >= cfg.timeout;
+        if !any_failed && !timed_out {
+            return self.state;
+        }
+
+        // stop counting on the working legs, pull the resting options and flatten whatever is left over
+        for leg in self.spot.iter_mut().filter(|l| l.status == LegStatus::Working) {
+            leg.status = LegStatus::Failed;
+        }
+        self.cancel_failed |= !Self::cancel_working(&mut self.opts, now, true, ledgerx, &mut self.cancelled);
+
+        let unwind = self.unwind_trade(cfg);
+        let report = route_trade(&unwind, binance, ledgerx);
+        self.unwind_spot = spot_legs(unwind.binance, report.binance);
+        self.unwind_opts = option_legs(unwind.ledgerx, report.ledgerx);
+        self.unwind_started = Some(now);
+        self.state = ComboState::Unwinding;
+        self.poll_unwind(now, cfg, ledgerx)
+    }
+
+    fn poll_unwind<L>(&mut self, now: Instant, cfg: &LeggingConfig, ledgerx: &mut L) -> ComboState
+    where
+        L: ExecutionVenue<Order = Order>,
+    {
+        let started = self.unwind_started.unwrap_or(now);
+        let working = self.unwind_status().any(|s| s == LegStatus::Working);
+        if working && now.duration_since(started) < cfg.timeout {
+            return self.state;
+        }
+
+        // whatever of the hedge hasn't filled by now is left open, and stays on the books as such
+        for leg in self.unwind_spot.iter_mut().filter(|l| l.status == LegStatus::Working) {
+            leg.status = LegStatus::Failed;
+        }
+        self.cancel_failed |= !Self::cancel_working(&mut self.unwind_opts, now, false, ledgerx, &mut self.cancelled);
+
+        let all_filled = self.unwind_status().all(|s| s == LegStatus::Filled);
+        self.state = if all_filled && !self.cancel_failed { ComboState::Hedged } else { ComboState::HedgeFailed };
+        self.state
+    }
+}
+
+// all the in-flight combos, polled from the event loop
+pub struct LeggingManager {
+    pub config: LeggingConfig,
+    pub active: Vec<ComboExecution>,
+    pub cancelled: Vec<CancelledLeg>,
+}
+
+impl LeggingManager {
+    pub fn new(config: LeggingConfig) -> Self {
+        Self { config, active: vec![], cancelled: vec![] }
+    }
+
+    pub fn track(&mut self, t: Trade, report: TradeReport, now: Instant) {
+        self.active.push(ComboExecution::new(t, report, now));
+    }
+
+    // fills are matched on the id the order was acked with, never on the contract or pair,
+    // which other combos and unwinds may be working too
+    pub fn on_ledgerx_fill(&mut self, order_id: &str, size: u64) -> bool {
+        self.active.iter_mut().any(|exec| exec.on_ledgerx_fill(order_id, size))
+    }
+
+    pub fn on_binance_fill(&mut self, order_id: &str, qty: f64) -> bool {
+        self.active.iter_mut().any(|exec| exec.on_binance_fill(order_id, qty))
+    }
+
+    // a fill on an order that was already pulled, returned for booking as it stands. A
+    // combo leg's is new exposure the combo's unwind didn't cover, so it's flattened and
+    // that hedge followed like any other unwind.
+    pub fn on_late_fill<B, L>(&mut self, order_id: &str, size: u64, now: Instant, binance: &mut B, ledgerx: &mut L) -> Option<Order>
+    where
+        B: ExecutionVenue<Order = BinanceMarketOrder>,
+        L: ExecutionVenue<Order = Order>,
+    {
+        let leg = self.cancelled.iter_mut()
+            .find(|l| l.order_id == order_id && l.unfilled > 0)?;
+        let size = size.min(leg.unfilled);
+        leg.unfilled -= size;
+
+        let fill = Order::new(leg.order.contract_id, leg.order.is_ask, leg.order.price, size);
+        if leg.flatten {
+            let mut unwind = Trade::empty();
+            unwind.ledgerx.push(unwind_order(&leg.order, size, &self.config));
+            let report = route_trade(&unwind, binance, ledgerx);
+            self.active.push(ComboExecution::unwinding(unwind, report, now));
+        }
+        Some(fill)
+    }
+
+    // returns the executions that reached a terminal state on this poll
+    pub fn poll<B, L>(&mut self, now: Instant, binance: &mut B, ledgerx: &mut L) -> Vec<ComboExecution>
+    where
+        B: ExecutionVenue<Order = BinanceMarketOrder>,
+        L: ExecutionVenue<Order = Order>,
+    {
+        self.cancelled.retain(|l| l.unfilled > 0 && now.duration_since(l.cancelled) < LATE_FILL_WINDOW);
+        for exec in self.active.iter_mut() {
+            exec.poll(now, &self.config, binance, ledgerx);
+            self.cancelled.append(&mut exec.cancelled);
+        }
+
+        let (done, active): (Vec<_>, Vec<_>) = self.active.drain(..)
+            .partition(|exec| !matches!(exec.state, ComboState::Working | ComboState::Unwinding));
+        self.active = active;
+        done
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use ftx_us_derivs::order::Order;
+
+    use crate::execution::{route_trade, MockVenue};
+    use crate::strat::{BinanceMarketOrder, Trade};
+    use super::{ComboState, LeggingConfig, LeggingManager};
+
+    fn cfg() -> LeggingConfig {
+        LeggingConfig {
+            timeout: Duration::from_millis(500),
+            opts_unwind_slippage: 0.05,
+        }
+    }
+
+    // a reversal: short spot, long call, short put
+    fn reversal() -> Trade {
+        Trade {
+            binance: vec![
+                BinanceMarketOrder { symbol: "BTCUSDT".to_string(), is_buy: false, qty: 0.5, price: 20299.0 },
+            ],
+            ledgerx: vec![
+                Order::new(22248027, false, 11180.0, 50),
+                Order::new(22248028, true, 500.0, 50),
+            ],
+        }
+    }
+
+    #[test]
+    fn all_legs_fill() {
+        let mut bn = MockVenue::<BinanceMarketOrder>::new("binance");
+        let mut lx = MockVenue::<Order>::new("ledgerx");
+        let mut mngr = LeggingManager::new(cfg());
+        let t0 = Instant::now();
+
+        let t = reversal();
+        let report = route_trade(&t, &mut bn, &mut lx);
+        mngr.track(t, report, t0);
+
+        assert!(mngr.on_binance_fill("binance-0", 0.5));
+        assert!(mngr.on_ledgerx_fill("ledgerx-0", 50));
+        assert!(mngr.poll(t0, &mut bn, &mut lx).is_empty());
+        assert!(mngr.on_ledgerx_fill("ledgerx-1", 50));
+
+        let done = mngr.poll(t0, &mut bn, &mut lx);
+        assert_eq!(done.len(), 1);
+        assert_eq!(done[0].state, ComboState::Filled);
+        assert!(done[0].unwind_started.is_none());
+    }
+
+    #[test]
+    fn fills_go_to_the_order_that_filled() {
+        let mut bn = MockVenue::<BinanceMarketOrder>::new("binance");
+        let mut lx = MockVenue::<Order>::new("ledgerx");
+        let mut mngr = LeggingManager::new(cfg());
+        let t0 = Instant::now();
+
+        // two combos working the same contracts and pair
+        for _ in 0..2 {
+            let t = reversal();
+            let report = route_trade(&t, &mut bn, &mut lx);
+            mngr.track(t, report, t0);
+        }
+
+        assert!(mngr.on_ledgerx_fill("ledgerx-2", 50));
+        assert!(mngr.on_binance_fill("binance-1", 0.5));
+        assert_eq!(mngr.active[0].opts[0].filled_size, 0);
+        assert_eq!(mngr.active[0].spot[0].filled_qty, 0.0);
+        assert_eq!(mngr.active[1].opts[0].filled_size, 50);
+        assert_eq!(mngr.active[1].spot[0].filled_qty, 0.5);
+
+        // nobody's order
+        assert!(!mngr.on_ledgerx_fill("ledgerx-9", 50));
+    }
+
+    #[test]
+    fn spot_flattened_on_timeout() {
+        let mut bn = MockVenue::<BinanceMarketOrder>::new("binance");
+        let mut lx = MockVenue::<Order>::new("ledgerx");
+        let mut mngr = LeggingManager::new(cfg());
+        let t0 = Instant::now();
+
+        let t = reversal();
+        let report = route_trade(&t, &mut bn, &mut lx);
+        mngr.track(t, report, t0);
+
+        // spot and half the call fill, the put never does
+        mngr.on_binance_fill("binance-0", 0.5);
+        mngr.on_ledgerx_fill("ledgerx-0", 25);
+        assert!(mngr.active[0].is_one_sided());
+        assert!(mngr.poll(t0 + Duration::from_millis(100), &mut bn, &mut lx).is_empty());
+
+        // both option legs were still working and pulled before the unwind went out
+        assert!(mngr.poll(t0 + Duration::from_millis(600), &mut bn, &mut lx).is_empty());
+        assert_eq!(lx.cancelled, vec!["ledgerx-0", "ledgerx-1"]);
+        let exec = &mngr.active[0];
+        assert_eq!(exec.state, ComboState::Unwinding);
+        assert_eq!(exec.unwind_spot.len(), 1);
+        assert!(exec.unwind_spot[0].order.is_buy);
+        assert!((exec.unwind_spot[0].order.qty - 0.5).abs() < 1e-9);
+        assert_eq!(exec.unwind_opts.len(), 1);
+        assert!(exec.unwind_opts[0].order.is_ask);
+        assert_eq!(exec.unwind_opts[0].order.size, 25);
+
+        // hedged only once the unwind has actually filled
+        assert!(mngr.on_binance_fill("binance-1", 0.5));
+        assert!(mngr.on_ledgerx_fill("ledgerx-2", 25));
+        let done = mngr.poll(t0 + Duration::from_millis(700), &mut bn, &mut lx);
+        assert_eq!(done.len(), 1);
+        assert_eq!(done[0].state, ComboState::Hedged);
+    }
+
+    #[test]
+    fn unfilled_unwind_is_pulled_and_left_open() {
+        let mut bn = MockVenue::<BinanceMarketOrder>::new("binance");
+        let mut lx = MockVenue::<Order>::new("ledgerx");
+        let mut mngr = LeggingManager::new(cfg());
+        let t0 = Instant::now();
+
+        let t = reversal();
+        let report = route_trade(&t, &mut bn, &mut lx);
+        mngr.track(t, report, t0);
+        mngr.on_binance_fill("binance-0", 0.5);
+        mngr.on_ledgerx_fill("ledgerx-0", 50);
+        assert!(mngr.poll(t0 + Duration::from_millis(600), &mut bn, &mut lx).is_empty());
+
+        // the spot unwind fills, the call sale rests and never does
+        mngr.on_binance_fill("binance-1", 0.5);
+        assert!(mngr.poll(t0 + Duration::from_millis(1000), &mut bn, &mut lx).is_empty());
+        let done = mngr.poll(t0 + Duration::from_millis(1100), &mut bn, &mut lx);
+        assert_eq!(done.len(), 1);
+        assert_eq!(done[0].state, ComboState::HedgeFailed);
+        assert_eq!(done[0].unwind_opts[0].filled_size, 0);
+        assert_eq!(lx.cancelled, vec!["ledgerx-1", "ledgerx-2"]);
+
+        // if it fills racing the cancel, that only takes exposure off: booked, not flattened
+        let fill = mngr.on_late_fill("ledgerx-2", 50, t0 + Duration::from_millis(1200), &mut bn, &mut lx).unwrap();
+        assert!(fill.is_ask);
+        assert_eq!(fill.size, 50);
+        assert!(mngr.active.is_empty());
+        assert_eq!(lx.sent.len(), 3);
+    }
+
+    #[test]
+    fn late_fill_after_timeout_is_flattened() {
+        let mut bn = MockVenue::<BinanceMarketOrder>::new("binance");
+        let mut lx = MockVenue::<Order>::new("ledgerx");
+        let mut mngr = LeggingManager::new(cfg());
+        let t0 = Instant::now();
+
+        let t = reversal();
+        let report = route_trade(&t, &mut bn, &mut lx);
+        mngr.track(t, report, t0);
+        mngr.on_binance_fill("binance-0", 0.5);
+        mngr.on_ledgerx_fill("ledgerx-0", 50);
+
+        let t1 = t0 + Duration::from_millis(600);
+        assert!(mngr.poll(t1, &mut bn, &mut lx).is_empty());
+        assert_eq!(lx.cancelled, vec!["ledgerx-1"]);
+
+        // the put fills anyway, racing the cancel; the combo's own unwind didn't cover it
+        assert!(!mngr.on_ledgerx_fill("ledgerx-1", 30));
+        let fill = mngr.on_late_fill("ledgerx-1", 30, t1, &mut bn, &mut lx).unwrap();
+        assert!(fill.is_ask);
+        assert_eq!(fill.size, 30);
+        let hedge = mngr.active.last().unwrap();
+        assert_eq!(hedge.state, ComboState::Unwinding);
+        assert!(!hedge.unwind_opts[0].order.is_ask);
+        assert_eq!(hedge.unwind_opts[0].order.size, 30);
+        assert!((hedge.unwind_opts[0].order.price - 525.0).abs() < 1e-9);
+
+        // more than what was left on the leg is only flattened up to its size
+        assert_eq!(mngr.on_late_fill("ledgerx-1", 50, t1, &mut bn, &mut lx).unwrap().size, 20);
+        assert!(mngr.on_late_fill("ledgerx-1", 1, t1, &mut bn, &mut lx).is_none());
+        assert_eq!(mngr.active.len(), 3);
+
+        // and each of those hedges is followed until it fills
+        for order_id in ["binance-1", "ledgerx-2", "ledgerx-3", "ledgerx-4"] {
+            assert!(mngr.on_binance_fill(order_id, 0.5) || mngr.on_ledgerx_fill(order_id, 50));
+        }
+        let done = mngr.poll(t1 + Duration::from_millis(100), &mut bn, &mut lx);
+        assert_eq!(done.len(), 3);
+        assert!(done.iter().all(|exec| exec.state == ComboState::Hedged));
+    }
+
+    #[test]
+    fn rejected_leg_unwinds_immediately() {
+        let mut bn = MockVenue::<BinanceMarketOrder>::new("binance");
+        let mut lx = MockVenue::<Order>::rejecting("ledgerx", vec![1]);
+        let mut mngr = LeggingManager::new(cfg());
+        let t0 = Instant::now();
+
+        let t = reversal();
+        let report = route_trade(&t, &mut bn, &mut lx);
+        mngr.track(t, report, t0);
+        mngr.on_binance_fill("binance-0", 0.5);
+
+        assert!(mngr.poll(t0, &mut bn, &mut lx).is_empty());
+        assert_eq!(bn.sent.len(), 2);
+        mngr.on_binance_fill("binance-1", 0.5);
+        let done = mngr.poll(t0, &mut bn, &mut lx);
+        assert_eq!(done.len(), 1);
+        assert_eq!(done[0].state, ComboState::Hedged);
+    }
+}
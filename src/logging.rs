@@ -0,0 +1,127 @@
+use serde::Deserialize;
+use tracing::Subscriber;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::EnvFilter;
+
+// overrides `logging.filter` when set, e.g. RUST_LOG=info,combo_trading::strat=debug
+pub const ENV_LOG_FILTER: &str = "RUST_LOG";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Text,
+    // one JSON object per event, fields included
+    Json,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LoggingConfig {
+    // a level, optionally followed by per-module overrides: "warn,combo_trading::strat=info"
+    pub filter: String,
+    pub format: LogFormat,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self { filter: "info".to_owned(), format: LogFormat::Text }
+    }
+}
+
+impl LoggingConfig {
+    // the configured filter only; a bad $RUST_LOG is reported by `init`
+    pub fn check(&self) -> Result<(), String> {
+        EnvFilter::try_new(&self.filter).map(|_| ()).map_err(|e| e.to_string())
+    }
+
+    fn env_filter(&self) -> Result<EnvFilter, String> {
+        let directives = std::env::var(ENV_LOG_FILTER).unwrap_or_else(|_| self.filter.clone());
+        EnvFilter::try_new(&directives).map_err(|e| format!("bad log filter {:?}: {}", directives, e))
+    }
+}
+
+pub fn subscriber<W>(cfg: &LoggingConfig, writer: W) -> Result<Box<dyn Subscriber + Send + Sync>, String>
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(cfg.env_filter()?)
+        .with_writer(writer);
+    Ok(match cfg.format {
+        LogFormat::Text => Box::new(builder.finish()),
+        LogFormat::Json => Box::new(builder.json().flatten_event(true).finish()),
+    })
+}
+
+// everything is logged to stderr; stdout is left to whatever a command prints as output
+pub fn init(cfg: &LoggingConfig) -> Result<(), String> {
+    tracing::subscriber::set_global_default(subscriber(cfg, std::io::stderr)?)
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::io;
+    use std::sync::{Arc, Mutex};
+
+    use tracing::{debug, info};
+    use tracing_subscriber::fmt::MakeWriter;
+
+    use super::{subscriber, LogFormat, LoggingConfig};
+
+    // collects whatever is logged, for tests that check log output
+    #[derive(Clone, Default)]
+    pub struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl Captured {
+        pub fn lines(&self) -> Vec<serde_json::Value> {
+            let buf = self.0.lock().unwrap();
+            String::from_utf8_lossy(&buf).lines().map(|l| serde_json::from_str(l).unwrap()).collect()
+        }
+    }
+
+    impl io::Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl<'w> MakeWriter<'w> for Captured {
+        type Writer = Captured;
+        fn make_writer(&'w self) -> Self::Writer {
+            self.clone()
+        }
+    }
+
+    pub fn capture_json(filter: &str) -> (Captured, Box<dyn tracing::Subscriber + Send + Sync>) {
+        let captured = Captured::default();
+        let cfg = LoggingConfig { filter: filter.to_owned(), format: LogFormat::Json };
+        let sub = subscriber(&cfg, captured.clone()).unwrap();
+        (captured, sub)
+    }
+
+    #[test]
+    fn json_events_filtered_per_module() {
+        let (captured, sub) = capture_json("warn,combo_trading::logging=info");
+        tracing::subscriber::with_default(sub, || {
+            info!(strike = 20000u64, edge = 0.01, "kept");
+            debug!("dropped");
+        });
+
+        let lines = captured.lines();
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0]["level"], "INFO");
+        assert_eq!(lines[0]["message"], "kept");
+        assert_eq!(lines[0]["strike"], 20000);
+        assert_eq!(lines[0]["target"], "combo_trading::logging::tests");
+    }
+
+    #[test]
+    fn bad_filter_rejected() {
+        let cfg = LoggingConfig { filter: "combo_trading=loud".to_owned(), format: LogFormat::Text };
+        assert!(subscriber(&cfg, io::sink).is_err());
+    }
+}
@@ -1,123 +1,462 @@
-use std::str::FromStr;
+use std::collections::HashSet;
+use std::net::TcpListener;
 use std::sync::Arc;
 use std::sync::atomic::AtomicBool;
-use std::sync::mpsc::{channel, Sender, SendError};
+use std::sync::mpsc::{channel, Sender, SendError, RecvTimeoutError};
 use std::sync::atomic::Ordering;
 use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use chrono::{offset::Utc, DateTime};
+use tracing::{error, info, warn};
 
 use binance::errors::{ErrorKind, Error};
-use ftx_us_derivs::error::WebSocketError;
 use ftx_us_derivs::ws::WebSocketMsg;
 use ftx_us_derivs::ws::WebSocketClient;
-use ftx_us_derivs::order::OrderMngr;
+use ftx_us_derivs::order::{Order, OrderMngr};
 
 use binance::websockets::{WebsocketEvent, WebSockets};
+use binance::futures::websockets::{FuturesMarket, FuturesWebSockets, FuturesWebsocketEvent};
 use binance::api::Binance;
 use binance::account::Account;
-use strat::{Trade, ComboStratConfig};
+use binance::market::Market;
+use strat::{Trade, BinanceMarketOrder};
+use execution::{ExecutionVenue, BinanceVenue, LedgerXVenue, TradeReport, TradeStatus, route_trade};
+use legging::{LeggingManager, ComboState};
+use config::{Config, config_path_from_args};
+use symbols::SymbolMapping;
+use dispatch::StratDispatcher;
+use risk::RiskGate;
+use feeds::{detached_readers, Backoff, DetachedReader, FeedConfig, FeedMonitor, FeedVenue, MAX_DETACHED_READERS};
+use recorder::Recorder;
+use replay::replay_args_from;
+use book::{BookUpdate, SpotDepthSnapshot};
+use book_state::BookStateClient;
+use margin::MarginRateClient;
+use latency::{LatencyStats, Stage};
+use metrics::Metrics;
+use carry::{FundingKind, FundingUpdate};
 
 
 
 
 pub mod options_chain;
 pub mod strat;
-
-const LEDGERX_BASE_URL: &str = "";
-const LEDGERX_WSS_URL: &str = "wss://api.ledgerx.com/ws";
-const LEDGERX_API_KEY: &str = "";
-
-const BINANCE_API_KEY: &str = "";
-const BINANCE_API_SECRET: &str = "";
+pub mod execution;
+pub mod legging;
+pub mod config;
+pub mod symbols;
+pub mod dispatch;
+pub mod positions;
+pub mod risk;
+pub mod feeds;
+pub mod recorder;
+pub mod replay;
+pub mod fillsim;
+pub mod book;
+pub mod thresholds;
+pub mod latency;
+pub mod metrics;
+pub mod logging;
+pub mod parity;
+pub mod carry;
+pub mod book_state;
+pub mod margin;
+
+const EVENT_LOOP_TICK: Duration = Duration::from_millis(100);
+// how often positions and feed health are copied out for the metrics endpoint
+const METRICS_REFRESH: Duration = Duration::from_secs(1);
+// levels fetched for a Binance depth snapshot
+const BINANCE_DEPTH_LIMIT: u64 = 1000;
+// LedgerX action report status for a trade against one of our resting orders
+const LX_STATUS_FILLED: u64 = 201;
 
 #[derive(Debug)]
 pub enum UniversalMsgWrapper {
     Binance(WebsocketEvent),
+    // a REST depth snapshot, fetched when the diff-depth stream (re)connects
+    BinanceDepth(SpotDepthSnapshot),
     LedgerX(WebSocketMsg),
+    // depth for one contract, from the book-state poller when `feeds.ledgerx_depth` is on
+    LedgerXBook(BookUpdate),
+    // the feed dropped and is reconnecting; anything it told us may be stale
+    FeedReset(FeedVenue),
+    // a Binance funding or margin interest rate, for the carry models
+    Funding(FundingUpdate),
+}
+
+impl UniversalMsgWrapper {
+    pub fn venue(&self) -> FeedVenue {
+        match self {
+            UniversalMsgWrapper::Binance(_) | UniversalMsgWrapper::BinanceDepth(_) | UniversalMsgWrapper::Funding(_) => FeedVenue::Binance,
+            UniversalMsgWrapper::LedgerX(_) | UniversalMsgWrapper::LedgerXBook(_) => FeedVenue::LedgerX,
+            UniversalMsgWrapper::FeedReset(venue) => *venue,
+        }
+    }
+}
+
+// a feed message and when its generator thread received it
+#[derive(Debug)]
+pub struct StampedMsg {
+    pub recv_time: DateTime<Utc>,
+    pub msg: UniversalMsgWrapper,
+}
+
+impl StampedMsg {
+    pub fn now(msg: UniversalMsgWrapper) -> Self {
+        Self { recv_time: Utc::now(), msg }
+    }
 }
 
 #[derive(Debug)]
 pub enum UniversalErrorWrapper {
-    ChannelError(SendError<UniversalMsgWrapper>),
-    LedgerXWS(WebSocketError),
-    BinanceError(Error),
+    ChannelError(SendError<StampedMsg>),
 }
 
+// counted on the way in, so the metrics can tell how far behind the event loop is
+fn send_msg(tx: &Sender<StampedMsg>, metrics: &Metrics, msg: UniversalMsgWrapper) -> Result<(), UniversalErrorWrapper> {
+    metrics.msgs_received.venue(msg.venue()).inc();
+    tx.send(StampedMsg::now(msg)).map_err(UniversalErrorWrapper::ChannelError)
+}
 
-fn ledgerx_msg_generator(tx: Sender<UniversalMsgWrapper>, run_flag: Arc<AtomicBool>) -> Result<(), UniversalErrorWrapper> {
-    println!("Starting LedgerX Message Generator...");
 
-    let mut client = WebSocketClient::connect(LEDGERX_WSS_URL)
-        .map_err(|x|{UniversalErrorWrapper::LedgerXWS(x)})?;
+fn ledgerx_msg_generator(
+    tx: Sender<StampedMsg>,
+    run_flag: Arc<AtomicBool>,
+    alive: Arc<AtomicBool>,
+    wss_url: String,
+    contract_ids: HashSet<u64>,
+    feed_cfg: FeedConfig,
+    metrics: Arc<Metrics>,
+) -> Result<(), UniversalErrorWrapper> {
+    info!("starting LedgerX message generator");
+    let mut backoff = Backoff::new(&feed_cfg);
 
     while run_flag.load(Ordering::Relaxed) {
-        match client.yield_msg() {
-            Ok(msg) => {
-                tx.send(UniversalMsgWrapper::LedgerX(msg))
-                    .map_err(|x| { UniversalErrorWrapper::ChannelError(x) })?;
-            },
+        // every reader abandoned on a dead socket still holds it open; don't pile up more
+        if detached_readers() >= MAX_DETACHED_READERS {
+            warn!(readers = detached_readers(), "too many LedgerX reads blocked on dead sockets, holding off reconnecting");
+            backoff.wait(&run_flag);
+            continue;
+        }
+        let mut client = match WebSocketClient::connect(&wss_url) {
+            Ok(client) => client,
             Err(err) => {
-                eprintln!("Error Encountered in LedgerX Message Parsing: {:?}", err);
+                warn!(error = ?err, "failed to connect to LedgerX");
+                backoff.wait(&run_flag);
+                continue;
             },
+        };
+        info!("connected to LedgerX");
+        alive.store(true, Ordering::Relaxed);
+
+        // a socket that dies silently blocks yield_msg for good, so read on a thread we can
+        // abandon: once the event loop calls the feed stale we stop waiting and reconnect
+        let reader = DetachedReader::spawn(move || client.yield_msg());
+        let mut consecutive_errors = 0;
+        while let Some(res) = reader.next(&run_flag, &alive) {
+            match res {
+                Ok(msg) => {
+                    consecutive_errors = 0;
+                    backoff.reset();
+                    // top-of-book for underlyings we don't trade never needs to hit the channel
+                    if let WebSocketMsg::BookTop(bt) = &msg {
+                        if !contract_ids.contains(&bt.contract_id) {
+                            continue;
+                        }
+                    }
+                    send_msg(&tx, &metrics, UniversalMsgWrapper::LedgerX(msg))?;
+                },
+                Err(err) => {
+                    warn!(error = ?err, "failed to parse LedgerX message");
+                    metrics.ledgerx_parse_errors.inc();
+                    consecutive_errors += 1;
+                    if consecutive_errors >= feed_cfg.max_consecutive_errors {
+                        warn!(errors = consecutive_errors, "too many LedgerX errors in a row, reconnecting");
+                        break;
+                    }
+                },
+            }
         }
-    }    
 
-    println!("Killing LedgerX Message Generator...");
+        send_msg(&tx, &metrics, UniversalMsgWrapper::FeedReset(FeedVenue::LedgerX))?;
+        backoff.wait(&run_flag);
+    }
+
+    info!("stopping LedgerX message generator");
     Ok(())
 }
 
-fn binance_msg_generator(tx: Sender<UniversalMsgWrapper>, run_flag: Arc<AtomicBool>) -> Result<(), UniversalErrorWrapper> {
-    println!("Starting Binance Message Generator...");
+fn binance_msg_generator(
+    tx: Sender<StampedMsg>,
+    run_flag: Arc<AtomicBool>,
+    alive: Arc<AtomicBool>,
+    symbols: Vec<SymbolMapping>,
+    feed_cfg: FeedConfig,
+    metrics: Arc<Metrics>,
+) -> Result<(), UniversalErrorWrapper> {
+    info!("starting Binance message generator");
+    let mut backoff = Backoff::new(&feed_cfg);
+    let mut streams: Vec<String> = symbols.iter().map(|s| s.binance_stream("bookTicker")).collect();
+    if feed_cfg.binance_depth {
+        streams.extend(symbols.iter().map(|s| s.binance_stream("depth@100ms")));
+    }
+    let market: Market = Binance::new(None, None);
 
-    let mut client = WebSockets::new(move|msg| {
-        tx.send(UniversalMsgWrapper::Binance(msg))
-            .map_err(|x| Error::from_kind(ErrorKind::Msg(x.to_string())))
+    while run_flag.load(Ordering::Relaxed) {
+        let cb_tx = tx.clone();
+        let cb_metrics = metrics.clone();
+        let mut client = WebSockets::new(move|msg| {
+            send_msg(&cb_tx, &cb_metrics, UniversalMsgWrapper::Binance(msg))
+                .map_err(|x| Error::from_kind(ErrorKind::Msg(format!("{:?}", x))))
+            }
+        );
+
+        if let Err(err) = client.connect_multiple_streams(&streams) {
+            warn!(error = ?err, "failed to connect to Binance");
+            backoff.wait(&run_flag);
+            continue;
+        }
+        info!("connected to Binance");
+        alive.store(true, Ordering::Relaxed);
+        backoff.reset();
+
+        // diffs queue up on the socket while the snapshots are fetched; a symbol whose
+        // snapshot fails is sized off bookTicker until the next reconnect
+        if feed_cfg.binance_depth {
+            for s in symbols.iter() {
+                match market.get_custom_depth(s.binance.as_str(), BINANCE_DEPTH_LIMIT) {
+                    Ok(book) => {
+                        let snapshot = SpotDepthSnapshot::from_rest(&s.binance, book);
+                        send_msg(&tx, &metrics, UniversalMsgWrapper::BinanceDepth(snapshot))?;
+                    },
+                    Err(err) => warn!(symbol = %s.binance, error = ?err, "failed to fetch depth snapshot"),
+                }
+            }
         }
-    );
-
 
-    client.connect("btcusdt@bookTicker").unwrap();
+        // returns once `alive` is cleared (shutdown or watchdog) or the socket errors out
+        if let Err(err) = client.event_loop(&alive) {
+            warn!(error = ?err, "Binance event loop failed");
+        }
+        let _ = client.disconnect();
 
-    client.event_loop(&*run_flag)
-        .map_err(|x| UniversalErrorWrapper::BinanceError(x))?;
+        send_msg(&tx, &metrics, UniversalMsgWrapper::FeedReset(FeedVenue::Binance))?;
+        backoff.wait(&run_flag);
+    }
     
-    println!("Stopping Binance Message Generator...");
+    info!("stopping Binance message generator");
+    Ok(())
+}
+
+// perpetual funding for the carry models that price with it; margin interest isn't streamed
+// and is polled by margin_msg_generator. A dropped socket is just reconnected: the models
+// keep the last rates they saw.
+fn funding_msg_generator(
+    tx: Sender<StampedMsg>,
+    run_flag: Arc<AtomicBool>,
+    alive: Arc<AtomicBool>,
+    streams: Vec<String>,
+    feed_cfg: FeedConfig,
+    metrics: Arc<Metrics>,
+) -> Result<(), UniversalErrorWrapper> {
+    info!("starting Binance funding generator");
+    let mut backoff = Backoff::new(&feed_cfg);
+
+    while run_flag.load(Ordering::Relaxed) {
+        let cb_tx = tx.clone();
+        let cb_metrics = metrics.clone();
+        let mut client = FuturesWebSockets::new(move|event| {
+            if let FuturesWebsocketEvent::MarkPrice(mark) = event {
+                if let Ok(rate) = mark.funding_rate.parse::<f64>() {
+                    let update = FundingUpdate { kind: FundingKind::Perpetual, symbol: mark.symbol, rate };
+                    send_msg(&cb_tx, &cb_metrics, UniversalMsgWrapper::Funding(update))
+                        .map_err(|x| Error::from_kind(ErrorKind::Msg(format!("{:?}", x))))?;
+                }
+            }
+            Ok(())
+        });
+
+        if let Err(err) = client.connect_multiple_streams(&FuturesMarket::USDM, &streams) {
+            warn!(error = ?err, "failed to connect to Binance futures");
+            backoff.wait(&run_flag);
+            continue;
+        }
+        info!("connected to Binance futures");
+        backoff.reset();
+
+        if let Err(err) = client.event_loop(&alive) {
+            warn!(error = ?err, "Binance futures event loop failed");
+        }
+        let _ = client.disconnect();
+        backoff.wait(&run_flag);
+    }
+
+    info!("stopping Binance funding generator");
+    Ok(())
+}
+
+// full-depth snapshots of every contract we trade, a pass every `ledgerx_depth_poll_ms`.
+// The websocket only has top of book, which keeps the best level current in between;
+// a contract whose request fails keeps the depth it had until the next pass.
+fn ledgerx_depth_generator(
+    tx: Sender<StampedMsg>,
+    run_flag: Arc<AtomicBool>,
+    client: BookStateClient,
+    contract_ids: HashSet<u64>,
+    feed_cfg: FeedConfig,
+    metrics: Arc<Metrics>,
+) -> Result<(), UniversalErrorWrapper> {
+    info!("starting LedgerX depth generator");
+    let interval = Duration::from_millis(feed_cfg.ledgerx_depth_poll_ms);
+
+    while run_flag.load(Ordering::Relaxed) {
+        let next_pass = Instant::now() + interval;
+        for &contract_id in contract_ids.iter() {
+            if !run_flag.load(Ordering::Relaxed) {
+                break;
+            }
+            match client.snapshot(contract_id) {
+                Ok(snapshot) => send_msg(&tx, &metrics, UniversalMsgWrapper::LedgerXBook(snapshot))?,
+                Err(err) => warn!(contract_id, error = %err, "failed to fetch LedgerX book state"),
+            }
+        }
+        while run_flag.load(Ordering::Relaxed) && Instant::now() < next_pass {
+            std::thread::sleep(Duration::from_millis(50).min(next_pass - Instant::now()));
+        }
+    }
+
+    info!("stopping LedgerX depth generator");
+    Ok(())
+}
+
+// margin interest on every asset a funding carry model borrows, each `margin_interest_poll_ms`.
+// A failed request leaves the models on the last rate they saw.
+fn margin_msg_generator(
+    tx: Sender<StampedMsg>,
+    run_flag: Arc<AtomicBool>,
+    client: MarginRateClient,
+    assets: Vec<String>,
+    feed_cfg: FeedConfig,
+    metrics: Arc<Metrics>,
+) -> Result<(), UniversalErrorWrapper> {
+    info!("starting Binance margin interest generator");
+    let interval = Duration::from_millis(feed_cfg.margin_interest_poll_ms);
+
+    while run_flag.load(Ordering::Relaxed) {
+        let next_pass = Instant::now() + interval;
+        for asset in assets.iter() {
+            match client.daily_rate(asset) {
+                Ok(rate) => {
+                    let update = FundingUpdate { kind: FundingKind::MarginInterest, symbol: asset.to_owned(), rate };
+                    send_msg(&tx, &metrics, UniversalMsgWrapper::Funding(update))?;
+                },
+                Err(err) => warn!(%asset, error = %err, "failed to fetch margin interest"),
+            }
+        }
+        while run_flag.load(Ordering::Relaxed) && Instant::now() < next_pass {
+            std::thread::sleep(Duration::from_millis(50).min(next_pass - Instant::now()));
+        }
+    }
+
+    info!("stopping Binance margin interest generator");
     Ok(())
 }
 
+type FeedHandle = JoinHandle<Result<(), UniversalErrorWrapper>>;
+
+// the LedgerX and Binance feeds, then whichever optional pollers are configured: funding
+// and margin interest if some strategy's carry model wants them, depth with `feeds.ledgerx_depth`
 fn start_msg_channels(
-    tx: &Sender<UniversalMsgWrapper>, 
-    run_flag: &Arc<AtomicBool>
-) -> (JoinHandle<Result<(), UniversalErrorWrapper>>, JoinHandle<Result<(), UniversalErrorWrapper>>) {
+    tx: &Sender<StampedMsg>, 
+    run_flag: &Arc<AtomicBool>,
+    feeds_alive: (&Arc<AtomicBool>, &Arc<AtomicBool>, &Arc<AtomicBool>),
+    cfg: &Config,
+    contract_ids: HashSet<u64>,
+    metrics: &Arc<Metrics>,
+) -> (FeedHandle, FeedHandle, Vec<FeedHandle>) {
     let lx_tx = tx.clone();
     let lx_flg = run_flag.clone();
+    let lx_alive = feeds_alive.0.clone();
+    // action reports for our own orders only come down an authenticated socket
+    let lx_url = format!("{}?token={}", cfg.ledgerx.wss_url, cfg.ledgerx.api_key);
+    let lx_cfg = cfg.feeds.clone();
+    let lx_metrics = metrics.clone();
+    let lx_ids = contract_ids.clone();
     let thread_handler_ledgerx = std::thread::spawn(move|| {
-        ledgerx_msg_generator(lx_tx, lx_flg)
+        ledgerx_msg_generator(lx_tx, lx_flg, lx_alive, lx_url, lx_ids, lx_cfg, lx_metrics)
+    });
+
+    let thread_handler_depth = cfg.feeds.ledgerx_depth.then(|| {
+        let dp_tx = tx.clone();
+        let dp_flg = run_flag.clone();
+        let dp_client = BookStateClient::new(&cfg.ledgerx.base_url, &cfg.ledgerx.api_key);
+        let dp_cfg = cfg.feeds.clone();
+        let dp_metrics = metrics.clone();
+        std::thread::spawn(move|| {
+            ledgerx_depth_generator(dp_tx, dp_flg, dp_client, contract_ids, dp_cfg, dp_metrics)
+        })
     });
 
     let bn_tx = tx.clone();
     let bn_flg = run_flag.clone();
+    let bn_alive = feeds_alive.1.clone();
+    let bn_symbols = cfg.strat.iter().map(|s| s.symbols.clone()).collect();
+    let bn_cfg = cfg.feeds.clone();
+    let bn_metrics = metrics.clone();
     let thread_handler_binance = std::thread::spawn(move|| {
-        binance_msg_generator(bn_tx, bn_flg)
+        binance_msg_generator(bn_tx, bn_flg, bn_alive, bn_symbols, bn_cfg, bn_metrics)
+    });
+
+    let fd_streams: Vec<String> = cfg.strat.iter().filter_map(|s| s.carry.funding_stream(&s.symbols)).collect();
+    let thread_handler_funding = (!fd_streams.is_empty()).then(|| {
+        let fd_tx = tx.clone();
+        let fd_flg = run_flag.clone();
+        let fd_alive = feeds_alive.2.clone();
+        let fd_cfg = cfg.feeds.clone();
+        let fd_metrics = metrics.clone();
+        std::thread::spawn(move|| {
+            funding_msg_generator(fd_tx, fd_flg, fd_alive, fd_streams, fd_cfg, fd_metrics)
+        })
     });
 
     
+    // the feeds block on their sockets, so clearing `alive` too gets them out promptly
     let run_flag_handle = run_flag.clone();
+    let alive_handles = (feeds_alive.0.clone(), feeds_alive.1.clone(), feeds_alive.2.clone());
     ctrlc::set_handler(move || {
         run_flag_handle.store(false, Ordering::Relaxed);
+        alive_handles.0.store(false, Ordering::Relaxed);
+        alive_handles.1.store(false, Ordering::Relaxed);
+        alive_handles.2.store(false, Ordering::Relaxed);
     }).expect("Error setting Ctrl-C handler");
 
-    return (thread_handler_ledgerx, thread_handler_binance);
+    let mut margin_assets: Vec<String> = cfg.strat.iter().flat_map(|s| s.carry.margin_assets()).collect();
+    margin_assets.sort();
+    margin_assets.dedup();
+    let thread_handler_margin = (!margin_assets.is_empty()).then(|| {
+        let mg_tx = tx.clone();
+        let mg_flg = run_flag.clone();
+        let mg_client = MarginRateClient::new(&cfg.binance.api_key, &cfg.binance.api_secret);
+        let mg_cfg = cfg.feeds.clone();
+        let mg_metrics = metrics.clone();
+        std::thread::spawn(move|| {
+            margin_msg_generator(mg_tx, mg_flg, mg_client, margin_assets, mg_cfg, mg_metrics)
+        })
+    });
+
+    let pollers = [thread_handler_funding, thread_handler_depth, thread_handler_margin].into_iter().flatten().collect();
+    return (thread_handler_ledgerx, thread_handler_binance, pollers);
 }
 
-fn do_trade(t: Trade) -> bool {
+fn do_trade<B, L>(t: &Trade, binance_venue: &mut B, ledgerx_venue: &mut L) -> TradeReport
+where
+    B: ExecutionVenue<Order = BinanceMarketOrder>,
+    L: ExecutionVenue<Order = Order>,
+{
     let mut msg = String::new();
 
-    let binance_res: Vec<_> = t.binance.into_iter().map(|binance_order| {
-        // if binance_order.is_buy {
-        //     binance_om.market_buy(binance_order.symbol, binance_order.qty)
-        // } else {
-        //     binance_om.market_sell(binance_order.symbol, binance_order.qty)
-        // }
+    for binance_order in t.binance.iter() {
         msg.push_str(format!(
             "{} {}: {}x{};",
             binance_order.symbol,
@@ -125,10 +464,9 @@ fn do_trade(t: Trade) -> bool {
             binance_order.price,
             binance_order.qty,
         ).as_str());
-    }).collect();
+    }
 
-    let ledgerx_res: Vec<_> = t.ledgerx.into_iter().map(|ledgerx_order| {
-        //ledgerx_om.send_order(&ledgerx_order)
+    for ledgerx_order in t.ledgerx.iter() {
         msg.push_str(&format!(
             "id_{} {}: {}x{};",
             ledgerx_order.contract_id, // can call or put be inferred from contract id?
@@ -136,54 +474,250 @@ fn do_trade(t: Trade) -> bool {
             ledgerx_order.price,
             ledgerx_order.size,
         ))
-    }).collect();
+    }
+
+    info!(orders = %msg, "sending trade");
 
-    println!("{msg}");
+    let report = route_trade(t, binance_venue, ledgerx_venue);
+    match report.status() {
+        TradeStatus::Complete => {},
+        TradeStatus::Partial => warn!(report = ?report, "partially executed trade"),
+        TradeStatus::Rejected => warn!(report = ?report, "rejected trade"),
+    }
 
-    true
+    report
 }
 
 fn main() {
+    let mut args = std::env::args().skip(1).peekable();
+    // `combo_trading replay ...` backtests over recorded market data instead of trading
+    if args.peek().map(|a| a.as_str()) == Some("replay") {
+        args.next();
+        let replay_args = match replay_args_from(args) {
+            Ok(replay_args) => replay_args,
+            Err(e) => {
+                eprintln!("{e}\nusage: combo_trading replay [--config <path>] [--trades <out.jsonl>] <log>...");
+                std::process::exit(2);
+            },
+        };
+        match replay::run(replay_args) {
+            Ok(summary) => println!("{summary}"),
+            Err(e) => {
+                eprintln!("{e}");
+                std::process::exit(1);
+            },
+        }
+        return;
+    }
+
+    let cfg_path = match config_path_from_args(args) {
+        Ok(path) => path,
+        Err(e) => {
+            eprintln!("{e}\nusage: combo_trading [--config <path.toml|path.json>]");
+            std::process::exit(2);
+        },
+    };
+    let cfg = match Config::load(&cfg_path) {
+        Ok(cfg) => cfg,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        },
+    };
+    if let Err(e) = logging::init(&cfg.logging) {
+        eprintln!("{e}");
+        std::process::exit(1);
+    }
 
     // connections to the exchanges
-    let mut ledgerx_om = OrderMngr::new(LEDGERX_BASE_URL, LEDGERX_API_KEY);
-    let binance_om: Account = Binance::new(Some(BINANCE_API_KEY.to_string()), Some(BINANCE_API_SECRET.to_string()));
+    let ledgerx_om = OrderMngr::new(&cfg.ledgerx.base_url, &cfg.ledgerx.api_key);
+    let binance_om: Account = Binance::new(Some(cfg.binance.api_key.clone()), Some(cfg.binance.api_secret.clone()));
+    let mut ledgerx_venue = LedgerXVenue::new(ledgerx_om);
+    let mut binance_venue = BinanceVenue::new(binance_om);
     
     // strategy configuration and startup
-    let strat_config = ComboStratConfig {
-        symbol: String::from_str("BTCUSDT").unwrap(),
-        ann_borrow_rate: 0.03,
-        opts_tc: 10e-4,
-        spot_tc: 10e-4,
-    };
-    let mut strat = strat::ComboStrat::startup(strat_config);
+    let mut legging = LeggingManager::new(cfg.legging_config());
+    let mut risk = RiskGate::new(cfg.risk.clone());
+    let mut dispatch = StratDispatcher::startup(cfg.strat.clone());
 
     // interprocess/thread communication
-    let (tx, rx) = channel::<UniversalMsgWrapper>();
+    let (tx, rx) = channel::<StampedMsg>();
     let run_flag = Arc::new(AtomicBool::new(true));
-    let (lx_handle, bn_handle) = start_msg_channels(&tx, &run_flag);
+    let lx_alive = Arc::new(AtomicBool::new(true));
+    let bn_alive = Arc::new(AtomicBool::new(true));
+    let fd_alive = Arc::new(AtomicBool::new(true));
+    let metrics = Arc::new(Metrics::default());
+    let (lx_handle, bn_handle, poller_handles) = start_msg_channels(&tx, &run_flag, (&lx_alive, &bn_alive, &fd_alive), &cfg, dispatch.contract_ids().collect(), &metrics);
+    let metrics_handle = cfg.metrics.listen.as_ref().map(|addr| {
+        let served = TcpListener::bind(addr).and_then(|listener| metrics::serve(listener, metrics.clone(), run_flag.clone()));
+        served.unwrap_or_else(|e| {
+            error!(%addr, error = %e, "failed to serve metrics");
+            std::process::exit(1);
+        })
+    });
+    let mut metrics_refreshed = Instant::now();
+    let mut monitor = FeedMonitor::new(cfg.feeds.clone(), Instant::now());
+    let mut recorder = cfg.recording.path.as_ref().map(|path| {
+        let mut recorder = Recorder::open(path).unwrap_or_else(|e| {
+            error!(%path, error = %e, "failed to open market data log");
+            std::process::exit(1);
+        });
+        // the chains as of startup, so a replay doesn't depend on what's still listed
+        if let Err(e) = recorder.record_contracts(&dispatch.contract_specs(), Utc::now()) {
+            error!(%path, error = %e, "failed to record contracts");
+            std::process::exit(1);
+        }
+        recorder
+    });
+    let mut latency = LatencyStats::new(&cfg.latency, Instant::now());
 
     // event processing loop
     while run_flag.load(Ordering::Relaxed) {
-        let msg = rx.recv().unwrap();
-        // println!("{:?}", msg);
-
-        let trade = match msg {
-            UniversalMsgWrapper::Binance(spot) => strat.process_spot_update(spot),
-            UniversalMsgWrapper::LedgerX(opts) => strat.process_opts_update(opts),
+        // the feed and receive time of the message behind this iteration's trade, if any
+        let mut origin = None;
+        // (order id, contract, size) of a LedgerX fill reported on this iteration
+        let mut lx_fill = None;
+        // wake up regularly even if both feeds go quiet, so staleness and legging get checked
+        let trade = match rx.recv_timeout(EVENT_LOOP_TICK) {
+            Ok(StampedMsg { recv_time, msg }) => {
+                let dequeued = Instant::now();
+                let venue = msg.venue();
+                metrics.msgs_processed.venue(venue).inc();
+                latency.record(Stage::Channel, venue, (Utc::now() - recv_time).to_std().unwrap_or_default());
+                match &msg {
+                    UniversalMsgWrapper::FeedReset(venue) => warn!(?venue, "feed reset"),
+                    // these come in on their own connections, so say nothing about the main feeds
+                    UniversalMsgWrapper::Funding(_) | UniversalMsgWrapper::LedgerXBook(_) => {},
+                    _ => monitor.on_msg(venue, dequeued),
+                }
+                // a full disk shouldn't stop trading, only the recording
+                if let Some(Err(e)) = recorder.as_mut().map(|r| r.record(&msg, recv_time)) {
+                    error!(error = %e, "stopped recording market data");
+                    recorder = None;
+                }
+                if let UniversalMsgWrapper::LedgerX(WebSocketMsg::ActionReport(r)) = &msg {
+                    if r.status_type == LX_STATUS_FILLED && r.filled_size > 0 {
+                        lx_fill = Some((r.mid.clone(), r.contract_id, r.filled_size));
+                    }
+                }
+                let trade = dispatch.process(msg, recv_time);
+                latency.record(Stage::Strategy, venue, dequeued.elapsed());
+                origin = Some((venue, recv_time));
+                if dispatch.take_depth_resync() {
+                    warn!("Binance depth gap, reconnecting");
+                    bn_alive.store(false, Ordering::Relaxed);
+                }
+                trade
+            },
+            Err(RecvTimeoutError::Timeout) => {
+                if let Some(Err(e)) = recorder.as_mut().map(|r| r.flush()) {
+                    error!(error = %e, "stopped recording market data");
+                    recorder = None;
+                }
+                None
+            },
+            Err(RecvTimeoutError::Disconnected) => break,
         };
 
+        // a silent feed is treated like a dropped one: forget its quotes and ask it to reconnect
+        for venue in monitor.check(Instant::now()) {
+            warn!(?venue, "feed stale, invalidating quotes");
+            dispatch.reset_feed(venue);
+            match venue {
+                FeedVenue::LedgerX => lx_alive.store(false, Ordering::Relaxed),
+                FeedVenue::Binance => bn_alive.store(false, Ordering::Relaxed),
+            }
+        }
 
         if let Some(t) = trade {
-            assert!(do_trade(t));
+            match risk.check(&t, &dispatch.books(), Instant::now()) {
+                Ok(()) => {
+                    let sent = Instant::now();
+                    let report = do_trade(&t, &mut binance_venue, &mut ledgerx_venue);
+                    metrics.trades_sent.inc();
+                    if report.status() == TradeStatus::Rejected {
+                        metrics.trades_rejected_venue.inc();
+                    }
+                    if let Some((venue, recv_time)) = origin {
+                        latency.record(Stage::Execution, venue, sent.elapsed());
+                        latency.record(Stage::WireToOrder, venue, (Utc::now() - recv_time).to_std().unwrap_or_default());
+                    }
+                    legging.track(t, report, Instant::now());
+                },
+                Err(reason) => {
+                    info!(%reason, trade = ?t, "risk rejected trade");
+                    metrics.trades_rejected_risk.inc();
+                    dispatch.release(&t);
+                },
+            }
+        }
+
+        // matched on the order that filled; one nothing is working on any more raced a cancel
+        if let Some((order_id, contract_id, size)) = lx_fill {
+            if !legging.on_ledgerx_fill(&order_id, size) {
+                match legging.on_late_fill(&order_id, size, Instant::now(), &mut binance_venue, &mut ledgerx_venue) {
+                    Some(fill) => {
+                        warn!(%order_id, fill = ?fill, "late fill on a cancelled order");
+                        dispatch.book_late_fill(&fill);
+                    },
+                    None => warn!(%order_id, contract_id, size, "LedgerX fill on an order we don't track"),
+                }
+            }
+        }
+
+        // anything still unfilled at the timeout is cancelled and unwound here, and the
+        // unwind followed until it fills
+        for exec in legging.poll(Instant::now(), &mut binance_venue, &mut ledgerx_venue) {
+            dispatch.settle(&exec);
+            match exec.state {
+                ComboState::Hedged => warn!(exec = ?exec, "unwound legged trade"),
+                ComboState::HedgeFailed => {
+                    error!(exec = ?exec, "failed to unwind legged trade");
+                    risk.engage_kill_switch("failed to unwind a legged trade");
+                },
+                _ => {},
+            }
+        }
+
+        if let Some(stats) = latency.dump_due(Instant::now()) {
+            for (stage, venue, h) in stats.iter() {
+                info!(
+                    stage = stage.name(),
+                    ?venue,
+                    count = h.count(),
+                    mean_us = h.mean().unwrap_or_default().as_micros() as u64,
+                    p50_us = h.quantile(0.5).unwrap_or_default().as_micros() as u64,
+                    p99_us = h.quantile(0.99).unwrap_or_default().as_micros() as u64,
+                    max_us = h.max().as_micros() as u64,
+                    "latency",
+                );
+            }
+        }
+        if metrics_refreshed.elapsed() >= METRICS_REFRESH {
+            metrics_refreshed = Instant::now();
+            metrics.observe(&dispatch, &monitor, metrics_refreshed);
         }
     }
 
     // Cleanup
+    if let Some(Err(e)) = recorder.as_mut().map(|r| r.flush()) {
+        error!(error = %e, "failed to flush market data log");
+    }
+    lx_alive.store(false, Ordering::Relaxed);
+    bn_alive.store(false, Ordering::Relaxed);
+    fd_alive.store(false, Ordering::Relaxed);
     if let Err(e) = lx_handle.join() {
-        eprintln!("{:?}", e);
+        error!(error = ?e, "thread panicked");
     }
     if let Err(e) = bn_handle.join() {
-        eprintln!("{:?}", e);
+        error!(error = ?e, "thread panicked");
+    }
+    for handle in poller_handles {
+        if let Err(e) = handle.join() {
+            error!(error = ?e, "thread panicked");
+        }
+    }
+    if let Some(Err(e)) = metrics_handle.map(|h| h.join()) {
+        error!(error = ?e, "thread panicked");
     }
 }
@@ -0,0 +1,107 @@
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+
+const BINANCE_API: &str = "https://api.binance.com";
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct InterestRate {
+    // per day, as a decimal string
+    daily_interest_rate: String,
+    timestamp: u64,
+}
+
+#[derive(Debug)]
+pub enum MarginRateError {
+    Http(reqwest::Error),
+    // no rate, or one that isn't a number
+    BadResponse(String),
+}
+
+impl fmt::Display for MarginRateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MarginRateError::Http(e) => write!(f, "{}", e),
+            MarginRateError::BadResponse(msg) => write!(f, "bad response: {}", msg),
+        }
+    }
+}
+
+impl From<reqwest::Error> for MarginRateError {
+    fn from(e: reqwest::Error) -> Self {
+        MarginRateError::Http(e)
+    }
+}
+
+// hex HMAC-SHA256 of the query string, as Binance signs USER_DATA requests
+fn sign(secret: &str, query: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes a key of any length");
+    mac.update(query.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+fn latest_rate(history: &[InterestRate]) -> Result<f64, MarginRateError> {
+    let latest = history.iter().max_by_key(|r| r.timestamp)
+        .ok_or_else(|| MarginRateError::BadResponse("empty rate history".to_string()))?;
+    latest.daily_interest_rate.parse()
+        .map_err(|_| MarginRateError::BadResponse(format!("rate {:?}", latest.daily_interest_rate)))
+}
+
+// Binance margin's current daily interest rate on an asset. It isn't streamed, and is
+// read from the signed rate history endpoint instead.
+pub struct MarginRateClient {
+    http: reqwest::blocking::Client,
+    api_key: String,
+    api_secret: String,
+}
+
+impl MarginRateClient {
+    pub fn new(api_key: &str, api_secret: &str) -> Self {
+        Self {
+            http: reqwest::blocking::Client::new(),
+            api_key: api_key.to_owned(),
+            api_secret: api_secret.to_owned(),
+        }
+    }
+
+    pub fn daily_rate(&self, asset: &str) -> Result<f64, MarginRateError> {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+        let query = format!("asset={}&limit=1&timestamp={}", asset, timestamp);
+        let url = format!("{}/sapi/v1/margin/interestRateHistory?{}&signature={}", BINANCE_API, query, sign(&self.api_secret, &query));
+        let history: Vec<InterestRate> = self.http.get(url)
+            .header("X-MBX-APIKEY", &self.api_key)
+            .timeout(REQUEST_TIMEOUT)
+            .send()?
+            .error_for_status()?
+            .json()?;
+        latest_rate(&history)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{latest_rate, sign, InterestRate};
+
+    #[test]
+    fn signs_like_binance_docs() {
+        let secret = "NhqPtmdSJYdKjVHjA7PZj4Mge3R5YNiP1e3UZjInClVN65XAbvqqM6A7H5fATj0j";
+        let query = "symbol=LTCBTC&side=BUY&type=LIMIT&timeInForce=GTC&quantity=1&price=0.1&recvWindow=5000&timestamp=1499827319559";
+        assert_eq!(sign(secret, query), "c8db56825ae71d6d79447849e617115f4a920fa2acdcab2b053c4b2838bd6b71");
+    }
+
+    #[test]
+    fn takes_the_latest_rate() {
+        let raw = r#"[
+            {"asset": "BTC", "dailyInterestRate": "0.00025", "timestamp": 1611544731000, "vipLevel": 0},
+            {"asset": "BTC", "dailyInterestRate": "0.00030", "timestamp": 1611548331000, "vipLevel": 0}
+        ]"#;
+        let history: Vec<InterestRate> = serde_json::from_str(raw).unwrap();
+        assert_eq!(latest_rate(&history).unwrap(), 0.0003);
+        assert!(latest_rate(&[]).is_err());
+    }
+}
@@ -0,0 +1,294 @@
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+use tracing::warn;
+
+use crate::dispatch::StratDispatcher;
+use crate::feeds::{FeedMonitor, FeedVenue};
+use crate::positions::PositionView;
+use crate::strat::ArbCounts;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct MetricsConfig {
+    // address to serve /metrics on, e.g. "127.0.0.1:9184"; no endpoint if unset
+    pub listen: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    pub fn inc(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct VenueCounter {
+    ledgerx: Counter,
+    binance: Counter,
+}
+
+impl VenueCounter {
+    pub fn venue(&self, venue: FeedVenue) -> &Counter {
+        match venue {
+            FeedVenue::LedgerX => &self.ledgerx,
+            FeedVenue::Binance => &self.binance,
+        }
+    }
+}
+
+// what the event loop last saw of the strategies and feeds
+#[derive(Debug, Default)]
+struct Observed {
+    arbs: ArbCounts,
+    // (underlying, contract id, filled, working)
+    options: Vec<(String, u64, i64, i64)>,
+    // (underlying, symbol, filled, working)
+    spot: Vec<(String, String, f64, f64)>,
+    // (underlying, committed net delta)
+    net_delta: Vec<(String, f64)>,
+    // (underlying, expiry, parity-implied rate, forward, flat rate)
+    implied: Vec<(String, String, f64, f64, f64)>,
+    // (venue, stale, seconds since last message)
+    feeds: Vec<(FeedVenue, bool, Option<f64>)>,
+}
+
+// Shared between the feed threads, the event loop and the endpoint. Counters are bumped
+// where things happen; everything else is copied in by `observe` from the event loop.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    // put on the channel by the feed threads
+    pub msgs_received: VenueCounter,
+    // taken off it by the event loop
+    pub msgs_processed: VenueCounter,
+    pub ledgerx_parse_errors: Counter,
+    pub trades_sent: Counter,
+    pub trades_rejected_risk: Counter,
+    pub trades_rejected_venue: Counter,
+    observed: Mutex<Observed>,
+}
+
+const VENUES: [FeedVenue; 2] = [FeedVenue::LedgerX, FeedVenue::Binance];
+
+fn venue_label(venue: FeedVenue) -> &'static str {
+    match venue {
+        FeedVenue::LedgerX => "ledgerx",
+        FeedVenue::Binance => "binance",
+    }
+}
+
+impl Metrics {
+    pub fn queue_depth(&self) -> u64 {
+        VENUES.iter()
+            .map(|&v| self.msgs_received.venue(v).get().saturating_sub(self.msgs_processed.venue(v).get()))
+            .sum()
+    }
+
+    pub fn observe(&self, dispatch: &StratDispatcher, monitor: &FeedMonitor, now: Instant) {
+        let mut observed = Observed { arbs: dispatch.arb_counts(), ..Observed::default() };
+        for strat in dispatch.strats.iter() {
+            let underlying = &strat.config.symbols.ledgerx;
+            let book = &strat.positions;
+            for (&id, pos) in book.options.iter() {
+                observed.options.push((underlying.clone(), id, pos.filled, pos.working));
+            }
+            for (symbol, pos) in book.spot.iter() {
+                observed.spot.push((underlying.clone(), symbol.clone(), pos.filled, pos.working));
+            }
+            observed.net_delta.push((underlying.clone(), book.net_delta(PositionView::Committed)));
+            for (expiry, fit) in strat.implied.iter() {
+                let expiry = expiry.format("%Y-%m-%d").to_string();
+                observed.implied.push((underlying.clone(), expiry, fit.rate, fit.forward, strat.config.funding_rate()));
+            }
+        }
+        observed.options.sort_by(|a, b| (&a.0, a.1).cmp(&(&b.0, b.1)));
+        observed.spot.sort_by(|a, b| (&a.0, &a.1).cmp(&(&b.0, &b.1)));
+        for venue in VENUES {
+            let silent = monitor.silent_for(venue, now).map(|d| d.as_secs_f64());
+            observed.feeds.push((venue, monitor.is_stale(venue), silent));
+        }
+        *self.observed.lock().unwrap() = observed;
+    }
+
+    // Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        header(&mut out, "combo_feed_messages_total", "counter", "Feed messages taken off the event loop's queue.");
+        for venue in VENUES {
+            let _ = writeln!(out, "combo_feed_messages_total{{venue=\"{}\"}} {}", venue_label(venue), self.msgs_processed.venue(venue).get());
+        }
+        header(&mut out, "combo_ledgerx_parse_errors_total", "counter", "LedgerX websocket messages that failed to parse.");
+        let _ = writeln!(out, "combo_ledgerx_parse_errors_total {}", self.ledgerx_parse_errors.get());
+        header(&mut out, "combo_event_queue_depth", "gauge", "Feed messages waiting for the event loop.");
+        let _ = writeln!(out, "combo_event_queue_depth {}", self.queue_depth());
+
+        header(&mut out, "combo_trades_sent_total", "counter", "Trades that passed risk and were sent to the venues.");
+        let _ = writeln!(out, "combo_trades_sent_total {}", self.trades_sent.get());
+        header(&mut out, "combo_trades_rejected_total", "counter", "Trades rejected by the risk gate or by both venues.");
+        let _ = writeln!(out, "combo_trades_rejected_total{{by=\"risk\"}} {}", self.trades_rejected_risk.get());
+        let _ = writeln!(out, "combo_trades_rejected_total{{by=\"venue\"}} {}", self.trades_rejected_venue.get());
+
+        let observed = self.observed.lock().unwrap();
+        header(&mut out, "combo_arbs_detected_total", "counter", "Combos the strategy generated orders for.");
+        let _ = writeln!(out, "combo_arbs_detected_total{{kind=\"conversion\"}} {}", observed.arbs.conversions);
+        let _ = writeln!(out, "combo_arbs_detected_total{{kind=\"reversal\"}} {}", observed.arbs.reversals);
+        let _ = writeln!(out, "combo_arbs_detected_total{{kind=\"box\"}} {}", observed.arbs.boxes);
+        let _ = writeln!(out, "combo_arbs_detected_total{{kind=\"jelly_roll\"}} {}", observed.arbs.rolls);
+
+        header(&mut out, "combo_feed_stale", "gauge", "1 if the feed has been silent past its staleness limit.");
+        for &(venue, stale, _) in observed.feeds.iter() {
+            let _ = writeln!(out, "combo_feed_stale{{venue=\"{}\"}} {}", venue_label(venue), stale as u8);
+        }
+        header(&mut out, "combo_feed_silent_seconds", "gauge", "Seconds since the feed's last message.");
+        for &(venue, _, silent) in observed.feeds.iter() {
+            if let Some(silent) = silent {
+                let _ = writeln!(out, "combo_feed_silent_seconds{{venue=\"{}\"}} {}", venue_label(venue), silent);
+            }
+        }
+
+        header(&mut out, "combo_option_position_contracts", "gauge", "LedgerX positions in contracts, positive is long.");
+        for (underlying, id, filled, working) in observed.options.iter() {
+            let _ = writeln!(out, "combo_option_position_contracts{{underlying=\"{underlying}\",contract_id=\"{id}\",state=\"filled\"}} {filled}");
+            let _ = writeln!(out, "combo_option_position_contracts{{underlying=\"{underlying}\",contract_id=\"{id}\",state=\"working\"}} {working}");
+        }
+        header(&mut out, "combo_spot_position", "gauge", "Binance positions in units of the underlying, positive is long.");
+        for (underlying, symbol, filled, working) in observed.spot.iter() {
+            let _ = writeln!(out, "combo_spot_position{{underlying=\"{underlying}\",symbol=\"{symbol}\",state=\"filled\"}} {filled}");
+            let _ = writeln!(out, "combo_spot_position{{underlying=\"{underlying}\",symbol=\"{symbol}\",state=\"working\"}} {working}");
+        }
+        header(&mut out, "combo_net_delta", "gauge", "Spot plus synthetic forwards, filled and working.");
+        for (underlying, delta) in observed.net_delta.iter() {
+            let _ = writeln!(out, "combo_net_delta{{underlying=\"{underlying}\"}} {delta}");
+        }
+        header(&mut out, "combo_implied_rate", "gauge", "Annual rate put-call parity implies per expiry, next to the configured flat rate.");
+        for (underlying, expiry, rate, _, flat) in observed.implied.iter() {
+            let _ = writeln!(out, "combo_implied_rate{{underlying=\"{underlying}\",expiry=\"{expiry}\",source=\"parity\"}} {rate}");
+            let _ = writeln!(out, "combo_implied_rate{{underlying=\"{underlying}\",expiry=\"{expiry}\",source=\"flat\"}} {flat}");
+        }
+        header(&mut out, "combo_implied_forward", "gauge", "Forward put-call parity implies per expiry.");
+        for (underlying, expiry, _, forward, _) in observed.implied.iter() {
+            let _ = writeln!(out, "combo_implied_forward{{underlying=\"{underlying}\",expiry=\"{expiry}\"}} {forward}");
+        }
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}\n# TYPE {name} {kind}");
+}
+
+fn respond(stream: TcpStream, metrics: &Metrics) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(1)))?;
+    let mut request_line = String::new();
+    BufReader::new(&stream).read_line(&mut request_line)?;
+
+    let mut stream = stream;
+    let mut parts = request_line.split_whitespace();
+    match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => {
+            let body = metrics.render();
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body,
+            )
+        },
+        _ => stream.write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"),
+    }
+}
+
+// Answers scrapes one at a time on its own thread until `run_flag` is cleared.
+pub fn serve(listener: TcpListener, metrics: Arc<Metrics>, run_flag: Arc<AtomicBool>) -> io::Result<JoinHandle<()>> {
+    // polled, so shutdown isn't stuck behind a blocking accept
+    listener.set_nonblocking(true)?;
+    Ok(std::thread::spawn(move || {
+        while run_flag.load(Ordering::Relaxed) {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    let _ = stream.set_nonblocking(false);
+                    if let Err(e) = respond(stream, &metrics) {
+                        warn!(error = %e, "failed to serve metrics");
+                    }
+                },
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => std::thread::sleep(Duration::from_millis(50)),
+                Err(e) => warn!(error = %e, "metrics endpoint accept failed"),
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::time::Instant;
+
+    use crate::dispatch::StratDispatcher;
+    use crate::feeds::{FeedConfig, FeedMonitor, FeedVenue};
+    use super::{serve, Metrics};
+
+    #[test]
+    fn renders_counters_and_queue_depth() {
+        let metrics = Metrics::default();
+        for _ in 0..3 {
+            metrics.msgs_received.venue(FeedVenue::Binance).inc();
+        }
+        metrics.msgs_received.venue(FeedVenue::LedgerX).inc();
+        metrics.msgs_processed.venue(FeedVenue::Binance).inc();
+        metrics.ledgerx_parse_errors.inc();
+        metrics.trades_rejected_risk.inc();
+
+        let now = Instant::now();
+        metrics.observe(&StratDispatcher::new(vec![]), &FeedMonitor::new(FeedConfig::default(), now), now);
+
+        let text = metrics.render();
+        assert!(text.contains("combo_feed_messages_total{venue=\"binance\"} 1\n"));
+        assert!(text.contains("combo_event_queue_depth 3\n"));
+        assert!(text.contains("combo_ledgerx_parse_errors_total 1\n"));
+        assert!(text.contains("combo_trades_rejected_total{by=\"risk\"} 1\n"));
+        assert!(text.contains("combo_arbs_detected_total{kind=\"reversal\"} 0\n"));
+        assert!(text.contains("combo_feed_stale{venue=\"ledgerx\"} 0\n"));
+        assert!(text.contains("# TYPE combo_trades_sent_total counter\n"));
+    }
+
+    #[test]
+    fn serves_metrics_over_http() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let metrics = Arc::new(Metrics::default());
+        metrics.trades_sent.inc();
+        let run_flag = Arc::new(AtomicBool::new(true));
+        let handle = serve(listener, metrics, run_flag.clone()).unwrap();
+
+        let get = |path: &str| {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            write!(stream, "GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+        let ok = get("/metrics");
+        assert!(ok.starts_with("HTTP/1.1 200 OK"));
+        assert!(ok.contains("combo_trades_sent_total 1\n"));
+        assert!(get("/").starts_with("HTTP/1.1 404"));
+
+        run_flag.store(false, Ordering::Relaxed);
+        handle.join().unwrap();
+    }
+}
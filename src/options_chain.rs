@@ -1,24 +1,14 @@
-use std::cell::{RefCell};
 use std::collections::HashMap;
-use std::rc::{Rc, Weak};
 
 use ftx_us_derivs::table::{OptionContractSpec, ContractSpecTable};
 
 use chrono::{offset::Utc, DateTime};
 
+use crate::book::LedgerXOrderbook;
 
-type LatticePointer = Weak<RefCell<LedgerXOptionsContract>>;
 
-pub trait LatticeRef {
-    type Target;
-    fn lattice_deref(&self) -> Rc<RefCell<Self::Target>>;
-}
-impl LatticeRef for LatticePointer {
-    type Target = LedgerXOptionsContract;
-    fn lattice_deref(&self) -> Rc<RefCell<Self::Target>> {
-        return self.upgrade().unwrap();
-    }   
-}
+// position of a contract in `LedgerXOptionsChain::contracts`
+pub type ContractIdx = usize;
 
 #[derive(Debug)]
 pub struct LedgerXOptionsContract {
@@ -34,14 +24,17 @@ pub struct LedgerXOptionsContract {
     pub bid_quantity: Option<f64>,
     pub ask: Option<f64>,
     pub ask_quantity: Option<f64>,
+    pub quote_time: Option<DateTime<Utc>>, // when the quote above was received
     
-    pub adjacent: Option<LatticePointer>,
-    pub up: Option<LatticePointer>,
-    pub down: Option<LatticePointer>,
+    // the other side of this level, and the same side one strike higher/lower
+    pub adjacent: Option<ContractIdx>,
+    pub up: Option<ContractIdx>,
+    pub down: Option<ContractIdx>,
 
     pub spec: OptionContractSpec,
 
-    //book: LedgerXOrderbook,
+    // full depth; bid/ask above mirror its top
+    pub book: LedgerXOrderbook,
 }
 
 impl LedgerXOptionsContract {
@@ -59,24 +52,30 @@ impl LedgerXOptionsContract {
             bid_quantity: None,
             ask: None,
             ask_quantity: None,
+            quote_time: None,
             
             adjacent: None,
             up: None,
             down: None,
             
             spec: spec.to_owned(),
+
+            book: LedgerXOrderbook::default(),
         }
     }
 }
 
 pub struct LedgerXOptionsChain {
+    // Every contract on the chain; everything below refers into this by index
+    pub contracts: Vec<LedgerXOptionsContract>,
+
     // Lists of all contracts for mass-repricing events, e.g. spot move.
-    pub calls: Vec<Rc<RefCell<LedgerXOptionsContract>>>,
-    pub puts: Vec<Rc<RefCell<LedgerXOptionsContract>>>,
+    pub calls: Vec<ContractIdx>,
+    pub puts: Vec<ContractIdx>,
 
     // Lookup tables by unique identifier for individual contract updates, e.g. market tick
-    pub id_map: HashMap<u64, Rc<RefCell<LedgerXOptionsContract>>>,
-    pub label_map: HashMap<String, Rc<RefCell<LedgerXOptionsContract>>>,
+    pub id_map: HashMap<u64, ContractIdx>,
+    pub label_map: HashMap<String, ContractIdx>,
 
     // Data structure for lookup of contracts by expiration, strike, then parity
     pub expirys: HashMap<DateTime<Utc>, LedgerXExpiryBlock>,
@@ -89,8 +88,50 @@ pub struct LedgerXExpiryBlock {
 }
 
 impl LedgerXExpiryBlock {
-    pub fn iter_down(&self) {
-        todo!()
+    pub fn expiration(&self) -> DateTime<Utc> {
+        self.expiration
+    }
+
+    // ascending
+    pub fn strikes(&self) -> &[u64] {
+        &self.strikes
+    }
+
+    pub fn level(&self, strike: u64) -> Option<&LedgerXOptionsLevel> {
+        self.strike_map.get(&strike)
+    }
+
+    // levels from the lowest strike up
+    pub fn iter_up(&self) -> impl Iterator<Item = &LedgerXOptionsLevel> + '_ {
+        self.iter_up_from(0)
+    }
+
+    // levels from the highest strike down
+    pub fn iter_down(&self) -> impl Iterator<Item = &LedgerXOptionsLevel> + '_ {
+        self.iter_down_from(u64::MAX)
+    }
+
+    // levels at or above `strike`, going up
+    pub fn iter_up_from(&self, strike: u64) -> impl Iterator<Item = &LedgerXOptionsLevel> + '_ {
+        let start = self.strikes.partition_point(|&k| k < strike);
+        self.strikes[start..].iter().map(move |k| &self.strike_map[k])
+    }
+
+    // levels at or below `strike`, going down
+    pub fn iter_down_from(&self, strike: u64) -> impl Iterator<Item = &LedgerXOptionsLevel> + '_ {
+        let end = self.strikes.partition_point(|&k| k <= strike);
+        self.strikes[..end].iter().rev().map(move |k| &self.strike_map[k])
+    }
+
+    // the level whose strike is closest to `spot`; ties go to the lower strike
+    pub fn nearest_strike(&self, spot: f64) -> Option<&LedgerXOptionsLevel> {
+        let above = self.strikes.partition_point(|&k| (k as f64) < spot);
+        let candidates = [above.checked_sub(1), Some(above)];
+        candidates.iter()
+            .flatten()
+            .filter_map(|&i| self.strikes.get(i))
+            .min_by(|a, b| (**a as f64 - spot).abs().total_cmp(&(**b as f64 - spot).abs()))
+            .map(|k| &self.strike_map[k])
     }
 }
 
@@ -98,8 +139,18 @@ pub struct LedgerXOptionsLevel {
     expiration: DateTime<Utc>,
     strike: u64,
     
-    pub call: Option<Rc<RefCell<LedgerXOptionsContract>>>,
-    pub put: Option<Rc<RefCell<LedgerXOptionsContract>>>,
+    pub call: Option<ContractIdx>,
+    pub put: Option<ContractIdx>,
+}
+
+impl LedgerXOptionsLevel {
+    pub fn expiration(&self) -> DateTime<Utc> {
+        self.expiration
+    }
+
+    pub fn strike(&self) -> u64 {
+        self.strike
+    }
 }
 
 
@@ -111,128 +162,81 @@ impl LedgerXOptionsChain {
     }
 
     pub fn from_spec_table(symbol: &str, table: ContractSpecTable) -> Self {
-        let mut calls: Vec<Rc<RefCell<LedgerXOptionsContract>>> = vec![];
-        let mut puts: Vec<Rc<RefCell<LedgerXOptionsContract>>> = vec![];
+        let mut contracts: Vec<LedgerXOptionsContract> = vec![];
+        let mut calls: Vec<ContractIdx> = vec![];
+        let mut puts: Vec<ContractIdx> = vec![];
         
         // build the list of all options
-        for spec in table.id_table.values().into_iter() {
+        for spec in table.id_table.values() {
             if let Some(s) = spec.as_opt_ref() {
-                let contr = Rc::new(RefCell::new(LedgerXOptionsContract::from_spec(s)));
-                if contr.as_ref().borrow().spec.active && contr.as_ref().borrow().underlying.as_str() == symbol {
-                    match contr.as_ref().borrow().is_call {
-                        true => calls.push(contr.clone()),
-                        false => puts.push(contr.clone()),
+                if s.active && s.underlying.as_str() == symbol {
+                    match s.is_call {
+                        true => calls.push(contracts.len()),
+                        false => puts.push(contracts.len()),
                     }
+                    contracts.push(LedgerXOptionsContract::from_spec(s));
                 }
             }
         }
         
         // build the unique ID maps
-        let mut id_map: HashMap<u64, Rc<RefCell<LedgerXOptionsContract>>> = HashMap::new();
-        let mut label_map: HashMap<String, Rc<RefCell<LedgerXOptionsContract>>> = HashMap::new();
-        for option_ref in calls.iter().chain(puts.iter()) {
-            id_map.insert(option_ref.as_ref().borrow().id, option_ref.clone());
-            label_map.insert(option_ref.as_ref().borrow().label.to_owned(), option_ref.clone());
+        let mut id_map: HashMap<u64, ContractIdx> = HashMap::new();
+        let mut label_map: HashMap<String, ContractIdx> = HashMap::new();
+        for (i, option) in contracts.iter().enumerate() {
+            id_map.insert(option.id, i);
+            label_map.insert(option.label.to_owned(), i);
         }
 
-        // build the full data structure by nesting the Entry API
+        // group by expiration, then strike
         let mut expirys: HashMap<DateTime<Utc>, LedgerXExpiryBlock>  = HashMap::new();
-        for option_ref in calls.iter().chain(puts.iter()) {
-            let option = option_ref.as_ref().borrow();
-
-            expirys.entry(option.spec.date_expires)
-                .and_modify(|exp_block| {
-                    exp_block.strike_map.entry(option.strike)
-                        .and_modify(|level| {
-                            if option.is_call {
-                                level.call = Some(option_ref.to_owned());
-                            } else {
-                                level.put = Some(option_ref.to_owned());
-                            }
-                        })
-                        .or_insert(
-                            LedgerXOptionsLevel {
-                                expiration: option.spec.date_expires,
-                                strike: option.strike,
-                                call: if option.is_call { Some(option_ref.to_owned()) } else { None },
-                                put: if !option.is_call { Some(option_ref.to_owned()) } else { None },
-                            }
-                        );
-
-                    if !exp_block.strikes.contains(&option.strike) {
-                        exp_block.strikes.push(option.strike);
-                        exp_block.strikes.sort();
-                    }
-                })
-                .or_insert(
-                    LedgerXExpiryBlock {
-                        expiration: option.spec.date_expires,
-                        strikes: vec![option.strike],
-                        strike_map: HashMap::from_iter(
-                            [(
-                                option.strike,
-                                LedgerXOptionsLevel {
-                                    expiration: option.spec.date_expires,
-                                    strike: option.strike,
-                                    call: if option.is_call { Some(option_ref.to_owned()) } else { None },
-                                    put: if !option.is_call { Some(option_ref.to_owned()) } else { None },
-                                }
-                            )]
-                        ),
-                    }
-                );
-                
+        for (i, option) in contracts.iter().enumerate() {
+            let exp_block = expirys.entry(option.spec.date_expires)
+                .or_insert_with(|| LedgerXExpiryBlock {
+                    expiration: option.spec.date_expires,
+                    strikes: vec![],
+                    strike_map: HashMap::new(),
+                });
+            let level = exp_block.strike_map.entry(option.strike)
+                .or_insert_with(|| LedgerXOptionsLevel {
+                    expiration: option.spec.date_expires,
+                    strike: option.strike,
+                    call: None,
+                    put: None,
+                });
+            if option.is_call {
+                level.call = Some(i);
+            } else {
+                level.put = Some(i);
+            }
+        }
+        for exp_block in expirys.values_mut() {
+            exp_block.strikes = exp_block.strike_map.keys().cloned().collect();
+            exp_block.strikes.sort();
         }
 
         // link the all the internal structures of the options:
-        // left-right linkage
-        for call_ext in calls.iter() {
-            let mut call = call_ext.as_ref().borrow_mut();
-
-            let block = expirys.get(&call.spec.date_expires).unwrap();
-            let level = block.strike_map.get(&call.strike).unwrap();
-            
-            if let Some(put) = &level.put {
-                call.adjacent = Some(Rc::downgrade(put));
-                put.as_ref().borrow_mut().adjacent = Some(Rc::downgrade(call_ext));
-            }
-        }
-        // up-down linkage
+        // left-right linkage, and up-down linkage along each side of each expiry
         for exp in expirys.values() {
-            let mut last_above = (None, None);
-            let mut last_below = (None, None);
-            for i in 0..(exp.strikes.len()) {
-                // one index that crawls up and one that crawls down
-                let crawl_down_ind = i;
-                let crawl_up_ind = exp.strikes.len() - i - 1;
-                
-                // crawling down the chain and setting the "up" pointers
-                let level = exp.strike_map.get(&exp.strikes[crawl_down_ind]).unwrap();
-                if let Some(call) = &level.call {
-                    call.as_ref().borrow_mut().up = last_above.0;
-                    last_above = (Some(Rc::downgrade(call)), last_above.1);
-                }
-                if let Some(put) = &level.put {
-                    put.as_ref().borrow_mut().up = last_above.1;
-                    last_above = (last_above.0, Some(Rc::downgrade(put)));
+            let mut last_below: (Option<ContractIdx>, Option<ContractIdx>) = (None, None);
+            for level in exp.strikes.iter().map(|k| &exp.strike_map[k]) {
+                if let (Some(call), Some(put)) = (level.call, level.put) {
+                    contracts[call].adjacent = Some(put);
+                    contracts[put].adjacent = Some(call);
                 }
-
-                // crawling up the chain and setting the "down" pointers
-                let level = exp.strike_map.get(&exp.strikes[crawl_up_ind]).unwrap();
-                if let Some(call) = &level.call {
-                    call.as_ref().borrow_mut().down = last_below.0;
-                    last_below = (Some(Rc::downgrade(call)), last_below.1);
-                }
-                if let Some(put) = &level.put {
-                    put.as_ref().borrow_mut().down = last_below.1;
-                    last_below = (last_below.0, Some(Rc::downgrade(put)));
+                for (this, below) in [(level.call, &mut last_below.0), (level.put, &mut last_below.1)] {
+                    if let Some(this) = this {
+                        contracts[this].down = *below;
+                        if let Some(b) = *below {
+                            contracts[b].up = Some(this);
+                        }
+                        *below = Some(this);
+                    }
                 }
             }
         }
 
-        
-        
         return LedgerXOptionsChain {
+            contracts,
             calls,
             puts,
             id_map,
@@ -241,17 +245,198 @@ impl LedgerXOptionsChain {
         };
     }
 
-    pub fn get_opts_level(expiration: DateTime<Utc>, strike: u64) -> Option<LedgerXOptionsLevel> {
-        todo!()
+    pub fn get(&self, idx: ContractIdx) -> &LedgerXOptionsContract {
+        &self.contracts[idx]
+    }
+
+    pub fn get_mut(&mut self, idx: ContractIdx) -> &mut LedgerXOptionsContract {
+        &mut self.contracts[idx]
+    }
+
+    pub fn by_id(&self, id: u64) -> Option<&LedgerXOptionsContract> {
+        self.id_map.get(&id).map(|&i| &self.contracts[i])
+    }
+
+    pub fn by_id_mut(&mut self, id: u64) -> Option<&mut LedgerXOptionsContract> {
+        let i = *self.id_map.get(&id)?;
+        Some(&mut self.contracts[i])
+    }
+
+    pub fn by_label(&self, label: &str) -> Option<&LedgerXOptionsContract> {
+        self.label_map.get(label).map(|&i| &self.contracts[i])
+    }
+
+    pub fn adjacent(&self, contract: &LedgerXOptionsContract) -> Option<&LedgerXOptionsContract> {
+        contract.adjacent.map(|i| &self.contracts[i])
+    }
+
+    pub fn up(&self, contract: &LedgerXOptionsContract) -> Option<&LedgerXOptionsContract> {
+        contract.up.map(|i| &self.contracts[i])
+    }
+
+    pub fn down(&self, contract: &LedgerXOptionsContract) -> Option<&LedgerXOptionsContract> {
+        contract.down.map(|i| &self.contracts[i])
+    }
+
+    pub fn calls(&self) -> impl Iterator<Item = &LedgerXOptionsContract> + '_ {
+        self.calls.iter().map(|&i| &self.contracts[i])
+    }
+
+    pub fn puts(&self) -> impl Iterator<Item = &LedgerXOptionsContract> + '_ {
+        self.puts.iter().map(|&i| &self.contracts[i])
+    }
+
+    // every level with both a call and a put, as (call, put)
+    pub fn pairs(&self) -> impl Iterator<Item = (&LedgerXOptionsContract, &LedgerXOptionsContract)> + '_ {
+        self.calls().filter_map(|call| Some((call, self.adjacent(call)?)))
+    }
+
+    pub fn get_expiry(&self, expiration: DateTime<Utc>) -> Option<&LedgerXExpiryBlock> {
+        self.expirys.get(&expiration)
+    }
+
+    pub fn get_opts_level(&self, expiration: DateTime<Utc>, strike: u64) -> Option<&LedgerXOptionsLevel> {
+        self.get_expiry(expiration)?.level(strike)
+    }
+
+    // soonest first
+    pub fn expiries(&self) -> impl Iterator<Item = &LedgerXExpiryBlock> + '_ {
+        let mut blocks: Vec<&LedgerXExpiryBlock> = self.expirys.values().collect();
+        blocks.sort_by_key(|b| b.expiration);
+        blocks.into_iter()
+    }
+
+    pub fn nearest_strike(&self, expiration: DateTime<Utc>, spot: f64) -> Option<&LedgerXOptionsLevel> {
+        self.get_expiry(expiration)?.nearest_strike(spot)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashMap;
     use std::rc::Rc;
     use std::str::FromStr;
 
+    use chrono::{DateTime, TimeZone, Utc};
+    use ftx_us_derivs::table::{ContractSpec, ContractSpecTable, OptionContractSpec};
+
     use super::LedgerXOptionsChain;
+
+    fn jun() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2023, 6, 30, 8, 0, 0).unwrap()
+    }
+    fn sep() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2023, 9, 29, 8, 0, 0).unwrap()
+    }
+
+    // two CBTC expiries, an ETH contract and an expired one that should be left out
+    fn mock_chain() -> LedgerXOptionsChain {
+        let mut id = 0;
+        let mut option = |underlying: &str, expires: DateTime<Utc>, strike: u64, is_call: bool, active: bool| {
+            id += 1;
+            let spec = OptionContractSpec {
+                id,
+                label: format!("{}-{}-{}-{}", underlying, expires.format("%d%b%Y"), strike, if is_call { "Call" } else { "Put" }),
+                underlying: underlying.to_string(),
+                strike_price: strike,
+                is_call,
+                tte: 0.5,
+                open_interest: 0,
+                multiplier: 100.0,
+                min_increment: 1.0,
+                active,
+                date_live: DateTime::<Utc>::MIN_UTC,
+                date_expires: expires,
+                collateral_asset: "USD".to_string(),
+                is_ecp_only: false,
+            };
+            (id, Rc::new(ContractSpec::Option(spec)))
+        };
+
+        let mut contracts = vec![];
+        for strike in [30000, 10000, 20000, 25000] {
+            contracts.push(option("CBTC", jun(), strike, true, true));
+            contracts.push(option("CBTC", jun(), strike, false, true));
+        }
+        // sep has a call-only strike
+        contracts.push(option("CBTC", sep(), 20000, true, true));
+        contracts.push(option("CBTC", sep(), 20000, false, true));
+        contracts.push(option("CBTC", sep(), 40000, true, true));
+        contracts.push(option("CETH", jun(), 2000, true, true));
+        contracts.push(option("CBTC", jun(), 15000, true, false));
+
+        let table = ContractSpecTable { id_table: HashMap::from_iter(contracts), label_table: HashMap::new() };
+        LedgerXOptionsChain::from_spec_table("CBTC", table)
+    }
+
+    #[test]
+    fn level_lookup() {
+        let chain = mock_chain();
+
+        let level = chain.get_opts_level(jun(), 20000).unwrap();
+        assert_eq!((level.expiration(), level.strike()), (jun(), 20000));
+        assert!(chain.get(level.call.unwrap()).is_call);
+        assert!(!chain.get(level.put.unwrap()).is_call);
+
+        let call_only = chain.get_opts_level(sep(), 40000).unwrap();
+        assert!(call_only.call.is_some() && call_only.put.is_none());
+
+        assert!(chain.get_opts_level(jun(), 15000).is_none());
+        assert!(chain.get_opts_level(jun(), 2000).is_none());
+        assert!(chain.get_opts_level(Utc.with_ymd_and_hms(2023, 7, 28, 8, 0, 0).unwrap(), 20000).is_none());
+
+        let expiries: Vec<_> = chain.expiries().map(|b| b.expiration()).collect();
+        assert_eq!(expiries, vec![jun(), sep()]);
+    }
+
+    #[test]
+    fn strike_iterators() {
+        let chain = mock_chain();
+        let block = chain.get_expiry(jun()).unwrap();
+        let strikes = |levels: Vec<&super::LedgerXOptionsLevel>| levels.iter().map(|l| l.strike()).collect::<Vec<_>>();
+
+        assert_eq!(block.strikes(), &[10000, 20000, 25000, 30000]);
+        assert_eq!(strikes(block.iter_up().collect()), vec![10000, 20000, 25000, 30000]);
+        assert_eq!(strikes(block.iter_down().collect()), vec![30000, 25000, 20000, 10000]);
+        assert_eq!(strikes(block.iter_up_from(20000).collect()), vec![20000, 25000, 30000]);
+        assert_eq!(strikes(block.iter_down_from(24999).collect()), vec![20000, 10000]);
+        assert!(block.iter_up_from(30001).next().is_none());
+    }
+
+    #[test]
+    fn lattice_links() {
+        let chain = mock_chain();
+        let call = chain.by_label("CBTC-30Jun2023-20000-Call").unwrap();
+
+        assert_eq!(chain.adjacent(call).unwrap().label, "CBTC-30Jun2023-20000-Put");
+        assert_eq!(chain.up(call).unwrap().label, "CBTC-30Jun2023-25000-Call");
+        assert_eq!(chain.down(call).unwrap().label, "CBTC-30Jun2023-10000-Call");
+        assert!(chain.down(chain.by_label("CBTC-30Jun2023-10000-Put").unwrap()).is_none());
+
+        // the call-only level has nothing to pair with
+        assert_eq!(chain.pairs().count(), 5);
+        assert!(chain.by_label("CBTC-29Sep2023-40000-Call").unwrap().adjacent.is_none());
+    }
+
+    #[test]
+    fn chain_is_send() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<LedgerXOptionsChain>();
+    }
+
+    #[test]
+    fn nearest_strike() {
+        let chain = mock_chain();
+        let nearest = |spot| chain.nearest_strike(jun(), spot).map(|l| l.strike());
+
+        assert_eq!(nearest(5000.0), Some(10000));
+        assert_eq!(nearest(21000.0), Some(20000));
+        assert_eq!(nearest(24000.0), Some(25000));
+        // halfway goes down
+        assert_eq!(nearest(22500.0), Some(20000));
+        assert_eq!(nearest(99000.0), Some(30000));
+        assert_eq!(chain.nearest_strike(sep(), 31000.0).map(|l| l.strike()), Some(40000));
+    }
     #[test]
     fn id_map() {
         let chain = LedgerXOptionsChain::build("CBTC");
@@ -259,23 +444,21 @@ mod tests {
         let id1 = 22252867;
         let id2 = 22252868;
 
-        println!("{:?}", chain.id_map.get(&id1).unwrap());
-        println!("{:?}", chain.id_map.get(&id2).unwrap());
+        println!("{:?}", chain.by_id(id1).unwrap());
+        println!("{:?}", chain.by_id(id2).unwrap());
     }
     #[test]
     fn crawl_up() {
         let chain = LedgerXOptionsChain::build("CBTC");
         
-        let eg_label = String::from_str("BTC-Mini-30JUN2023-200000-Call").unwrap();
-        let eg = chain.label_map.get(&eg_label).unwrap().to_owned();
+        let eg_label = String::from_str("BTC-Mini-30JUN2023-10000-Call").unwrap();
+        let eg = chain.by_label(&eg_label).unwrap();
         
-        let mut eg_crawler = Some(Rc::downgrade(&eg));
+        let mut eg_crawler = Some(eg);
         loop {
             if let Some(e) = eg_crawler {
-                let e_borrow = e.upgrade().unwrap();
-                let e_borrow = e_borrow.as_ref().borrow();
-                println!("{}", e_borrow.label); 
-                eg_crawler = e_borrow.up.to_owned();
+                println!("{}", e.label); 
+                eg_crawler = chain.up(e);
             } else {
                 break;
             }
@@ -286,16 +469,14 @@ mod tests {
     fn crawl_down() {
         let chain = LedgerXOptionsChain::build("CBTC");
         
-        let eg_label = String::from_str("BTC-Mini-30JUN2023-10000-Put").unwrap();
-        let eg = chain.label_map.get(&eg_label).unwrap().to_owned();
+        let eg_label = String::from_str("BTC-Mini-30JUN2023-200000-Put").unwrap();
+        let eg = chain.by_label(&eg_label).unwrap();
         
-        let mut eg_crawler = Some(Rc::downgrade(&eg));
+        let mut eg_crawler = Some(eg);
         loop {
             if let Some(e) = eg_crawler {
-                let e_borrow = e.upgrade().unwrap();
-                let e_borrow = e_borrow.as_ref().borrow();
-                println!("{}", e_borrow.label); 
-                eg_crawler = e_borrow.down.to_owned();
+                println!("{}", e.label); 
+                eg_crawler = chain.down(e);
             } else {
                 break;
             }
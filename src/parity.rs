@@ -0,0 +1,261 @@
+use std::collections::BTreeMap;
+
+use chrono::{offset::Utc, DateTime, Duration};
+use serde::Deserialize;
+
+use crate::options_chain::{LedgerXExpiryBlock, LedgerXOptionsChain};
+
+// LedgerX quotes whole dollars, so call minus put at mids is only good to about a dollar;
+// residuals smaller than that are ticks, not bad quotes
+const MIN_DEVIATION: f64 = 1.0;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ParityConfig {
+    // price each expiry at the rate its own quotes imply, where it can be fit, rather
+    // than at the carry model's rates
+    pub use_implied_rates: bool,
+    // strikes with both legs two-sided, after outliers are dropped, needed to fit an expiry
+    pub min_strikes: usize,
+    // strikes further than this many deviations off the fitted line are dropped
+    pub max_deviations: f64,
+}
+
+impl Default for ParityConfig {
+    fn default() -> Self {
+        Self { use_implied_rates: false, min_strikes: 4, max_deviations: 3.0 }
+    }
+}
+
+impl ParityConfig {
+    pub fn check(&self) -> Result<(), String> {
+        if self.min_strikes < 3 {
+            return Err(format!("parity.min_strikes must be at least 3, got {}", self.min_strikes));
+        }
+        if !self.max_deviations.is_finite() || self.max_deviations <= 0.0 {
+            return Err(format!("parity.max_deviations must be positive, got {}", self.max_deviations));
+        }
+        Ok(())
+    }
+}
+
+// what put-call parity says about one expiry
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ParityFit {
+    pub tte: f64,
+    pub forward: f64,
+    // to expiry, and the continuously compounded annual rate it comes to
+    pub discount: f64,
+    pub rate: f64,
+    // strikes the fit was made from, and those dropped as outliers
+    pub strikes: usize,
+    pub outliers: usize,
+}
+
+// C - P = df·(F - K) at every strike, so call minus put against strike is a line with slope
+// -df and intercept df·F. Points too far off the line, measured in median absolute
+// deviations of the residuals, are dropped and the line refit until none are.
+pub fn fit_parity(points: &[(f64, f64)], tte: f64, cfg: &ParityConfig) -> Option<ParityFit> {
+    if tte <= 0.0 {
+        return None;
+    }
+    let mut kept = points.to_vec();
+    loop {
+        if kept.len() < cfg.min_strikes {
+            return None;
+        }
+        let (intercept, slope) = least_squares(&kept)?;
+        let residuals: Vec<f64> = kept.iter().map(|&(k, cp)| cp - (intercept + slope * k)).collect();
+        let centre = median(residuals.clone());
+        // scaled so it matches the standard deviation of normal residuals
+        let deviation = (1.4826 * median(residuals.iter().map(|r| (r - centre).abs()).collect())).max(MIN_DEVIATION);
+
+        let before = kept.len();
+        let mut residuals = residuals.into_iter();
+        kept.retain(|_| (residuals.next().unwrap() - centre).abs() <= cfg.max_deviations * deviation);
+        if kept.len() < before {
+            continue;
+        }
+
+        let discount = -slope;
+        if discount <= 0.0 {
+            return None;
+        }
+        return Some(ParityFit {
+            tte,
+            forward: intercept / discount,
+            discount,
+            rate: -discount.ln() / tte,
+            strikes: kept.len(),
+            outliers: points.len() - kept.len(),
+        });
+    }
+}
+
+// (intercept, slope) of y on x
+fn least_squares(points: &[(f64, f64)]) -> Option<(f64, f64)> {
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|p| p.0).sum::<f64>() / n;
+    let mean_y = points.iter().map(|p| p.1).sum::<f64>() / n;
+    let sxx: f64 = points.iter().map(|p| (p.0 - mean_x).powi(2)).sum();
+    let sxy: f64 = points.iter().map(|p| (p.0 - mean_x) * (p.1 - mean_y)).sum();
+    if sxx == 0.0 {
+        return None;
+    }
+    let slope = sxy / sxx;
+    Some((mean_y - slope * mean_x, slope))
+}
+
+fn median(mut xs: Vec<f64>) -> f64 {
+    xs.sort_by(|a, b| a.total_cmp(b));
+    let mid = xs.len() / 2;
+    if xs.len().is_multiple_of(2) { (xs[mid - 1] + xs[mid]) / 2.0 } else { xs[mid] }
+}
+
+// (strike, mid call - mid put) at every strike of the expiry with both legs two-sided
+pub fn parity_points(chain: &LedgerXOptionsChain, block: &LedgerXExpiryBlock) -> Vec<(f64, f64)> {
+    let mid = |idx| {
+        let contract = chain.get(idx);
+        match (contract.bid, contract.ask) {
+            (Some(bid), Some(ask)) => Some((bid + ask) / 2.0),
+            _ => None,
+        }
+    };
+    block.iter_up()
+        .filter_map(|level| match (level.call.and_then(mid), level.put.and_then(mid)) {
+            (Some(call), Some(put)) => Some((level.strike() as f64, call - put)),
+            _ => None,
+        })
+        .collect()
+}
+
+// the latest fit of every expiry that has one
+#[derive(Debug, Default)]
+pub struct ImpliedCurve {
+    fits: BTreeMap<DateTime<Utc>, ParityFit>,
+    // when each expiry was last refit, whether or not that found a fit
+    refit_at: BTreeMap<DateTime<Utc>, DateTime<Utc>>,
+    // the fitted rate each expiry was last priced at
+    priced: BTreeMap<DateTime<Utc>, f64>,
+}
+
+impl ImpliedCurve {
+    // refit one expiry from the chain's current quotes; true if that changed its fit
+    pub fn refit(&mut self, chain: &LedgerXOptionsChain, expiry: DateTime<Utc>, cfg: &ParityConfig, now: DateTime<Utc>) -> bool {
+        self.refit_at.insert(expiry, now);
+        let fit = chain.get_expiry(expiry).and_then(|block| {
+            let tte = block.iter_up().find_map(|level| level.call.or(level.put)).map(|idx| chain.get(idx).tte)?;
+            fit_parity(&parity_points(chain, block), tte, cfg)
+        });
+        let old = match fit {
+            Some(fit) => self.fits.insert(expiry, fit),
+            None => self.fits.remove(&expiry),
+        };
+        old != fit
+    }
+
+    // true unless the expiry was refit less than `interval` before `now`
+    pub fn refit_due(&self, expiry: DateTime<Utc>, now: DateTime<Utc>, interval: Duration) -> bool {
+        !matches!(self.refit_at.get(&expiry), Some(&at) if now - at < interval)
+    }
+
+    // true if the expiry's rate has moved more than `tolerance` since it was last priced,
+    // or it gained or lost its fit; it then counts as priced at the current rate
+    pub fn take_repricing(&mut self, expiry: DateTime<Utc>, tolerance: f64) -> bool {
+        let rate = self.rate(expiry);
+        let moved = match (rate, self.priced.get(&expiry)) {
+            (Some(rate), Some(&priced)) => (rate - priced).abs() > tolerance,
+            (None, None) => false,
+            _ => true,
+        };
+        if moved {
+            match rate {
+                Some(rate) => self.priced.insert(expiry, rate),
+                None => self.priced.remove(&expiry),
+            };
+        }
+        moved
+    }
+
+    pub fn get(&self, expiry: DateTime<Utc>) -> Option<&ParityFit> {
+        self.fits.get(&expiry)
+    }
+
+    pub fn rate(&self, expiry: DateTime<Utc>) -> Option<f64> {
+        self.get(expiry).map(|fit| fit.rate)
+    }
+
+    // nearest expiry first
+    pub fn iter(&self) -> impl Iterator<Item = (&DateTime<Utc>, &ParityFit)> + '_ {
+        self.fits.iter()
+    }
+
+    pub fn clear(&mut self) {
+        self.fits.clear();
+        self.refit_at.clear();
+        self.priced.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, Utc};
+
+    use super::{fit_parity, ImpliedCurve, ParityConfig, ParityFit};
+
+    // call minus put at each strike for a forward and rate
+    fn parity_line(forward: f64, rate: f64, tte: f64, strikes: &[f64]) -> Vec<(f64, f64)> {
+        let df = (-rate * tte).exp();
+        strikes.iter().map(|&k| (k, df * (forward - k))).collect()
+    }
+
+    #[test]
+    fn recovers_forward_and_rate() {
+        let strikes = [18000.0, 19000.0, 20000.0, 21000.0, 22000.0];
+        let points = parity_line(20150.0, 0.045, 0.25, &strikes);
+        let fit = fit_parity(&points, 0.25, &ParityConfig::default()).unwrap();
+        assert!((fit.forward - 20150.0).abs() < 1e-6);
+        assert!((fit.rate - 0.045).abs() < 1e-9);
+        assert_eq!((fit.strikes, fit.outliers), (5, 0));
+
+        // too few strikes to trust
+        assert!(fit_parity(&points[..3], 0.25, &ParityConfig::default()).is_none());
+        assert!(fit_parity(&points, 0.0, &ParityConfig::default()).is_none());
+    }
+
+    #[test]
+    fn stale_strike_dropped() {
+        let strikes = [16000.0, 17000.0, 18000.0, 19000.0, 20000.0, 21000.0, 22000.0];
+        let mut points = parity_line(20150.0, 0.045, 0.25, &strikes);
+        // a little noise from the quotes, and one strike whose legs are way off
+        for (i, p) in points.iter_mut().enumerate() {
+            p.1 += [0.5, -0.5, 0.25, 0.0, -0.25, 0.5, -0.5][i];
+        }
+        points[1].1 += 400.0;
+
+        let fit = fit_parity(&points, 0.25, &ParityConfig::default()).unwrap();
+        assert_eq!((fit.strikes, fit.outliers), (6, 1));
+        assert!((fit.forward - 20150.0).abs() < 5.0);
+        assert!((fit.rate - 0.045).abs() < 0.001);
+    }
+
+    #[test]
+    fn repriced_only_past_tolerance() {
+        let expiry = DateTime::<Utc>::MIN_UTC;
+        let fit = |rate: f64| ParityFit { tte: 0.25, forward: 20150.0, discount: (-rate * 0.25).exp(), rate, strikes: 5, outliers: 0 };
+        let mut curve = ImpliedCurve::default();
+        assert!(!curve.take_repricing(expiry, 1e-4));
+
+        curve.fits.insert(expiry, fit(0.045));
+        assert!(curve.take_repricing(expiry, 1e-4));
+        curve.fits.insert(expiry, fit(0.04505));
+        assert!(!curve.take_repricing(expiry, 1e-4));
+        // measured from where it was last priced, not from the last fit
+        curve.fits.insert(expiry, fit(0.04511));
+        assert!(curve.take_repricing(expiry, 1e-4));
+
+        curve.fits.clear();
+        assert!(curve.take_repricing(expiry, 1e-4));
+        assert!(!curve.take_repricing(expiry, 1e-4));
+    }
+}
@@ -0,0 +1,341 @@
+use std::collections::HashMap;
+
+use chrono::{offset::Utc, DateTime};
+
+use crate::options_chain::LedgerXOptionsChain;
+use crate::strat::Trade;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PositionView {
+    // what has actually been filled
+    Filled,
+    // filled plus everything still working at the venues
+    Committed,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Position<T> {
+    pub filled: T,
+    pub working: T,
+}
+
+impl Position<i64> {
+    fn view(&self, view: PositionView) -> i64 {
+        match view {
+            PositionView::Filled => self.filled,
+            PositionView::Committed => self.filled + self.working,
+        }
+    }
+}
+
+impl Position<f64> {
+    fn view(&self, view: PositionView) -> f64 {
+        match view {
+            PositionView::Filled => self.filled,
+            PositionView::Committed => self.filled + self.working,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ContractMeta {
+    pub expiry: DateTime<Utc>,
+    pub strike: u64,
+    pub is_call: bool,
+    pub multiplier: f64,
+}
+
+// exposure at one strike/expiry, in units of the underlying
+#[derive(Debug, Clone, PartialEq)]
+pub struct LevelExposure {
+    pub expiry: DateTime<Utc>,
+    pub strike: u64,
+    // +1 is long call / short put, i.e. a synthetic long
+    pub synthetic: f64,
+    // option exposure not paired off against the other side
+    pub unpaired_call: f64,
+    pub unpaired_put: f64,
+}
+
+// positions of a single underlying across LedgerX contracts and Binance spot
+#[derive(Debug, Default)]
+pub struct PositionBook {
+    // in contracts, positive is long
+    pub options: HashMap<u64, Position<i64>>,
+    // in units of the underlying, positive is long
+    pub spot: HashMap<String, Position<f64>>,
+    meta: HashMap<u64, ContractMeta>,
+}
+
+fn signed_contracts(is_ask: bool, size: u64) -> i64 {
+    if is_ask { -(size as i64) } else { size as i64 }
+}
+
+fn signed_qty(is_buy: bool, qty: f64) -> f64 {
+    if is_buy { qty } else { -qty }
+}
+
+// shrink `working` toward zero by `amount` without flipping its sign
+fn release<T>(working: T, amount: T) -> T
+where
+    T: PartialOrd + std::ops::Sub<Output = T> + Default + Copy,
+{
+    let zero = T::default();
+    let left = working - amount;
+    if (working > zero && left < zero) || (working < zero && left > zero) { zero } else { left }
+}
+
+impl PositionBook {
+    pub fn from_chain(chain: &LedgerXOptionsChain) -> Self {
+        let mut book = Self::default();
+        for contr in chain.contracts.iter() {
+            book.meta.insert(contr.id, ContractMeta {
+                expiry: contr.spec.date_expires,
+                strike: contr.strike,
+                is_call: contr.is_call,
+                multiplier: contr.spec.multiplier,
+            });
+        }
+        book
+    }
+
+    // every leg of a freshly generated trade is working until it fills or is cancelled
+    pub fn submit_trade(&mut self, t: &Trade) {
+        for order in t.binance.iter() {
+            self.spot.entry(order.symbol.to_owned()).or_default().working += signed_qty(order.is_buy, order.qty);
+        }
+        for order in t.ledgerx.iter() {
+            self.options.entry(order.contract_id).or_default().working += signed_contracts(order.is_ask, order.size);
+        }
+    }
+
+    pub fn fill_ledgerx(&mut self, contract_id: u64, is_ask: bool, size: u64) {
+        let pos = self.options.entry(contract_id).or_default();
+        let amount = signed_contracts(is_ask, size);
+        pos.working = release(pos.working, amount);
+        pos.filled += amount;
+    }
+
+    pub fn cancel_ledgerx(&mut self, contract_id: u64, is_ask: bool, size: u64) {
+        let pos = self.options.entry(contract_id).or_default();
+        pos.working = release(pos.working, signed_contracts(is_ask, size));
+    }
+
+    // a fill on an order the book never counted as working, like an unwind's
+    pub fn book_ledgerx(&mut self, contract_id: u64, is_ask: bool, size: u64) {
+        self.options.entry(contract_id).or_default().filled += signed_contracts(is_ask, size);
+    }
+
+    pub fn fill_binance(&mut self, symbol: &str, is_buy: bool, qty: f64) {
+        let pos = self.spot.entry(symbol.to_owned()).or_default();
+        let amount = signed_qty(is_buy, qty);
+        pos.working = release(pos.working, amount);
+        pos.filled += amount;
+    }
+
+    pub fn cancel_binance(&mut self, symbol: &str, is_buy: bool, qty: f64) {
+        let pos = self.spot.entry(symbol.to_owned()).or_default();
+        pos.working = release(pos.working, signed_qty(is_buy, qty));
+    }
+
+    pub fn book_binance(&mut self, symbol: &str, is_buy: bool, qty: f64) {
+        self.spot.entry(symbol.to_owned()).or_default().filled += signed_qty(is_buy, qty);
+    }
+
+    pub fn contract_meta(&self, contract_id: u64) -> Option<&ContractMeta> {
+        self.meta.get(&contract_id)
+    }
+
+    pub fn working_binance(&self, symbol: &str) -> f64 {
+        self.spot.get(symbol).map(|p| p.working).unwrap_or(0.0)
+    }
+
+    pub fn level_exposure(&self, expiry: DateTime<Utc>, strike: u64, view: PositionView) -> LevelExposure {
+        self.level_exposure_with(&Trade::empty(), expiry, strike, view)
+    }
+
+    // as `level_exposure`, with `pending` counted as working on top of what's been submitted
+    pub fn level_exposure_with(&self, pending: &Trade, expiry: DateTime<Utc>, strike: u64, view: PositionView) -> LevelExposure {
+        let mut call = 0.0;
+        let mut put = 0.0;
+        for (id, pos) in self.options.iter() {
+            if let Some(meta) = self.meta.get(id) {
+                if meta.expiry == expiry && meta.strike == strike {
+                    let qty = pos.view(view) as f64 / meta.multiplier;
+                    if meta.is_call { call += qty } else { put += qty }
+                }
+            }
+        }
+        if view == PositionView::Committed {
+            for order in pending.ledgerx.iter() {
+                if let Some(meta) = self.meta.get(&order.contract_id).filter(|m| m.expiry == expiry && m.strike == strike) {
+                    let qty = signed_contracts(order.is_ask, order.size) as f64 / meta.multiplier;
+                    if meta.is_call { call += qty } else { put += qty }
+                }
+            }
+        }
+
+        let synthetic = if call > 0.0 && put < 0.0 {
+            call.min(-put)
+        } else if call < 0.0 && put > 0.0 {
+            -(-call).min(put)
+        } else {
+            0.0
+        };
+
+        LevelExposure {
+            expiry,
+            strike,
+            synthetic,
+            unpaired_call: call - synthetic,
+            unpaired_put: put + synthetic,
+        }
+    }
+
+    // true while an order on either option at the level is still working
+    pub fn level_working(&self, expiry: DateTime<Utc>, strike: u64) -> bool {
+        self.options.iter().any(|(id, pos)| {
+            pos.working != 0 && self.meta.get(id).is_some_and(|meta| meta.expiry == expiry && meta.strike == strike)
+        })
+    }
+
+    pub fn level_working_with(&self, pending: &Trade, expiry: DateTime<Utc>, strike: u64) -> bool {
+        self.level_working(expiry, strike) || pending.ledgerx.iter().any(|order| {
+            self.meta.get(&order.contract_id).is_some_and(|meta| meta.expiry == expiry && meta.strike == strike)
+        })
+    }
+
+    pub fn exposures(&self, view: PositionView) -> Vec<LevelExposure> {
+        let mut levels: Vec<(DateTime<Utc>, u64)> = self.options.keys()
+            .filter_map(|id| self.meta.get(id))
+            .map(|meta| (meta.expiry, meta.strike))
+            .collect();
+        levels.sort();
+        levels.dedup();
+
+        levels.into_iter()
+            .map(|(expiry, strike)| self.level_exposure(expiry, strike, view))
+            .collect()
+    }
+
+    pub fn net_synthetic(&self, view: PositionView) -> f64 {
+        self.exposures(view).iter().map(|l| l.synthetic).sum()
+    }
+
+    pub fn net_spot(&self, view: PositionView) -> f64 {
+        self.spot.values().map(|p| p.view(view)).sum()
+    }
+
+    // (symbol, coin held), negative where it's short
+    pub fn spot_positions(&self, view: PositionView) -> impl Iterator<Item = (&str, f64)> + '_ {
+        self.spot.iter().map(move |(symbol, p)| (symbol.as_str(), p.view(view)))
+    }
+
+    // spot plus synthetic forwards; unpaired options are reported by `exposures`
+    pub fn net_delta(&self, view: PositionView) -> f64 {
+        self.net_spot(view) + self.net_synthetic(view)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::rc::Rc;
+
+    use chrono::{Utc, DateTime};
+    use ftx_us_derivs::order::Order;
+    use ftx_us_derivs::table::{ContractSpecTable, OptionContractSpec, ContractSpec};
+
+    use crate::options_chain::LedgerXOptionsChain;
+    use crate::strat::{BinanceMarketOrder, Trade};
+    use super::{PositionBook, PositionView};
+
+    fn mock_book() -> PositionBook {
+        let spec = |id: u64, is_call: bool| OptionContractSpec {
+            id,
+            label: format!("BTC-Mini-30JUN2023-10000-{}", if is_call { "Call" } else { "Put" }),
+            underlying: "CBTC".to_string(),
+            strike_price: 10000,
+            is_call,
+            tte: 0.66,
+            open_interest: 0,
+            multiplier: 100.0,
+            min_increment: 1.0,
+            active: true,
+            date_live: DateTime::<Utc>::MIN_UTC,
+            date_expires: DateTime::<Utc>::MIN_UTC,
+            collateral_asset: "USD".to_string(),
+            is_ecp_only: false,
+        };
+        let table = ContractSpecTable {
+            id_table: HashMap::from_iter([
+                (1, Rc::new(ContractSpec::Option(spec(1, true)))),
+                (2, Rc::new(ContractSpec::Option(spec(2, false)))),
+            ]),
+            label_table: HashMap::new(),
+        };
+        PositionBook::from_chain(&LedgerXOptionsChain::from_spec_table("CBTC", table))
+    }
+
+    // buy 0.5 spot, sell 50 calls, buy 50 puts
+    fn conversion() -> Trade {
+        Trade {
+            binance: vec![BinanceMarketOrder { symbol: "BTCUSDT".to_string(), is_buy: true, qty: 0.5, price: 0.0 }],
+            ledgerx: vec![Order::new(1, true, 0.0, 50), Order::new(2, false, 0.0, 50)],
+        }
+    }
+
+    #[test]
+    fn working_then_filled() {
+        let mut book = mock_book();
+        book.submit_trade(&conversion());
+
+        let committed = book.level_exposure(DateTime::<Utc>::MIN_UTC, 10000, PositionView::Committed);
+        assert_eq!(committed.synthetic, -0.5);
+        assert!(book.level_working(DateTime::<Utc>::MIN_UTC, 10000));
+        assert_eq!(book.net_delta(PositionView::Committed), 0.0);
+        assert_eq!(book.net_delta(PositionView::Filled), 0.0);
+
+        book.fill_binance("BTCUSDT", true, 0.5);
+        book.fill_ledgerx(1, true, 50);
+        assert_eq!(book.net_delta(PositionView::Filled), 0.5);
+
+        let filled = book.level_exposure(DateTime::<Utc>::MIN_UTC, 10000, PositionView::Filled);
+        assert_eq!(filled.synthetic, 0.0);
+        assert_eq!(filled.unpaired_call, -0.5);
+
+        book.fill_ledgerx(2, false, 50);
+        assert_eq!(book.net_delta(PositionView::Filled), 0.0);
+        assert_eq!(book.exposures(PositionView::Filled).len(), 1);
+        assert_eq!(book.options[&1].working, 0);
+    }
+
+    #[test]
+    fn cancel_releases_working() {
+        let mut book = mock_book();
+        book.submit_trade(&conversion());
+        book.cancel_binance("BTCUSDT", true, 0.5);
+        book.cancel_ledgerx(1, true, 50);
+        book.cancel_ledgerx(2, false, 80);
+
+        assert_eq!(book.working_binance("BTCUSDT"), 0.0);
+        assert_eq!(book.options[&2].working, 0);
+        assert!(!book.level_working(DateTime::<Utc>::MIN_UTC, 10000));
+        assert_eq!(book.net_delta(PositionView::Committed), 0.0);
+    }
+
+    #[test]
+    fn unwind_fill_leaves_working_alone() {
+        let mut book = mock_book();
+        book.submit_trade(&conversion());
+        book.fill_binance("BTCUSDT", true, 0.5);
+
+        // the orders flattening a legged combo were never working in the book
+        book.book_binance("BTCUSDT", false, 0.5);
+        book.book_ledgerx(1, false, 20);
+        assert_eq!(book.working_binance("BTCUSDT"), 0.0);
+        assert_eq!(book.spot["BTCUSDT"].filled, 0.0);
+        assert_eq!(book.options[&1].working, -50);
+        assert_eq!(book.options[&1].filled, 20);
+    }
+}
@@ -0,0 +1,392 @@
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use binance::model::{Asks, Bids, BookTickerEvent, DepthOrderBookEvent};
+use binance::websockets::WebsocketEvent;
+use chrono::{offset::Utc, DateTime, TimeZone};
+use ftx_us_derivs::table::OptionContractSpec;
+use ftx_us_derivs::ws::{BookTop, WebSocketMsg};
+use serde::{Deserialize, Serialize};
+
+use crate::book::{BookUpdate, SpotDepthSnapshot};
+use crate::carry::FundingUpdate;
+use crate::feeds::FeedVenue;
+use crate::{StampedMsg, UniversalMsgWrapper};
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct RecordingConfig {
+    // market data is appended here as JSON lines; nothing is recorded if unset
+    pub path: Option<String>,
+}
+
+// the parts of a feed message the strategy reads, in a form we can write out
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum RecordedEvent {
+    BookTicker {
+        update_id: u64,
+        symbol: String,
+        best_bid: String,
+        best_bid_qty: String,
+        best_ask: String,
+        best_ask_qty: String,
+    },
+    DepthUpdate {
+        symbol: String,
+        event_time: u64,
+        first_update_id: u64,
+        final_update_id: u64,
+        // (price, qty)
+        bids: Vec<(f64, f64)>,
+        asks: Vec<(f64, f64)>,
+    },
+    DepthSnapshot {
+        snapshot: SpotDepthSnapshot,
+    },
+    BookTop {
+        contract_id: u64,
+        bid: f64,
+        bid_size: u64,
+        ask: f64,
+        ask_size: u64,
+        contract_type: u64,
+        clock: u64,
+    },
+    LedgerXBook {
+        update: BookUpdate,
+    },
+    FeedReset {
+        venue: FeedVenue,
+    },
+    Funding {
+        update: FundingUpdate,
+    },
+    // written at startup so a replay can rebuild chains that have since expired
+    Contract {
+        id: u64,
+        label: String,
+        underlying: String,
+        strike_price: u64,
+        is_call: bool,
+        tte: f64,
+        open_interest: u64,
+        multiplier: f64,
+        min_increment: f64,
+        active: bool,
+        date_live_us: i64,
+        date_expires_us: i64,
+        collateral_asset: String,
+        is_ecp_only: bool,
+    },
+}
+
+fn from_micros(us: i64) -> DateTime<Utc> {
+    Utc.timestamp_nanos(us * 1000)
+}
+
+// one line of the log
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Record {
+    // receive time, microseconds since the unix epoch
+    pub recv_us: i64,
+    #[serde(flatten)]
+    pub event: RecordedEvent,
+}
+
+impl Record {
+    // None for messages the strategy ignores (heartbeats, other Binance streams, ...)
+    pub fn from_msg(msg: &UniversalMsgWrapper, recv_time: DateTime<Utc>) -> Option<Self> {
+        let event = match msg {
+            UniversalMsgWrapper::Binance(WebsocketEvent::BookTicker(bt)) => RecordedEvent::BookTicker {
+                update_id: bt.update_id,
+                symbol: bt.symbol.to_owned(),
+                best_bid: bt.best_bid.to_owned(),
+                best_bid_qty: bt.best_bid_qty.to_owned(),
+                best_ask: bt.best_ask.to_owned(),
+                best_ask_qty: bt.best_ask_qty.to_owned(),
+            },
+            UniversalMsgWrapper::Binance(WebsocketEvent::DepthOrderBook(depth)) => RecordedEvent::DepthUpdate {
+                symbol: depth.symbol.to_owned(),
+                event_time: depth.event_time,
+                first_update_id: depth.first_update_id,
+                final_update_id: depth.final_update_id,
+                bids: depth.bids.iter().map(|l| (l.price, l.qty)).collect(),
+                asks: depth.asks.iter().map(|l| (l.price, l.qty)).collect(),
+            },
+            UniversalMsgWrapper::BinanceDepth(snapshot) => RecordedEvent::DepthSnapshot { snapshot: snapshot.to_owned() },
+            UniversalMsgWrapper::LedgerX(WebSocketMsg::BookTop(bt)) => RecordedEvent::BookTop {
+                contract_id: bt.contract_id,
+                bid: bt.bid,
+                bid_size: bt.bid_size,
+                ask: bt.ask,
+                ask_size: bt.ask_size,
+                contract_type: bt.contract_type,
+                clock: bt.clock,
+            },
+            UniversalMsgWrapper::LedgerXBook(update) => RecordedEvent::LedgerXBook { update: update.to_owned() },
+            UniversalMsgWrapper::FeedReset(venue) => RecordedEvent::FeedReset { venue: *venue },
+            UniversalMsgWrapper::Funding(update) => RecordedEvent::Funding { update: update.to_owned() },
+            _ => return None,
+        };
+        Some(Self { recv_us: recv_time.timestamp_micros(), event })
+    }
+
+    pub fn contract(spec: &OptionContractSpec, recv_time: DateTime<Utc>) -> Self {
+        let event = RecordedEvent::Contract {
+            id: spec.id,
+            label: spec.label.to_owned(),
+            underlying: spec.underlying.to_owned(),
+            strike_price: spec.strike_price,
+            is_call: spec.is_call,
+            tte: spec.tte,
+            open_interest: spec.open_interest,
+            multiplier: spec.multiplier,
+            min_increment: spec.min_increment,
+            active: spec.active,
+            date_live_us: spec.date_live.timestamp_micros(),
+            date_expires_us: spec.date_expires.timestamp_micros(),
+            collateral_asset: spec.collateral_asset.to_owned(),
+            is_ecp_only: spec.is_ecp_only,
+        };
+        Self { recv_us: recv_time.timestamp_micros(), event }
+    }
+
+    pub fn recv_time(&self) -> DateTime<Utc> {
+        from_micros(self.recv_us)
+    }
+
+    pub fn option_spec(&self) -> Option<OptionContractSpec> {
+        match &self.event {
+            RecordedEvent::Contract {
+                id, label, underlying, strike_price, is_call, tte, open_interest, multiplier,
+                min_increment, active, date_live_us, date_expires_us, collateral_asset, is_ecp_only,
+            } => Some(OptionContractSpec {
+                id: *id,
+                label: label.to_owned(),
+                underlying: underlying.to_owned(),
+                strike_price: *strike_price,
+                is_call: *is_call,
+                tte: *tte,
+                open_interest: *open_interest,
+                multiplier: *multiplier,
+                min_increment: *min_increment,
+                active: *active,
+                date_live: from_micros(*date_live_us),
+                date_expires: from_micros(*date_expires_us),
+                collateral_asset: collateral_asset.to_owned(),
+                is_ecp_only: *is_ecp_only,
+            }),
+            _ => None,
+        }
+    }
+
+    // None for contract records, which aren't feed messages
+    pub fn into_msg(self) -> Option<StampedMsg> {
+        let recv_time = self.recv_time();
+        let msg = match self.event {
+            RecordedEvent::BookTicker { update_id, symbol, best_bid, best_bid_qty, best_ask, best_ask_qty } => {
+                UniversalMsgWrapper::Binance(WebsocketEvent::BookTicker(BookTickerEvent {
+                    update_id, symbol, best_bid, best_bid_qty, best_ask, best_ask_qty,
+                }))
+            },
+            RecordedEvent::DepthUpdate { symbol, event_time, first_update_id, final_update_id, bids, asks } => {
+                UniversalMsgWrapper::Binance(WebsocketEvent::DepthOrderBook(DepthOrderBookEvent {
+                    event_type: "depthUpdate".to_string(),
+                    event_time,
+                    symbol,
+                    first_update_id,
+                    final_update_id,
+                    bids: bids.into_iter().map(|(price, qty)| Bids { price, qty }).collect(),
+                    asks: asks.into_iter().map(|(price, qty)| Asks { price, qty }).collect(),
+                }))
+            },
+            RecordedEvent::DepthSnapshot { snapshot } => UniversalMsgWrapper::BinanceDepth(snapshot),
+            RecordedEvent::BookTop { contract_id, bid, bid_size, ask, ask_size, contract_type, clock } => {
+                UniversalMsgWrapper::LedgerX(WebSocketMsg::BookTop(BookTop {
+                    bid, bid_size, ask, ask_size, contract_id, contract_type, clock,
+                }))
+            },
+            RecordedEvent::LedgerXBook { update } => UniversalMsgWrapper::LedgerXBook(update),
+            RecordedEvent::FeedReset { venue } => UniversalMsgWrapper::FeedReset(venue),
+            RecordedEvent::Funding { update } => UniversalMsgWrapper::Funding(update),
+            RecordedEvent::Contract { .. } => return None,
+        };
+        Some(StampedMsg { recv_time, msg })
+    }
+}
+
+#[derive(Debug)]
+pub enum RecordError {
+    Io(io::Error),
+    Parse { line: usize, err: serde_json::Error },
+}
+
+impl fmt::Display for RecordError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RecordError::Io(e) => write!(f, "market data log: {}", e),
+            RecordError::Parse { line, err } => write!(f, "market data log line {}: {}", line, err),
+        }
+    }
+}
+
+impl From<io::Error> for RecordError {
+    fn from(e: io::Error) -> Self {
+        RecordError::Io(e)
+    }
+}
+
+// appends to the log; writes are buffered, so call `flush` when the loop is idle
+pub struct Recorder<W: Write> {
+    out: BufWriter<W>,
+    pub written: u64,
+}
+
+impl Recorder<File> {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self::new(file))
+    }
+}
+
+impl<W: Write> Recorder<W> {
+    pub fn new(out: W) -> Self {
+        Self { out: BufWriter::new(out), written: 0 }
+    }
+
+    pub fn record(&mut self, msg: &UniversalMsgWrapper, recv_time: DateTime<Utc>) -> Result<(), RecordError> {
+        match Record::from_msg(msg, recv_time) {
+            Some(record) => self.write(&record),
+            None => Ok(()),
+        }
+    }
+
+    pub fn record_contracts<'a, I>(&mut self, specs: I, recv_time: DateTime<Utc>) -> Result<(), RecordError>
+    where
+        I: IntoIterator<Item = &'a OptionContractSpec>,
+    {
+        for spec in specs {
+            self.write(&Record::contract(spec, recv_time))?;
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, record: &Record) -> Result<(), RecordError> {
+        serde_json::to_writer(&mut self.out, record).map_err(io::Error::from)?;
+        self.out.write_all(b"\n")?;
+        self.written += 1;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<(), RecordError> {
+        Ok(self.out.flush()?)
+    }
+
+    pub fn into_inner(self) -> Result<W, RecordError> {
+        self.out.into_inner().map_err(|e| RecordError::Io(e.into_error()))
+    }
+}
+
+// yields records in file order; blank lines are skipped
+pub struct LogReader<R: BufRead> {
+    lines: io::Lines<R>,
+    line: usize,
+}
+
+impl LogReader<BufReader<File>> {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+impl<R: BufRead> LogReader<R> {
+    pub fn new(input: R) -> Self {
+        Self { lines: input.lines(), line: 0 }
+    }
+}
+
+impl<R: BufRead> Iterator for LogReader<R> {
+    type Item = Result<Record, RecordError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let text = match self.lines.next()? {
+                Ok(text) => text,
+                Err(e) => return Some(Err(e.into())),
+            };
+            self.line += 1;
+            if text.trim().is_empty() {
+                continue;
+            }
+            let line = self.line;
+            return Some(serde_json::from_str(&text).map_err(|err| RecordError::Parse { line, err }));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use binance::model::BookTickerEvent;
+    use binance::websockets::WebsocketEvent;
+    use chrono::{TimeZone, Utc};
+    use ftx_us_derivs::ws::{BookTop, WebSocketMsg};
+
+    use crate::UniversalMsgWrapper;
+    use crate::carry::{FundingKind, FundingUpdate};
+    use crate::feeds::FeedVenue;
+    use super::{LogReader, RecordError, RecordedEvent, Recorder};
+
+    #[test]
+    fn round_trip() {
+        let t0 = Utc.timestamp_millis_opt(1_665_000_000_123).unwrap();
+        let msgs = [
+            UniversalMsgWrapper::Binance(WebsocketEvent::BookTicker(BookTickerEvent {
+                update_id: 7,
+                symbol: "BTCUSDT".to_string(),
+                best_bid: "20449.0".to_string(),
+                best_bid_qty: "1.5".to_string(),
+                best_ask: "20450.0".to_string(),
+                best_ask_qty: "0.2".to_string(),
+            })),
+            UniversalMsgWrapper::LedgerX(WebSocketMsg::BookTop(
+                BookTop { bid: 11070.0, bid_size: 3, ask: 11180.0, ask_size: 1, contract_id: 22248027, contract_type: 0, clock: 42 }
+            )),
+            UniversalMsgWrapper::FeedReset(FeedVenue::LedgerX),
+            UniversalMsgWrapper::Funding(FundingUpdate { kind: FundingKind::Perpetual, symbol: "BTCUSDT".to_string(), rate: 0.0001 }),
+        ];
+
+        let mut recorder = Recorder::new(vec![]);
+        for (i, msg) in msgs.iter().enumerate() {
+            recorder.record(msg, t0 + chrono::Duration::microseconds(i as i64)).unwrap();
+        }
+        assert_eq!(recorder.written, 4);
+        let bytes = recorder.into_inner().unwrap();
+
+        let records: Vec<_> = LogReader::new(&bytes[..]).map(|r| r.unwrap()).collect();
+        assert_eq!(records.len(), 4);
+        assert_eq!(records[1].recv_time(), t0 + chrono::Duration::microseconds(1));
+        assert!(matches!(records[1].event, RecordedEvent::BookTop { contract_id: 22248027, clock: 42, .. }));
+
+        let replayed = records[0].clone().into_msg().unwrap();
+        assert_eq!(replayed.recv_time, t0);
+        match replayed.msg {
+            UniversalMsgWrapper::Binance(WebsocketEvent::BookTicker(bt)) => assert_eq!(bt.best_bid_qty, "1.5"),
+            other => panic!("unexpected {:?}", other),
+        }
+        match records[3].clone().into_msg().unwrap().msg {
+            UniversalMsgWrapper::Funding(update) => assert_eq!(update.rate, 0.0001),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn bad_line_reports_position() {
+        let log = "{\"recv_us\":1,\"type\":\"FeedReset\",\"venue\":\"Binance\"}\n\nnot json\n";
+        let results: Vec<_> = LogReader::new(log.as_bytes()).collect();
+        assert!(results[0].is_ok());
+        assert!(matches!(results[1], Err(RecordError::Parse { line: 3, .. })));
+    }
+}
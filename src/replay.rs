@@ -0,0 +1,658 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::iter::Peekable;
+use std::rc::Rc;
+use std::str::FromStr;
+use std::time::Instant;
+
+use binance::websockets::WebsocketEvent;
+use chrono::{offset::Utc, DateTime};
+use ftx_us_derivs::table::{ContractSpec, ContractSpecTable};
+use ftx_us_derivs::ws::WebSocketMsg;
+use serde::Serialize;
+use tracing::{info, warn};
+
+use crate::config::{Config, ConfigError, DEFAULT_CONFIG_PATH};
+use crate::dispatch::StratDispatcher;
+use crate::execution::route_trade;
+use crate::fillsim::{BacktestConfig, FillModel, SimBinance, SimLedgerX};
+use crate::logging;
+use crate::legging::{ComboState, LeggingConfig, LeggingManager};
+use crate::recorder::{LogReader, Record, RecordError, RecordedEvent};
+use crate::risk::RiskGate;
+use crate::strat::Trade;
+use crate::{StampedMsg, UniversalMsgWrapper};
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Config(ConfigError),
+    Log(String, RecordError),
+    Io(String, io::Error),
+    Logging(String),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplayError::Config(e) => write!(f, "{}", e),
+            ReplayError::Log(path, e) => write!(f, "{}: {}", path, e),
+            ReplayError::Io(path, e) => write!(f, "{}: {}", path, e),
+            ReplayError::Logging(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ReplayArgs {
+    pub config: String,
+    // generated trades are written here as JSON lines
+    pub trades: Option<String>,
+    pub logs: Vec<String>,
+}
+
+// `[--config <path>] [--trades <path>] <log>...`
+pub fn replay_args_from<I: Iterator<Item = String>>(mut args: I) -> Result<ReplayArgs, String> {
+    let mut config = None;
+    let mut trades = None;
+    let mut logs = vec![];
+    while let Some(arg) = args.next() {
+        if arg == "--config" || arg == "-c" {
+            config = Some(args.next().ok_or_else(|| format!("{} expects a path", arg))?);
+        } else if let Some(p) = arg.strip_prefix("--config=") {
+            config = Some(p.to_owned());
+        } else if arg == "--trades" {
+            trades = Some(args.next().ok_or_else(|| format!("{} expects a path", arg))?);
+        } else if let Some(p) = arg.strip_prefix("--trades=") {
+            trades = Some(p.to_owned());
+        } else if arg.starts_with('-') {
+            return Err(format!("unrecognized argument {:?}", arg));
+        } else {
+            logs.push(arg);
+        }
+    }
+    if logs.is_empty() {
+        return Err("replay expects at least one market data log".to_string());
+    }
+    Ok(ReplayArgs {
+        config: config.unwrap_or_else(|| DEFAULT_CONFIG_PATH.to_owned()),
+        trades,
+        logs,
+    })
+}
+
+// strategy time is whatever the last replayed message says it is
+pub struct SimClock {
+    start: DateTime<Utc>,
+    base: Instant,
+    now: DateTime<Utc>,
+}
+
+impl SimClock {
+    pub fn new(start: DateTime<Utc>) -> Self {
+        Self { start, base: Instant::now(), now: start }
+    }
+
+    // the feed threads stamp independently, so a log can be a little out of order;
+    // time never runs backwards
+    pub fn advance(&mut self, t: DateTime<Utc>) -> DateTime<Utc> {
+        if t > self.now {
+            self.now = t;
+        }
+        self.now
+    }
+
+    pub fn now(&self) -> DateTime<Utc> {
+        self.now
+    }
+
+    // the same moment as an Instant, for the parts that measure time that way
+    pub fn instant(&self) -> Instant {
+        self.base + (self.now - self.start).to_std().unwrap_or_default()
+    }
+}
+
+// interleaves several logs by receive time, assuming each is in order on its own
+pub struct MergedLogs<R: BufRead> {
+    logs: Vec<Peekable<LogReader<R>>>,
+}
+
+impl<R: BufRead> MergedLogs<R> {
+    pub fn new(logs: Vec<LogReader<R>>) -> Self {
+        Self { logs: logs.into_iter().map(|l| l.peekable()).collect() }
+    }
+}
+
+impl<R: BufRead> Iterator for MergedLogs<R> {
+    type Item = Result<Record, RecordError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut earliest: Option<(usize, i64)> = None;
+        for (i, log) in self.logs.iter_mut().enumerate() {
+            match log.peek() {
+                // surface errors as soon as they're seen
+                Some(Err(_)) => return log.next(),
+                Some(Ok(record)) if !matches!(earliest, Some((_, t)) if t <= record.recv_us) => {
+                    earliest = Some((i, record.recv_us));
+                },
+                _ => {},
+            }
+        }
+        self.logs[earliest?.0].next()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ReplaySummary {
+    pub messages: u64,
+    pub trades: u64,
+    pub risk_rejects: u64,
+    // trades that didn't fill in full and had to be unwound
+    pub legged: u64,
+    // trades still waiting on fills when the replay ended
+    pub open_executions: usize,
+    pub start: Option<DateTime<Utc>>,
+    pub end: Option<DateTime<Utc>>,
+    // USD paid (negative) and received over all fills
+    pub cash: f64,
+    // cash plus open positions at the last mid of each instrument
+    pub mark_pnl: f64,
+    // cash plus open positions as if every option expired now against the last spot mid;
+    // for a flat book of combos this is the edge locked in
+    pub intrinsic_pnl: f64,
+    // option contracts held with no two-sided quote to mark them at, valued at zero
+    pub unmarked_contracts: usize,
+}
+
+impl fmt::Display for ReplaySummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let fmt_time = |t: Option<DateTime<Utc>>| t.map(|t| t.to_rfc3339()).unwrap_or_else(|| "-".to_string());
+        writeln!(f, "period:         {} .. {}", fmt_time(self.start), fmt_time(self.end))?;
+        writeln!(f, "messages:       {}", self.messages)?;
+        writeln!(f, "trades:         {} ({} rejected by risk)", self.trades, self.risk_rejects)?;
+        writeln!(f, "legged:         {} unwound, {} still open", self.legged, self.open_executions)?;
+        writeln!(f, "cash:           {:.2}", self.cash)?;
+        writeln!(f, "mark p&l:       {:.2}", self.mark_pnl)?;
+        write!(f, "intrinsic p&l:  {:.2}", self.intrinsic_pnl)?;
+        if self.unmarked_contracts > 0 {
+            write!(f, "\n{} contract(s) held without a quote to mark them at", self.unmarked_contracts)?;
+        }
+        Ok(())
+    }
+}
+
+// the venue sims, with legging handled as it is live
+struct SimFills {
+    binance: SimBinance,
+    ledgerx: SimLedgerX,
+    legging: LeggingManager,
+}
+
+// feeds recorded messages through the same dispatch and risk path as the live loop;
+// without a fill sim every trade that passes risk fills in full at its quoted prices
+pub struct Replay {
+    pub dispatch: StratDispatcher,
+    risk: RiskGate,
+    sim: Option<SimFills>,
+    clock: Option<SimClock>,
+    spot_marks: HashMap<String, f64>,
+    opts_marks: HashMap<u64, f64>,
+    summary: ReplaySummary,
+}
+
+impl Replay {
+    pub fn new(dispatch: StratDispatcher, risk: RiskGate) -> Self {
+        Self {
+            dispatch,
+            risk,
+            sim: None,
+            clock: None,
+            spot_marks: HashMap::new(),
+            opts_marks: HashMap::new(),
+            summary: ReplaySummary::default(),
+        }
+    }
+
+    pub fn with_fill_sim(mut self, cfg: &BacktestConfig, legging: LeggingConfig) -> Self {
+        self.sim = Some(SimFills {
+            binance: SimBinance::new(cfg),
+            ledgerx: SimLedgerX::new(cfg),
+            legging: LeggingManager::new(legging),
+        });
+        self
+    }
+
+    // returns the trade generated by this message, if it passed risk
+    pub fn step(&mut self, stamped: StampedMsg) -> Option<Trade> {
+        let clock = self.clock.get_or_insert_with(|| SimClock::new(stamped.recv_time));
+        let now = clock.advance(stamped.recv_time);
+        let now_instant = clock.instant();
+        self.summary.start.get_or_insert(now);
+        self.summary.end = Some(now);
+        self.summary.messages += 1;
+
+        self.update_marks(&stamped.msg);
+        self.sim_fills(&stamped.msg, now, now_instant);
+
+        let out = self.dispatch.process(stamped.msg, now).and_then(|t| {
+            if let Err(reason) = self.risk.check(&t, &self.dispatch.books(), now_instant) {
+                info!(sim_time = %now.to_rfc3339(), %reason, "risk rejected trade");
+                self.summary.risk_rejects += 1;
+                self.dispatch.release(&t);
+                return None;
+            }
+            self.summary.trades += 1;
+            Some(t)
+        });
+
+        match &mut self.sim {
+            Some(SimFills { binance, ledgerx, legging }) => {
+                if let Some(t) = &out {
+                    let report = route_trade(t, binance, ledgerx);
+                    legging.track(t.clone(), report, now_instant);
+                }
+                for exec in legging.poll(now_instant, binance, ledgerx) {
+                    self.dispatch.settle(&exec);
+                    if exec.state != ComboState::Filled {
+                        self.summary.legged += 1;
+                    }
+                }
+                self.summary.open_executions = legging.active.len();
+            },
+            None => {
+                if let Some(t) = &out {
+                    self.dispatch.fill(t);
+                    self.summary.cash += self.cash_flow(t);
+                }
+            },
+        }
+        out
+    }
+
+    // execute whatever the venue sims have due and hand the fills to legging
+    fn sim_fills(&mut self, msg: &UniversalMsgWrapper, now: DateTime<Utc>, now_instant: Instant) {
+        let SimFills { binance, ledgerx, legging } = match &mut self.sim {
+            Some(sim) => sim,
+            None => return,
+        };
+        let books = self.dispatch.books();
+        let mut late_fills = vec![];
+        for fill in binance.on_market(msg, now) {
+            if !legging.on_binance_fill(&fill.order_id, fill.qty) {
+                warn!(order_id = %fill.order_id, "SimBinance fill on an order we don't track");
+            }
+            let notional = fill.qty * fill.price;
+            self.summary.cash += if fill.is_buy { -notional } else { notional };
+        }
+        for fill in ledgerx.on_market(msg, now) {
+            if !legging.on_ledgerx_fill(&fill.order_id, fill.size) {
+                late_fills.extend(legging.on_late_fill(&fill.order_id, fill.size, now_instant, binance, ledgerx));
+            }
+            let multiplier = books.iter()
+                .find_map(|b| b.contract_meta(fill.contract_id))
+                .map(|meta| meta.multiplier)
+                .unwrap_or(1.0);
+            let premium = fill.price * fill.size as f64 / multiplier;
+            self.summary.cash += if fill.is_ask { premium } else { -premium };
+        }
+        for late in late_fills.iter() {
+            self.dispatch.book_late_fill(late);
+        }
+    }
+
+    pub fn now(&self) -> Option<DateTime<Utc>> {
+        self.clock.as_ref().map(|c| c.now())
+    }
+
+    fn update_marks(&mut self, msg: &UniversalMsgWrapper) {
+        match msg {
+            UniversalMsgWrapper::Binance(WebsocketEvent::BookTicker(bt)) => {
+                if let (Ok(bid), Ok(ask)) = (f64::from_str(&bt.best_bid), f64::from_str(&bt.best_ask)) {
+                    self.spot_marks.insert(bt.symbol.to_owned(), (bid + ask) / 2.0);
+                }
+            },
+            UniversalMsgWrapper::LedgerX(WebSocketMsg::BookTop(bt)) => {
+                if bt.bid > 0.0 && bt.ask > 0.0 {
+                    self.opts_marks.insert(bt.contract_id, (bt.bid + bt.ask) / 2.0);
+                } else {
+                    self.opts_marks.remove(&bt.contract_id);
+                }
+            },
+            _ => {},
+        }
+    }
+
+    fn cash_flow(&self, t: &Trade) -> f64 {
+        let books = self.dispatch.books();
+        let spot: f64 = t.binance.iter()
+            .map(|o| if o.is_buy { -o.qty * o.price } else { o.qty * o.price })
+            .sum();
+        let opts: f64 = t.ledgerx.iter().map(|o| {
+            let multiplier = books.iter()
+                .find_map(|b| b.contract_meta(o.contract_id))
+                .map(|meta| meta.multiplier)
+                .unwrap_or(1.0);
+            let premium = o.price * o.size as f64 / multiplier;
+            if o.is_ask { premium } else { -premium }
+        }).sum();
+        spot + opts
+    }
+
+    pub fn summary(&self) -> ReplaySummary {
+        let mut summary = self.summary.clone();
+        let mut mark_value = 0.0;
+        let mut intrinsic_value = 0.0;
+
+        for strat in self.dispatch.strats.iter() {
+            let book = &strat.positions;
+            let spot_mark = self.spot_marks.get(&strat.config.symbols.binance).cloned();
+
+            for (symbol, pos) in book.spot.iter() {
+                let value = pos.filled * self.spot_marks.get(symbol).cloned().unwrap_or(0.0);
+                mark_value += value;
+                intrinsic_value += value;
+            }
+            for (id, pos) in book.options.iter() {
+                let meta = match book.contract_meta(*id) {
+                    Some(meta) if pos.filled != 0 => meta,
+                    _ => continue,
+                };
+                let qty = pos.filled as f64 / meta.multiplier;
+                match self.opts_marks.get(id) {
+                    Some(mid) => mark_value += qty * mid,
+                    None => summary.unmarked_contracts += 1,
+                }
+                if let Some(spot) = spot_mark {
+                    let strike = meta.strike as f64;
+                    let intrinsic = if meta.is_call { (spot - strike).max(0.0) } else { (strike - spot).max(0.0) };
+                    intrinsic_value += qty * intrinsic;
+                }
+            }
+        }
+
+        summary.mark_pnl = summary.cash + mark_value;
+        summary.intrinsic_pnl = summary.cash + intrinsic_value;
+        summary
+    }
+}
+
+#[derive(Serialize)]
+struct TradeLine<'a> {
+    time: String,
+    binance: Vec<SpotLine<'a>>,
+    ledgerx: Vec<OptionLine>,
+}
+
+#[derive(Serialize)]
+struct SpotLine<'a> {
+    symbol: &'a str,
+    is_buy: bool,
+    qty: f64,
+    price: f64,
+}
+
+#[derive(Serialize)]
+struct OptionLine {
+    contract_id: u64,
+    is_ask: bool,
+    price: f64,
+    size: u64,
+}
+
+fn write_trade<W: Write>(out: &mut W, time: DateTime<Utc>, t: &Trade) -> io::Result<()> {
+    let line = TradeLine {
+        time: time.to_rfc3339(),
+        binance: t.binance.iter()
+            .map(|o| SpotLine { symbol: &o.symbol, is_buy: o.is_buy, qty: o.qty, price: o.price })
+            .collect(),
+        ledgerx: t.ledgerx.iter()
+            .map(|o| OptionLine { contract_id: o.contract_id, is_ask: o.is_ask, price: o.price, size: o.size })
+            .collect(),
+    };
+    serde_json::to_writer(&mut *out, &line)?;
+    out.write_all(b"\n")
+}
+
+fn open_logs(paths: &[String]) -> Result<Vec<LogReader<BufReader<File>>>, ReplayError> {
+    paths.iter()
+        .map(|path| LogReader::open(path).map_err(|e| ReplayError::Io(path.to_owned(), e)))
+        .collect()
+}
+
+// the chains as they were recorded, so expired contracts can still be replayed;
+// later records of the same contract replace earlier ones
+pub fn contract_table<I: Iterator<Item = Record>>(records: I) -> ContractSpecTable {
+    let id_table = records
+        .filter_map(|record| record.option_spec())
+        .map(|spec| (spec.id, Rc::new(ContractSpec::Option(spec))))
+        .collect();
+    ContractSpecTable { id_table, label_table: HashMap::new() }
+}
+
+fn recorded_contracts(paths: &[String]) -> Result<ContractSpecTable, ReplayError> {
+    let mut records = vec![];
+    for (path, log) in paths.iter().zip(open_logs(paths)?) {
+        for record in log {
+            let record = record.map_err(|e| ReplayError::Log(path.to_owned(), e))?;
+            if matches!(record.event, RecordedEvent::Contract { .. }) {
+                records.push(record);
+            }
+        }
+    }
+    Ok(contract_table(records.into_iter()))
+}
+
+pub fn run(args: ReplayArgs) -> Result<ReplaySummary, ReplayError> {
+    let cfg = Config::load_offline(&args.config).map_err(ReplayError::Config)?;
+    logging::init(&cfg.logging).map_err(ReplayError::Logging)?;
+
+    let table = recorded_contracts(&args.logs)?;
+    let dispatch = if table.id_table.is_empty() {
+        warn!("no contracts recorded in logs, using the current LedgerX contract table");
+        StratDispatcher::startup(cfg.strat.clone())
+    } else {
+        StratDispatcher::from_spec_table(cfg.strat.clone(), table)
+    };
+    let mut replay = Replay::new(dispatch, RiskGate::new(cfg.risk.clone()));
+    if cfg.backtest.fills == FillModel::Simulated {
+        replay = replay.with_fill_sim(&cfg.backtest, cfg.legging_config());
+    }
+
+    let mut trades_out = match &args.trades {
+        Some(path) => Some(BufWriter::new(File::create(path).map_err(|e| ReplayError::Io(path.to_owned(), e))?)),
+        None => None,
+    };
+    let trades_path = args.trades.clone().unwrap_or_default();
+
+    for record in MergedLogs::new(open_logs(&args.logs)?) {
+        let record = record.map_err(|e| ReplayError::Log(args.logs.join(", "), e))?;
+        let stamped = match record.into_msg() {
+            Some(stamped) => stamped,
+            None => continue,
+        };
+        if let (Some(t), Some(now)) = (replay.step(stamped), replay.now()) {
+            match trades_out.as_mut() {
+                Some(out) => write_trade(out, now, &t).map_err(|e| ReplayError::Io(trades_path.to_owned(), e))?,
+                None => println!("{} {:?}", now.to_rfc3339(), t),
+            }
+        }
+    }
+    if let Some(out) = trades_out.as_mut() {
+        out.flush().map_err(|e| ReplayError::Io(trades_path.to_owned(), e))?;
+    }
+
+    Ok(replay.summary())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use binance::model::BookTickerEvent;
+    use binance::websockets::WebsocketEvent;
+    use chrono::{Duration, TimeZone, Utc};
+    use ftx_us_derivs::table::OptionContractSpec;
+    use ftx_us_derivs::ws::{BookTop, WebSocketMsg};
+
+    use crate::{StampedMsg, UniversalMsgWrapper};
+    use crate::dispatch::StratDispatcher;
+    use crate::fillsim::BacktestConfig;
+    use crate::legging::LeggingConfig;
+    use crate::recorder::{LogReader, Recorder};
+    use crate::risk::{RiskConfig, RiskGate};
+    use crate::strat::tests::mock_config;
+    use super::{contract_table, replay_args_from, MergedLogs, Replay};
+
+    fn spec(id: u64, is_call: bool) -> OptionContractSpec {
+        OptionContractSpec {
+            id,
+            label: format!("BTC-Mini-30JUN2023-10000-{}", if is_call { "Call" } else { "Put" }),
+            underlying: "CBTC".to_string(),
+            strike_price: 10000,
+            is_call,
+            tte: 0.66,
+            open_interest: 0,
+            multiplier: 100.0,
+            min_increment: 1.0,
+            active: true,
+            date_live: Utc.timestamp_opt(1_650_000_000, 0).unwrap(),
+            date_expires: Utc.timestamp_opt(1_688_140_800, 0).unwrap(),
+            collateral_asset: "USD".to_string(),
+            is_ecp_only: false,
+        }
+    }
+
+    fn spot(bid: &str, ask: &str) -> UniversalMsgWrapper {
+        UniversalMsgWrapper::Binance(WebsocketEvent::BookTicker(BookTickerEvent {
+            update_id: 0,
+            symbol: "BTCUSDT".to_string(),
+            best_bid: bid.to_string(),
+            best_bid_qty: "1.0".to_string(),
+            best_ask: ask.to_string(),
+            best_ask_qty: "1.0".to_string(),
+        }))
+    }
+
+    fn book_top(contract_id: u64, bid: f64, ask: f64) -> UniversalMsgWrapper {
+        UniversalMsgWrapper::LedgerX(WebSocketMsg::BookTop(
+            BookTop { bid, bid_size: 100, ask, ask_size: 100, contract_id, contract_type: 0, clock: 0 }
+        ))
+    }
+
+    fn risk() -> RiskGate {
+        RiskGate::new(RiskConfig {
+            max_notional_per_trade: 1e9,
+            max_open_combos_per_expiry: 10,
+            max_net_delta: 1.0,
+            max_orders_per_sec: 100,
+            kill_file: None,
+            max_borrow: HashMap::new(),
+        })
+    }
+
+    #[test]
+    fn args() {
+        let args = |v: &[&str]| replay_args_from(v.iter().map(|s| s.to_string()).collect::<Vec<_>>().into_iter());
+
+        let parsed = args(&["--config", "bt.toml", "--trades=out.jsonl", "mon.jsonl", "tue.jsonl"]).unwrap();
+        assert_eq!(parsed.config, "bt.toml");
+        assert_eq!(parsed.trades.as_deref(), Some("out.jsonl"));
+        assert_eq!(parsed.logs, vec!["mon.jsonl", "tue.jsonl"]);
+        assert!(args(&["--config", "bt.toml"]).is_err());
+        assert!(args(&["--speed", "10", "mon.jsonl"]).is_err());
+    }
+
+    #[test]
+    fn merged_logs_replay_arb() {
+        let t0 = Utc.timestamp_millis_opt(1_665_000_000_000).unwrap();
+        let ms = Duration::milliseconds;
+
+        // options and spot recorded to separate files, interleaved only by time
+        let mut lx_log = Recorder::new(vec![]);
+        lx_log.record_contracts(&[spec(1, true), spec(2, false)], t0).unwrap();
+        lx_log.record(&book_top(1, 11070.0, 11180.0), t0 + ms(1)).unwrap();
+        lx_log.record(&book_top(2, 500.0, 580.0), t0 + ms(2)).unwrap();
+        let lx_log = lx_log.into_inner().unwrap();
+
+        let mut bn_log = Recorder::new(vec![]);
+        bn_log.record(&spot("20449.0", "20450.0"), t0).unwrap();
+        bn_log.record(&spot("20299.0", "20300.0"), t0 + ms(3)).unwrap();
+        let bn_log = bn_log.into_inner().unwrap();
+
+        let table = contract_table(LogReader::new(&lx_log[..]).map(|r| r.unwrap()));
+        assert_eq!(table.id_table.len(), 2);
+        let mut replay = Replay::new(StratDispatcher::from_spec_table(vec![mock_config()], table), risk());
+
+        let merged = MergedLogs::new(vec![LogReader::new(&lx_log[..]), LogReader::new(&bn_log[..])]);
+        let mut trades = vec![];
+        for record in merged {
+            if let Some(t) = record.unwrap().into_msg().and_then(|msg| replay.step(msg)) {
+                trades.push((replay.now().unwrap(), t));
+            }
+        }
+
+        // the arb is only there once the last spot tick lands after both option quotes
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].0, t0 + ms(3));
+
+        // buy 0.5 spot at 20300, sell 50 calls at 11070, buy 50 puts at 580
+        let summary = replay.summary();
+        assert_eq!(summary.messages, 4);
+        assert!((summary.cash - -4905.0).abs() < 1e-6);
+        // held to expiry the conversion is worth the strike on 0.5 coin
+        assert!((summary.intrinsic_pnl - 95.0).abs() < 1e-6);
+        assert_eq!(summary.unmarked_contracts, 0);
+    }
+
+    #[test]
+    fn expired_unwind_stays_on_the_books() {
+        let t0 = Utc.timestamp_millis_opt(1_665_000_000_000).unwrap();
+        let ms = Duration::milliseconds;
+
+        let mut lx_log = Recorder::new(vec![]);
+        lx_log.record_contracts(&[spec(1, true), spec(2, false)], t0).unwrap();
+        let lx_log = lx_log.into_inner().unwrap();
+        let table = contract_table(LogReader::new(&lx_log[..]).map(|r| r.unwrap()));
+        let backtest = BacktestConfig { ledgerx_order_ttl_ms: 300, ..BacktestConfig::default() };
+        let legging = LeggingConfig { timeout: std::time::Duration::from_millis(500), opts_unwind_slippage: 0.05 };
+        let mut replay = Replay::new(StratDispatcher::from_spec_table(vec![mock_config()], table), risk())
+            .with_fill_sim(&backtest, legging);
+
+        // the conversion goes out at 3ms; by the time the options land at 103ms the put
+        // offer has gone, and the call offer has moved past where the unwind will buy back
+        let msgs = [
+            (0, spot("20449.0", "20450.0")),
+            (1, book_top(1, 11070.0, 11180.0)),
+            (2, book_top(2, 500.0, 580.0)),
+            (3, spot("20299.0", "20300.0")),
+            (50, book_top(2, 500.0, 700.0)),
+            (60, book_top(1, 11070.0, 12000.0)),
+            // the legging timeout pulls the put and sends the call unwind
+            (600, spot("20449.0", "20450.0")),
+            // the unwind expires unfilled at the venue, then times out in legging
+            (1050, spot("20449.0", "20450.0")),
+            (1200, spot("20449.0", "20450.0")),
+        ];
+        let mut trades = 0;
+        for (at, msg) in msgs {
+            trades += replay.step(StampedMsg { recv_time: t0 + ms(at), msg }).is_some() as usize;
+        }
+        assert_eq!(trades, 1);
+
+        let summary = replay.summary();
+        assert_eq!(summary.legged, 1);
+        assert_eq!(summary.open_executions, 0);
+        // sold 50 calls at 11070, bought 0.5 spot at 20300 and sold it back at 20449; the
+        // calls were never bought back
+        assert!((summary.cash - 5609.5).abs() < 1e-6);
+
+        // and the books say the same: the short calls are still held, nothing's working
+        let book = &replay.dispatch.strats[0].positions;
+        assert_eq!(book.options[&1].filled, -50);
+        assert_eq!(book.options[&1].working, 0);
+        assert_eq!(book.options.get(&2).map(|p| p.filled).unwrap_or(0), 0);
+        assert_eq!(book.spot["BTCUSDT"].filled, 0.0);
+        assert_eq!(book.spot["BTCUSDT"].working, 0.0);
+    }
+}
@@ -0,0 +1,323 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use chrono::{offset::Utc, DateTime};
+use serde::Deserialize;
+
+use crate::positions::{PositionBook, PositionView};
+use crate::strat::Trade;
+use crate::symbols::binance_base_asset;
+
+// anything smaller is a level we're flat on
+const EXPOSURE_EPSILON: f64 = 1e-9;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RiskConfig {
+    // USD: spot legs at their price plus option premium
+    pub max_notional_per_trade: f64,
+    // strike levels with synthetic exposure, per expiry, across all underlyings
+    pub max_open_combos_per_expiry: usize,
+    // in units of the underlying, per underlying
+    pub max_net_delta: f64,
+    // individual legs sent to either venue
+    pub max_orders_per_sec: usize,
+    // trading halts while this file exists
+    #[serde(default)]
+    pub kill_file: Option<String>,
+    // coin that can be borrowed to short spot, by asset ("BTC"), across all pairs trading it;
+    // shorts in assets not listed aren't capped
+    #[serde(default)]
+    pub max_borrow: HashMap<String, f64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RejectReason {
+    KillSwitch(String),
+    MaxNotional { notional: f64, limit: f64 },
+    MaxOpenCombos { expiry: DateTime<Utc>, open: usize, limit: usize },
+    MaxNetDelta { delta: f64, limit: f64 },
+    OrderRate { orders: usize, limit: usize },
+    MaxBorrow { asset: String, borrowed: f64, limit: f64 },
+}
+
+impl fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RejectReason::KillSwitch(why) => write!(f, "kill switch engaged: {}", why),
+            RejectReason::MaxNotional { notional, limit } => write!(f, "notional {:.2} over limit {:.2}", notional, limit),
+            RejectReason::MaxOpenCombos { expiry, open, limit } => write!(f, "{} open combos on {} over limit {}", open, expiry, limit),
+            RejectReason::MaxNetDelta { delta, limit } => write!(f, "net delta {:.4} over limit {:.4}", delta, limit),
+            RejectReason::OrderRate { orders, limit } => write!(f, "{} orders in the last second over limit {}", orders, limit),
+            RejectReason::MaxBorrow { asset, borrowed, limit } => write!(f, "{:.4} {} borrowed over limit {:.4}", borrowed, asset, limit),
+        }
+    }
+}
+
+pub struct RiskGate {
+    pub config: RiskConfig,
+    killed: Option<String>,
+    recent_orders: VecDeque<Instant>,
+}
+
+impl RiskGate {
+    pub fn new(config: RiskConfig) -> Self {
+        Self {
+            config,
+            killed: None,
+            recent_orders: VecDeque::new(),
+        }
+    }
+
+    pub fn engage_kill_switch(&mut self, why: &str) {
+        self.killed = Some(why.to_owned());
+    }
+
+    pub fn is_killed(&self) -> bool {
+        self.killed.is_some()
+    }
+
+    // `books` must already include `t` as working, which ComboStrat does when it emits a trade
+    pub fn check(&mut self, t: &Trade, books: &[&PositionBook], now: Instant) -> Result<(), RejectReason> {
+        if let Some(why) = &self.killed {
+            return Err(RejectReason::KillSwitch(why.to_owned()));
+        }
+        if let Some(path) = &self.config.kill_file {
+            if Path::new(path).exists() {
+                return Err(RejectReason::KillSwitch(format!("{} exists", path)));
+            }
+        }
+
+        while let Some(&sent) = self.recent_orders.front() {
+            if now.duration_since(sent) < Duration::from_secs(1) {
+                break;
+            }
+            self.recent_orders.pop_front();
+        }
+        let orders = self.recent_orders.len() + t.binance.len() + t.ledgerx.len();
+        if orders > self.config.max_orders_per_sec {
+            return Err(RejectReason::OrderRate { orders, limit: self.config.max_orders_per_sec });
+        }
+
+        let notional = trade_notional(t, books);
+        if notional > self.config.max_notional_per_trade {
+            return Err(RejectReason::MaxNotional { notional, limit: self.config.max_notional_per_trade });
+        }
+
+        let expirys: HashSet<DateTime<Utc>> = t.ledgerx.iter()
+            .filter_map(|o| books.iter().find_map(|b| b.contract_meta(o.contract_id)))
+            .map(|meta| meta.expiry)
+            .collect();
+        for expiry in expirys {
+            let open = books.iter()
+                .flat_map(|b| b.exposures(PositionView::Committed))
+                .filter(|l| l.expiry == expiry && l.synthetic.abs() > EXPOSURE_EPSILON)
+                .count();
+            if open > self.config.max_open_combos_per_expiry {
+                return Err(RejectReason::MaxOpenCombos { expiry, open, limit: self.config.max_open_combos_per_expiry });
+            }
+        }
+
+        // only the underlyings this trade touches
+        for book in books.iter().filter(|b| touches(b, t)) {
+            let delta = book.net_delta(PositionView::Committed);
+            if delta.abs() > self.config.max_net_delta {
+                return Err(RejectReason::MaxNetDelta { delta, limit: self.config.max_net_delta });
+            }
+        }
+
+        // only reversals short spot, so only they can run out of coin to borrow
+        for asset in t.binance.iter().filter(|o| !o.is_buy).filter_map(|o| binance_base_asset(&o.symbol)) {
+            let limit = match self.config.max_borrow.get(asset) {
+                Some(&limit) => limit,
+                None => continue,
+            };
+            let held: f64 = books.iter()
+                .flat_map(|b| b.spot_positions(PositionView::Committed))
+                .filter(|(symbol, _)| binance_base_asset(symbol) == Some(asset))
+                .map(|(_, qty)| qty)
+                .sum();
+            let borrowed = (-held).max(0.0);
+            if borrowed > limit + EXPOSURE_EPSILON {
+                return Err(RejectReason::MaxBorrow { asset: asset.to_owned(), borrowed, limit });
+            }
+        }
+
+        for _ in 0..(t.binance.len() + t.ledgerx.len()) {
+            self.recent_orders.push_back(now);
+        }
+        Ok(())
+    }
+}
+
+fn touches(book: &PositionBook, t: &Trade) -> bool {
+    t.ledgerx.iter().any(|o| book.contract_meta(o.contract_id).is_some())
+        || t.binance.iter().any(|o| book.spot.contains_key(&o.symbol))
+}
+
+pub fn trade_notional(t: &Trade, books: &[&PositionBook]) -> f64 {
+    let spot: f64 = t.binance.iter().map(|o| o.qty * o.price).sum();
+    let premium: f64 = t.ledgerx.iter().map(|o| {
+        let multiplier = books.iter()
+            .find_map(|b| b.contract_meta(o.contract_id))
+            .map(|meta| meta.multiplier)
+            .unwrap_or(1.0);
+        o.price * o.size as f64 / multiplier
+    }).sum();
+    spot + premium
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::rc::Rc;
+    use std::time::{Duration, Instant};
+
+    use chrono::{Utc, DateTime};
+    use ftx_us_derivs::order::Order;
+    use ftx_us_derivs::table::{ContractSpecTable, OptionContractSpec, ContractSpec};
+
+    use crate::options_chain::LedgerXOptionsChain;
+    use crate::positions::PositionBook;
+    use crate::strat::{BinanceMarketOrder, Trade};
+    use super::{RejectReason, RiskConfig, RiskGate, trade_notional};
+
+    fn mock_book() -> PositionBook {
+        let spec = |id: u64, strike: u64, is_call: bool| OptionContractSpec {
+            id,
+            label: format!("BTC-Mini-30JUN2023-{}-{}", strike, if is_call { "Call" } else { "Put" }),
+            underlying: "CBTC".to_string(),
+            strike_price: strike,
+            is_call,
+            tte: 0.66,
+            open_interest: 0,
+            multiplier: 100.0,
+            min_increment: 1.0,
+            active: true,
+            date_live: DateTime::<Utc>::MIN_UTC,
+            date_expires: DateTime::<Utc>::MIN_UTC,
+            collateral_asset: "USD".to_string(),
+            is_ecp_only: false,
+        };
+        let table = ContractSpecTable {
+            id_table: HashMap::from_iter([
+                (1, Rc::new(ContractSpec::Option(spec(1, 10000, true)))),
+                (2, Rc::new(ContractSpec::Option(spec(2, 10000, false)))),
+                (3, Rc::new(ContractSpec::Option(spec(3, 20000, true)))),
+                (4, Rc::new(ContractSpec::Option(spec(4, 20000, false)))),
+            ]),
+            label_table: HashMap::new(),
+        };
+        PositionBook::from_chain(&LedgerXOptionsChain::from_spec_table("CBTC", table))
+    }
+
+    fn cfg() -> RiskConfig {
+        RiskConfig {
+            max_notional_per_trade: 20_000.0,
+            max_open_combos_per_expiry: 1,
+            max_net_delta: 0.1,
+            max_orders_per_sec: 6,
+            kill_file: None,
+            max_borrow: HashMap::new(),
+        }
+    }
+
+    fn conversion(call: u64, put: u64, qty: f64) -> Trade {
+        let size = (qty * 100.0) as u64;
+        Trade {
+            binance: vec![BinanceMarketOrder { symbol: "BTCUSDT".to_string(), is_buy: true, qty, price: 20000.0 }],
+            ledgerx: vec![Order::new(call, true, 11000.0, size), Order::new(put, false, 500.0, size)],
+        }
+    }
+
+    #[test]
+    fn notional() {
+        let book = mock_book();
+        let t = conversion(1, 2, 0.5);
+        assert_eq!(trade_notional(&t, &[&book]), 10000.0 + 5500.0 + 250.0);
+    }
+
+    #[test]
+    fn limits() {
+        let mut book = mock_book();
+        let mut gate = RiskGate::new(cfg());
+        let now = Instant::now();
+
+        let t = conversion(1, 2, 0.5);
+        book.submit_trade(&t);
+        assert_eq!(gate.check(&t, &[&book], now), Ok(()));
+
+        // a second strike in the same expiry is one combo too many
+        let t = conversion(3, 4, 0.5);
+        book.submit_trade(&t);
+        assert!(matches!(gate.check(&t, &[&book], now), Err(RejectReason::MaxOpenCombos { open: 2, .. })));
+        book.cancel_ledgerx(3, true, 50);
+        book.cancel_ledgerx(4, false, 50);
+        book.cancel_binance("BTCUSDT", true, 0.5);
+
+        // too big
+        let t = conversion(1, 2, 1.0);
+        assert!(matches!(gate.check(&t, &[&book], now), Err(RejectReason::MaxNotional { .. })));
+
+        // naked spot leaves delta on
+        let t = Trade {
+            binance: vec![BinanceMarketOrder { symbol: "BTCUSDT".to_string(), is_buy: true, qty: 0.5, price: 20000.0 }],
+            ledgerx: vec![],
+        };
+        book.submit_trade(&t);
+        assert!(matches!(gate.check(&t, &[&book], now), Err(RejectReason::MaxNetDelta { .. })));
+    }
+
+    #[test]
+    fn order_rate_and_kill_switch() {
+        let book = mock_book();
+        let mut gate = RiskGate::new(cfg());
+        let now = Instant::now();
+        let t = Trade { binance: vec![], ledgerx: vec![Order::new(1, true, 1.0, 1), Order::new(1, false, 1.0, 1)] };
+
+        for _ in 0..3 {
+            assert_eq!(gate.check(&t, &[&book], now), Ok(()));
+        }
+        assert!(matches!(gate.check(&t, &[&book], now), Err(RejectReason::OrderRate { orders: 8, limit: 6 })));
+        assert_eq!(gate.check(&t, &[&book], now + Duration::from_millis(1001)), Ok(()));
+
+        gate.engage_kill_switch("test");
+        assert!(matches!(gate.check(&t, &[&book], now + Duration::from_secs(5)), Err(RejectReason::KillSwitch(_))));
+    }
+
+    #[test]
+    fn borrow_caps_reversals_only() {
+        let mut book = mock_book();
+        let mut gate = RiskGate::new(RiskConfig {
+            max_borrow: HashMap::from([("BTC".to_string(), 0.3)]),
+            max_open_combos_per_expiry: 2,
+            ..cfg()
+        });
+        let now = Instant::now();
+        let reversal = |call: u64, put: u64, qty: f64| Trade {
+            binance: vec![BinanceMarketOrder { symbol: "BTCUSDT".to_string(), is_buy: false, qty, price: 20000.0 }],
+            ledgerx: vec![Order::new(call, false, 11000.0, (qty * 100.0) as u64), Order::new(put, true, 500.0, (qty * 100.0) as u64)],
+        };
+
+        let t = reversal(1, 2, 0.2);
+        book.submit_trade(&t);
+        assert_eq!(gate.check(&t, &[&book], now), Ok(()));
+
+        // 0.4 short with the second one
+        let t = reversal(3, 4, 0.2);
+        book.submit_trade(&t);
+        assert!(matches!(
+            gate.check(&t, &[&book], now),
+            Err(RejectReason::MaxBorrow { ref asset, limit, .. }) if asset == "BTC" && limit == 0.3
+        ));
+        book.cancel_ledgerx(3, false, 20);
+        book.cancel_ledgerx(4, true, 20);
+        book.cancel_binance("BTCUSDT", false, 0.2);
+
+        // buying spot back is never capped, whatever's still borrowed
+        let t = conversion(3, 4, 0.2);
+        book.submit_trade(&t);
+        assert_eq!(gate.check(&t, &[&book], now + Duration::from_secs(1)), Ok(()));
+    }
+}
@@ -1,30 +1,138 @@
 use ftx_us_derivs::order::Order;
-use ftx_us_derivs::table::ContractSpecTable;
+use ftx_us_derivs::table::{ContractSpecTable, OptionContractSpec};
 use ftx_us_derivs::ws::{WebSocketMsg};
 use binance::websockets::WebsocketEvent;
-use binance::model::BookTickerEvent;
-use std::cell::Ref;
+use binance::model::{BookTickerEvent, DepthOrderBookEvent};
 use std::str::FromStr;
 use std::collections::HashMap;
+use serde::Deserialize;
+use chrono::{offset::Utc, DateTime};
+use tracing::{debug, info};
 
-use crate::options_chain::{LedgerXOptionsChain, LedgerXOptionsContract, LatticeRef};
+use crate::book::{price_at, sweep_pair, BookError, BookUpdate, SpotDepthSnapshot, SpotOrderbook};
+use crate::carry::{CarryConfig, CarryModel, CarrySide, FundingUpdate};
+use crate::options_chain::{LedgerXOptionsChain, LedgerXOptionsContract, LedgerXOptionsLevel};
+use crate::parity::{ImpliedCurve, ParityConfig};
+use crate::symbols::SymbolMapping;
+use crate::positions::{PositionBook, PositionView};
+use crate::thresholds::{LevelThresholds, ThresholdIndex};
 
 pub struct ComboStrat {
     opts_chain: LedgerXOptionsChain,
-    spec_table: ContractSpecTable,
     last_spot_tick: Option<BookTickerEvent>,
+    last_spot_time: Option<DateTime<Utc>>,
+    spot_book: SpotOrderbook,
+    thresholds: ThresholdIndex,
+    pub positions: PositionBook,
     pub config: ComboStratConfig,
+    // combos orders were generated for since startup
+    pub arbs: ArbCounts,
+    // each expiry's forward and rate as put-call parity has them
+    pub implied: ImpliedCurve,
+    // what holding spot long or short to expiry costs
+    carry: Box<dyn CarryModel>,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArbKind {
+    Conversion,
+    Reversal,
+    // synthetics at two strikes of one expiry against each other, no spot leg
+    BoxSpread,
+    // synthetics at one strike of two expiries against each other, no spot leg
+    JellyRoll,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ArbCounts {
+    pub conversions: u64,
+    pub reversals: u64,
+    pub boxes: u64,
+    pub rolls: u64,
+}
+
+impl ArbCounts {
+    pub fn add(&mut self, kind: ArbKind) {
+        match kind {
+            ArbKind::Conversion => self.conversions += 1,
+            ArbKind::Reversal => self.reversals += 1,
+            ArbKind::BoxSpread => self.boxes += 1,
+            ArbKind::JellyRoll => self.rolls += 1,
+        }
+    }
+
+    pub fn merge(&mut self, other: ArbCounts) {
+        self.conversions += other.conversions;
+        self.reversals += other.reversals;
+        self.boxes += other.boxes;
+        self.rolls += other.rolls;
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
 pub struct ComboStratConfig {
-    pub symbol: String,
+    pub symbols: SymbolMapping,
     pub ann_borrow_rate: f64,
+    // what the cash behind a conversion's long spot costs; ann_borrow_rate if unset
+    #[serde(default)]
+    pub long_funding_rate: Option<f64>,
+    // what borrowing the coin for a reversal's short spot costs, on top of that
+    #[serde(default)]
+    pub short_borrow_rate: f64,
     pub opts_tc: f64,
     pub spot_tc: f64,
+    // cap on synthetic exposure per strike/expiry, in units of the underlying
+    #[serde(default = "default_max_level_exposure")]
+    pub max_level_exposure: f64,
+    // quotes older than this, measured from receipt, are not traded against
+    #[serde(default = "default_max_opts_age_ms")]
+    pub max_opts_age_ms: u64,
+    #[serde(default = "default_max_spot_age_ms")]
+    pub max_spot_age_ms: u64,
+    // also trade boxes between strikes of an expiry
+    #[serde(default)]
+    pub box_spreads: bool,
+    // also trade jelly rolls between expiries at a strike
+    #[serde(default)]
+    pub jelly_rolls: bool,
+    // fitting each expiry's own rate from its quotes, and whether to price combos at it
+    #[serde(default)]
+    pub parity: ParityConfig,
+    // the rates spot legs are carried at; the two above unless set
+    #[serde(default)]
+    pub carry: CarryConfig,
+}
+
+impl ComboStratConfig {
+    pub fn funding_rate(&self) -> f64 {
+        self.long_funding_rate.unwrap_or(self.ann_borrow_rate)
+    }
+}
+
+// how often an expiry's parity fit is redone when it's only logged and exported, not traded on
+const PARITY_WATCH_INTERVAL_MS: i64 = 1_000;
+// how far a fitted rate moves before the expiry's thresholds are redone at it
+const IMPLIED_RATE_TOLERANCE: f64 = 1e-4;
+
+fn default_max_level_exposure() -> f64 {
+    1.0
+}
+fn default_max_opts_age_ms() -> u64 {
+    10_000
+}
+fn default_max_spot_age_ms() -> u64 {
+    1_000
+}
+
+fn is_fresh(recv_time: Option<DateTime<Utc>>, max_age_ms: u64, now: DateTime<Utc>) -> bool {
+    match recv_time {
+        Some(t) => now.signed_duration_since(t) <= chrono::Duration::milliseconds(max_age_ms as i64),
+        None => false,
+    }
 }
 
 // move the trade objects to some other file at some point I reckon
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct BinanceMarketOrder {
     pub symbol: String,
     pub is_buy: bool,
@@ -37,6 +145,15 @@ pub struct Trade {
     pub binance: Vec<BinanceMarketOrder>,
     pub ledgerx: Vec<Order>,
 }
+// Order isn't Clone, so the LedgerX legs are rebuilt
+impl Clone for Trade {
+    fn clone(&self) -> Self {
+        Trade {
+            binance: self.binance.clone(),
+            ledgerx: self.ledgerx.iter().map(|o| Order::new(o.contract_id, o.is_ask, o.price, o.size)).collect(),
+        }
+    }
+}
 impl Trade {
     pub fn empty() -> Self {
         Trade {
@@ -44,6 +161,13 @@ impl Trade {
             ledgerx: vec![],
         }
     }
+    pub fn is_empty(&self) -> bool {
+        self.binance.is_empty() && self.ledgerx.is_empty()
+    }
+    pub fn merge(&mut self, other: Trade) {
+        self.binance.extend(other.binance);
+        self.ledgerx.extend(other.ledgerx);
+    }
     pub fn net_out(&mut self) {
         // only leave in the first order corresponding to each symbol
         // for each order that repeats a symbol, update quantity and is_buy of original
@@ -74,35 +198,48 @@ impl Trade {
                 None
             }).collect();
     }
-    pub fn reversal(&mut self, cfg: &ComboStratConfig, spot: &BookTickerEvent, call: &LedgerXOptionsContract, put: &LedgerXOptionsContract) -> bool {
-        let spot_bid_quantity: f64 = f64::from_str(&spot.best_bid_qty).unwrap();
-        
-        let min_size = (call.ask_quantity.unwrap() as f64 * call.spec.multiplier)
-                        .min(put.bid_quantity.unwrap() as f64 * put.spec.multiplier)
-                        .min(spot_bid_quantity);
+    // option books are walked level by level for as long as the marginal combo still clears
+    // costs against the marginal spot price; book sizes are in contracts, trade sizes in
+    // units of the underlying
+    pub fn reversal(&mut self, cfg: &ComboStratConfig, rate: f64, spot: &SpotLiquidity, call: &LedgerXOptionsContract, put: &LedgerXOptionsContract, max_size: f64) -> bool {
+        let (call_asks, put_bids) = (call.book.levels(true), put.book.levels(false));
+        let clears = |contracts: u64, call_ask, put_bid| {
+            let spot_bid = spot.marginal(contracts as f64 / call.spec.multiplier);
+            clears_rev(cfg, rate, spot_bid, call_ask, put_bid, call.strike, call.tte)
+        };
+        let depth = match sweep_pair(&call_asks, &put_bids, u64::MAX, clears) {
+            Some((contracts, _, _)) => contracts as f64 / call.spec.multiplier,
+            None => return false,
+        };
+
+        let min_size = depth.min(spot.available());
         // can change sizing later; not sure how scared we are about (not) getting filled
         let trade_size_factor: f64 = 0.5;
-        let trade_size = (min_size as f64) * trade_size_factor;
+        let trade_size = (min_size * trade_size_factor).min(max_size);
         if trade_size * call.spec.multiplier < 1.0 ||  trade_size * put.spec.multiplier < 1.0 { return false;}
 
+        // limit each leg at the deepest level the trade reaches
+        let contracts = (trade_size * call.spec.multiplier) as u64;
+        let (_, call_limit, put_limit) = sweep_pair(&call_asks, &put_bids, contracts, clears).unwrap();
+
         let binance_order = BinanceMarketOrder {
-            symbol: String::from_str(&cfg.symbol).unwrap(),
+            symbol: cfg.symbols.binance.to_owned(),
             is_buy: false,
             qty: trade_size,
-            price: f64::from_str(&spot.best_bid).unwrap(),
+            price: spot.vwap(trade_size),
         };
 
         let call_order = Order::new(
             call.id,
             false,
-            call.ask.unwrap(),
-            (trade_size * call.spec.multiplier) as u64,
+            call_limit,
+            contracts,
         );
 
         let put_order = Order::new(
             put.id,
             true,
-            put.bid.unwrap(),
+            put_limit,
             (trade_size * put.spec.multiplier) as u64,
         );
         // Add to trade list
@@ -113,36 +250,44 @@ impl Trade {
 
         return true;
     }
-    pub fn conversion(&mut self, cfg: &ComboStratConfig, spot: &BookTickerEvent, call: &LedgerXOptionsContract, put: &LedgerXOptionsContract) -> bool {
-        let spot_ask_quantity: f64 = f64::from_str(&spot.best_ask_qty).unwrap();
-
+    pub fn conversion(&mut self, cfg: &ComboStratConfig, rate: f64, spot: &SpotLiquidity, call: &LedgerXOptionsContract, put: &LedgerXOptionsContract, max_size: f64) -> bool {
+        let (call_bids, put_asks) = (call.book.levels(false), put.book.levels(true));
+        let clears = |contracts: u64, call_bid, put_ask| {
+            let spot_ask = spot.marginal(contracts as f64 / call.spec.multiplier);
+            clears_conv(cfg, rate, spot_ask, call_bid, put_ask, call.strike, call.tte)
+        };
+        let depth = match sweep_pair(&call_bids, &put_asks, u64::MAX, clears) {
+            Some((contracts, _, _)) => contracts as f64 / call.spec.multiplier,
+            None => return false,
+        };
 
-        let min_size = call.bid_quantity.unwrap()
-                        .min(put.ask_quantity.unwrap())
-                        .min(spot_ask_quantity);
+        let min_size = depth.min(spot.available());
         // can change sizing later; not sure how scared we are about (not) getting filled
         let trade_size_factor: f64 = 0.5;
-        let trade_size = (min_size as f64) * trade_size_factor;
+        let trade_size = (min_size * trade_size_factor).min(max_size);
         if trade_size * call.spec.multiplier < 1.0 ||  trade_size * put.spec.multiplier < 1.0 { return false; }
 
+        let contracts = (trade_size * call.spec.multiplier) as u64;
+        let (_, call_limit, put_limit) = sweep_pair(&call_bids, &put_asks, contracts, clears).unwrap();
+
         let binance_order = BinanceMarketOrder {
-            symbol: String::from_str(&cfg.symbol).unwrap(),
+            symbol: cfg.symbols.binance.to_owned(),
             is_buy: true,
             qty: trade_size,
-            price: f64::from_str(&spot.best_ask).unwrap(),
+            price: spot.vwap(trade_size),
         };
 
         let call_order = Order::new(
             call.id,
             true,
-            call.bid.unwrap(),
-            (trade_size * call.spec.multiplier) as u64,
+            call_limit,
+            contracts,
         );
 
         let put_order = Order::new(
             put.id,
             false,
-            put.ask.unwrap(),
+            put_limit,
             (trade_size * put.spec.multiplier) as u64,
         );
 
@@ -153,125 +298,620 @@ impl Trade {
         self.ledgerx.push(put_order);
         return true;
     }
+    // buys the synthetic at one level and sells it at another, LedgerX only: a box across
+    // strikes or a jelly roll across expiries. The long books are walked level by level for
+    // as long as the marginal pair still clears against the short books at the same size;
+    // `clears` takes the long call ask, long put bid, short call bid and short put ask
+    pub fn synthetic_pair<F>(&mut self, long: (&LedgerXOptionsContract, &LedgerXOptionsContract), short: (&LedgerXOptionsContract, &LedgerXOptionsContract), max_size: f64, clears: F) -> bool
+    where
+        F: Fn(f64, f64, f64, f64) -> bool,
+    {
+        let (long_call, long_put) = long;
+        let (short_call, short_put) = short;
+        let (call_asks, put_bids) = (long_call.book.levels(true), long_put.book.levels(false));
+        let (call_bids, put_asks) = (short_call.book.levels(false), short_put.book.levels(true));
+        // the short side's marginal prices at a size in long call contracts
+        let short_prices = |contracts: u64| {
+            let qty = contracts as f64 / long_call.spec.multiplier;
+            let call_bid = price_at(&call_bids, (qty * short_call.spec.multiplier).ceil() as u64)?;
+            let put_ask = price_at(&put_asks, (qty * short_put.spec.multiplier).ceil() as u64)?;
+            Some((call_bid, put_ask))
+        };
+        let pair_clears = |contracts: u64, call_ask, put_bid| match short_prices(contracts) {
+            Some((call_bid, put_ask)) => clears(call_ask, put_bid, call_bid, put_ask),
+            None => false,
+        };
+        let depth = match sweep_pair(&call_asks, &put_bids, u64::MAX, pair_clears) {
+            Some((contracts, _, _)) => contracts as f64 / long_call.spec.multiplier,
+            None => return false,
+        };
+
+        // can change sizing later; not sure how scared we are about (not) getting filled
+        let trade_size_factor: f64 = 0.5;
+        let trade_size = (depth * trade_size_factor).min(max_size);
+        if [long_call, long_put, short_call, short_put].iter().any(|c| trade_size * c.spec.multiplier < 1.0) { return false; }
+
+        // limit each leg at the deepest level the trade reaches
+        let contracts = (trade_size * long_call.spec.multiplier) as u64;
+        let (_, call_ask, put_bid) = sweep_pair(&call_asks, &put_bids, contracts, pair_clears).unwrap();
+        let (call_bid, put_ask) = short_prices(contracts).unwrap();
+        for (contract, is_ask, price) in [(long_call, false, call_ask), (long_put, true, put_bid), (short_call, true, call_bid), (short_put, false, put_ask)] {
+            self.ledgerx.push(Order::new(contract.id, is_ask, price, (trade_size * contract.spec.multiplier) as u64));
+        }
+        return true;
+    }
+}
+
+// what the spot leg of a combo can trade: the whole diff-depth book while it's synced,
+// otherwise just the bookTicker top
+pub struct SpotLiquidity<'a> {
+    book: Option<&'a SpotOrderbook>,
+    is_buy: bool,
+    top: f64,
+    top_qty: f64,
+}
+
+impl<'a> SpotLiquidity<'a> {
+    pub fn new(book: &'a SpotOrderbook, tick: &BookTickerEvent, is_buy: bool) -> Self {
+        let (top, top_qty) = if is_buy { (&tick.best_ask, &tick.best_ask_qty) } else { (&tick.best_bid, &tick.best_bid_qty) };
+        Self {
+            book: Some(book).filter(|b| b.is_synced()),
+            is_buy,
+            top: f64::from_str(top).unwrap(),
+            top_qty: f64::from_str(top_qty).unwrap(),
+        }
+    }
+
+    pub fn available(&self) -> f64 {
+        match self.book {
+            Some(book) => book.depth(self.is_buy),
+            None => self.top_qty,
+        }
+    }
+
+    // past what's available the last level's price is used; sizing caps at `available` anyway
+    pub fn marginal(&self, qty: f64) -> f64 {
+        self.book
+            .and_then(|book| book.marginal(self.is_buy, qty.min(self.available())))
+            .unwrap_or(self.top)
+    }
+
+    pub fn vwap(&self, qty: f64) -> f64 {
+        self.book.and_then(|book| book.vwap(self.is_buy, qty)).unwrap_or(self.top)
+    }
+}
+
+// the forward a call and put at one strike make, priced at the given quotes and
+// discounted at `rate`
+fn synthetic(rate: f64, call_px: f64, put_px: f64, strike: u64, tte: f64) -> f64 {
+    call_px - put_px + (strike as f64) * (-rate * tte).exp()
+}
+// what selling at `sell` and buying at `buy` earns, as a fraction of their midpoint
+fn edge(sell: f64, buy: f64) -> f64 {
+    2.0*(sell - buy) / (sell + buy)
+}
+fn clears_rev(cfg: &ComboStratConfig, rate: f64, spot_bid: f64, call_ask: f64, put_bid: f64, strike: u64, tte: f64) -> bool {
+    edge(spot_bid, synthetic(rate, call_ask, put_bid, strike, tte)) > (cfg.opts_tc + cfg.spot_tc)
+}
+fn clears_conv(cfg: &ComboStratConfig, rate: f64, spot_ask: f64, call_bid: f64, put_ask: f64, strike: u64, tte: f64) -> bool {
+    edge(synthetic(rate, call_bid, put_ask, strike, tte), spot_ask) > (cfg.opts_tc + cfg.spot_tc)
+}
+// two synthetics, each paying option costs. Discounting each at its expiry's rate puts the
+// box's width, or a roll's carry between expiries, in their difference
+fn clears_pair(cfg: &ComboStratConfig, synth_long: f64, synth_short: f64) -> bool {
+    edge(synth_short, synth_long) > 2.0 * cfg.opts_tc
+}
+// The rate between two expiries implied by call minus put at one strike in each, taking
+// `near_rate` to the first. A roll clears when this is far enough from the rates it's priced at.
+pub fn implied_forward_rate(near_rate: f64, strike: u64, near_tte: f64, near_cp: f64, far_tte: f64, far_cp: f64) -> Option<f64> {
+    if far_tte <= near_tte {
+        return None;
+    }
+    // C - P = S - K·df at each expiry, so the difference is K·(df_near - df_far)
+    let df_near = (-near_rate * near_tte).exp();
+    let df_far = df_near - (far_cp - near_cp) / strike as f64;
+    if df_far <= 0.0 {
+        return None;
+    }
+    Some((df_near / df_far).ln() / (far_tte - near_tte))
 }
 
 impl ComboStrat {
     pub fn startup(config: ComboStratConfig) -> Self {
         let spec_table = ContractSpecTable::build().expect("Failed to Build Contract Table!");
+        Self::from_spec_table(config, spec_table)
+    }
+    pub fn from_spec_table(config: ComboStratConfig, spec_table: ContractSpecTable) -> Self {
+        let opts_chain = LedgerXOptionsChain::from_spec_table(&config.symbols.ledgerx, spec_table);
         Self {
-            spec_table: spec_table.clone(),
-            opts_chain: LedgerXOptionsChain::from_spec_table("CBTC",spec_table),
+            positions: PositionBook::from_chain(&opts_chain),
+            opts_chain,
             last_spot_tick: None,
+            last_spot_time: None,
+            spot_book: SpotOrderbook::default(),
+            thresholds: ThresholdIndex::default(),
+            arbs: ArbCounts::default(),
+            implied: ImpliedCurve::default(),
+            carry: config.carry.build(&config.symbols, config.funding_rate(), config.short_borrow_rate),
             config,
         }
     }
-    fn is_rev_arb(&self, spot_bid: &str, call_ask: f64, put_bid: f64, strike: u64, tte: f64) -> bool {
-        let spot_bid: f64 = f64::from_str(spot_bid).unwrap();
-        let synth_long = call_ask - put_bid + (strike as f64) * (-self.config.ann_borrow_rate * tte).exp();
-        
-        return 2.0*(spot_bid - synth_long) / (spot_bid + synth_long) > (self.config.opts_tc + self.config.spot_tc);
+    // the spot feed dropped; no arbs until a fresh tick arrives
+    pub fn reset_spot(&mut self) {
+        self.last_spot_tick = None;
+        self.last_spot_time = None;
+        self.spot_book.clear();
     }
-    fn is_conv_arb(&self, spot_ask: &str, call_bid: f64, put_ask: f64, strike: u64, tte: f64) -> bool {
-        let spot_ask: f64 = f64::from_str(spot_ask).unwrap();
-        let synth_short = call_bid - put_ask + (strike as f64) * (-self.config.ann_borrow_rate * tte).exp();
-        
-        return 2.0*(synth_short - spot_ask) / (synth_short + spot_ask) > (self.config.opts_tc + self.config.spot_tc);
+    pub fn process_depth_snapshot(&mut self, snapshot: &SpotDepthSnapshot) {
+        self.spot_book.apply_snapshot(snapshot);
+    }
+    // like book updates, depth only feeds sizing; the bookTicker drives the checks
+    pub fn process_depth_update(&mut self, event: &DepthOrderBookEvent) -> Result<(), BookError> {
+        self.spot_book.apply_diff(event)
+    }
+    // the options feed dropped; every quote on the chain is suspect
+    pub fn reset_opts(&mut self) {
+        for contr in self.opts_chain.contracts.iter_mut() {
+            contr.bid = None;
+            contr.bid_quantity = None;
+            contr.ask = None;
+            contr.ask_quantity = None;
+            contr.quote_time = None;
+            contr.book.clear();
+        }
+        self.thresholds.clear();
+        self.implied.clear();
+    }
+    // LedgerX contracts this strategy trades, for filtering the feed
+    pub fn contract_ids(&self) -> impl Iterator<Item = u64> + '_ {
+        self.opts_chain.id_map.keys().cloned()
+    }
+    pub fn contract_specs(&self) -> impl Iterator<Item = OptionContractSpec> + '_ {
+        self.opts_chain.contracts.iter().map(|contr| contr.spec.to_owned())
+    }
+    // what a combo on this contract's expiry is discounted at: its parity-implied rate when
+    // configured and there's a fit, otherwise what the carry model charges for the spot side
+    fn rate(&self, side: CarrySide, contract: &LedgerXOptionsContract) -> f64 {
+        match self.implied.rate(contract.spec.date_expires) {
+            Some(rate) if self.config.parity.use_implied_rates => rate,
+            _ => self.carry.rate(side, contract.tte),
+        }
+    }
+    fn is_rev_arb(&self, rate: f64, spot_bid: &str, call_ask: f64, put_bid: f64, strike: u64, tte: f64) -> bool {
+        clears_rev(&self.config, rate, f64::from_str(spot_bid).unwrap(), call_ask, put_bid, strike, tte)
+    }
+    fn is_conv_arb(&self, rate: f64, spot_ask: &str, call_bid: f64, put_ask: f64, strike: u64, tte: f64) -> bool {
+        clears_conv(&self.config, rate, f64::from_str(spot_ask).unwrap(), call_bid, put_ask, strike, tte)
     }
-    pub fn process_spot_update(&mut self, msg: WebsocketEvent) -> Option<Trade> {
+    // is_conv_arb and is_rev_arb solved for the spot price
+    fn level_thresholds(&self, call: &LedgerXOptionsContract, put: &LedgerXOptionsContract) -> LevelThresholds {
+        let discounted_strike = |side| (call.strike as f64) * (-self.rate(side, call) * call.tte).exp();
+        let tc = self.config.opts_tc + self.config.spot_tc;
+        LevelThresholds {
+            conv_below: match (call.bid, put.ask) {
+                (Some(bid), Some(ask)) => Some((bid - ask + discounted_strike(CarrySide::Long)) * (2.0 - tc) / (2.0 + tc)),
+                _ => None,
+            },
+            rev_above: match (call.ask, put.bid) {
+                (Some(ask), Some(bid)) => Some((ask - bid + discounted_strike(CarrySide::Short)) * (2.0 + tc) / (2.0 - tc)),
+                _ => None,
+            },
+        }
+    }
+    pub fn process_spot_update(&mut self, msg: WebsocketEvent, recv_time: DateTime<Utc>) -> Option<Trade> {
         let mut out = Trade::empty();
         let mut ret_flag = false;
         if let WebsocketEvent::BookTicker(spot_bt) = msg {
-            debug_assert!(self.config.symbol == spot_bt.symbol);
-
-            for call_ext in self.opts_chain.calls.iter() {
-                let call = call_ext.as_ref().borrow();
-                
-                if let Some(put_ext) = &call.adjacent {
-                    let put = put_ext.lattice_deref();
-                    let put = put.as_ref().borrow();
+            debug_assert!(self.config.symbols.binance == spot_bt.symbol);
 
-                    if self.arb_check(&mut out, call, put, &spot_bt) {
-                        ret_flag = true;
-                    }
+            // only levels this tick puts in the money are worth a full check
+            let spot_bid = f64::from_str(&spot_bt.best_bid).unwrap();
+            let spot_ask = f64::from_str(&spot_bt.best_ask).unwrap();
+            let mut found = ArbCounts::default();
+            for call_idx in self.thresholds.candidates(spot_bid, spot_ask) {
+                let call = self.opts_chain.get(call_idx);
+                let put = self.opts_chain.adjacent(call).expect("thresholds are only kept for paired levels");
+                if let Some(kind) = self.arb_check(&mut out, call, put, &spot_bt, recv_time, recv_time) {
+                    found.add(kind);
+                    ret_flag = true;
                 }
             }
+            self.arbs.merge(found);
 
             self.last_spot_tick = Some(spot_bt);
+            self.last_spot_time = Some(recv_time);
         }
 
         if ret_flag {
             out.net_out();
+            self.positions.submit_trade(&out);
             return Some(out);
         } else {
             return None;
         }
     }
 
-    pub fn process_opts_update(&self, msg: WebSocketMsg) -> Option<Trade> {
+    // a new rate moves every level's thresholds; combos are checked on the next quote
+    pub fn process_funding(&mut self, update: &FundingUpdate) {
+        if self.carry.on_funding(update) {
+            let expiries: Vec<DateTime<Utc>> = self.opts_chain.expiries().map(|b| b.expiration()).collect();
+            for expiry in expiries {
+                self.reprice_expiry(expiry);
+            }
+        }
+    }
+
+    // depth only changes how much an arb can size to, so book updates don't trigger a
+    // check; the BookTop that accompanies any change at the top does
+    pub fn process_book_update(&mut self, update: &BookUpdate) -> Result<(), BookError> {
+        match self.opts_chain.by_id_mut(update.contract_id()) {
+            Some(option) => option.book.apply(update),
+            None => Ok(()),
+        }
+    }
+
+    pub fn process_opts_update(&mut self, msg: WebSocketMsg, recv_time: DateTime<Utc>) -> Option<Trade> {
+        let out = self.check_opts_update(msg, recv_time)?;
+        self.positions.submit_trade(&out);
+        Some(out)
+    }
+
+    fn check_opts_update(&mut self, msg: WebSocketMsg, recv_time: DateTime<Utc>) -> Option<Trade> {
         let mut out = Trade::empty();
         if let WebSocketMsg::BookTop(new_bt) = msg {
-            // check that we're looking at an options contract
-            let contr = self.spec_table.id_table.get(&new_bt.contract_id).expect("Unseen Contract!");
-            if contr.as_opt_ref().is_some() {
-                let option_ref = self.opts_chain.id_map.get(&new_bt.contract_id)?;
-                let mut option = option_ref.as_ref().borrow_mut();
-
-                // update the chain
-                option.bid = Some(new_bt.bid);
-                option.bid_quantity = Some(new_bt.bid_size as f64);
-                option.ask = Some(new_bt.ask);
-                option.ask_quantity = Some(new_bt.ask_size as f64);
-                drop(option);
-
-                let option = option_ref.as_ref().borrow();
-
-                // if we have data on the adjacent option
-                let adj_option_ref = option.adjacent.to_owned()?;
-                let adj_option_inner = adj_option_ref.upgrade().expect("what the fuck");
-                let adj_option = adj_option_inner.as_ref().borrow();
-
-                // and if we have spot data, check out the possibility of arbs on this level
-                let spot_tick = self.last_spot_tick.as_ref()?;
-
-                let (call,put) = if option.is_call { (option,adj_option) } else { (adj_option,option) }; 
-
-                if self.arb_check(&mut out, call, put, spot_tick) {
-                    out.net_out();
-                    return Some(out);
+            // the chain only holds this underlying's options
+            let idx = *self.opts_chain.id_map.get(&new_bt.contract_id)?;
+
+            // update the chain
+            let option = self.opts_chain.get_mut(idx);
+            option.bid = Some(new_bt.bid);
+            option.bid_quantity = Some(new_bt.bid_size as f64);
+            option.ask = Some(new_bt.ask);
+            option.ask_quantity = Some(new_bt.ask_size as f64);
+            option.quote_time = Some(recv_time);
+            option.book.apply_top(new_bt.bid, new_bt.bid_size, new_bt.ask, new_bt.ask_size, new_bt.clock);
+
+            // a moved quote moves what parity implies for its expiry. Priced at that, the fit
+            // is kept current and the expiry's thresholds redone once the rate has really
+            // moved; otherwise it's only watched, and refit every so often
+            let expiry = option.spec.date_expires;
+            let use_implied = self.config.parity.use_implied_rates;
+            let watch = chrono::Duration::milliseconds(PARITY_WATCH_INTERVAL_MS);
+            if (use_implied || self.implied.refit_due(expiry, recv_time, watch))
+                && self.implied.refit(&self.opts_chain, expiry, &self.config.parity, recv_time)
+            {
+                self.log_refit(expiry);
+            }
+            if use_implied && self.implied.take_repricing(expiry, IMPLIED_RATE_TOLERANCE) {
+                self.reprice_expiry(expiry);
+            }
+
+            // if we have data on the adjacent option
+            let option = self.opts_chain.get(idx);
+            let adj_option = self.opts_chain.adjacent(option)?;
+            let (call,put) = if option.is_call { (option,adj_option) } else { (adj_option,option) }; 
+
+            // the level's quotes moved, so do the spot prices it pays at
+            let call_idx = if option.is_call { idx } else { option.adjacent? };
+            let thresholds = self.level_thresholds(call, put);
+            self.thresholds.update(call_idx, thresholds);
+
+            // and if we have spot data, check out the possibility of arbs on this level
+            let mut found = ArbCounts::default();
+            if let (Some(spot_tick), Some(spot_time)) = (self.last_spot_tick.as_ref(), self.last_spot_time) {
+                if let Some(kind) = self.arb_check(&mut out, call, put, spot_tick, spot_time, recv_time) {
+                    found.add(kind);
                 }
             }
+            // boxes and rolls don't involve spot
+            if self.config.box_spreads {
+                found.boxes += self.box_checks(&mut out, call, put, recv_time);
+            }
+            if self.config.jelly_rolls {
+                found.rolls += self.roll_checks(&mut out, call, put, recv_time);
+            }
+            self.arbs.merge(found);
+
+            if !out.is_empty() {
+                out.net_out();
+                return Some(out);
+            }
         }
         return None;
     }
 
-    fn arb_check(&self, out: &mut Trade, call: Ref<LedgerXOptionsContract>, put: Ref<LedgerXOptionsContract>, spot_tick: &BookTickerEvent) -> bool {
-        
+    fn reprice_expiry(&mut self, expiry: DateTime<Utc>) {
+        let block = match self.opts_chain.get_expiry(expiry) {
+            Some(block) => block,
+            None => return,
+        };
+        for level in block.iter_up() {
+            if let (Some(call), Some(put)) = (level.call, level.put) {
+                let thresholds = self.level_thresholds(self.opts_chain.get(call), self.opts_chain.get(put));
+                self.thresholds.update(call, thresholds);
+            }
+        }
+    }
+
+    // the implied rate next to the flat one, so the two can be compared
+    fn log_refit(&self, expiry: DateTime<Utc>) {
+        match self.implied.get(expiry) {
+            Some(fit) => debug!(
+                underlying = %self.config.symbols.ledgerx,
+                expiry = %expiry.to_rfc3339(),
+                tte = fit.tte,
+                forward = fit.forward,
+                implied_rate = fit.rate,
+                funding_rate = self.config.funding_rate(),
+                strikes = fit.strikes,
+                outliers = fit.outliers,
+                "parity refit",
+            ),
+            None => debug!(
+                underlying = %self.config.symbols.ledgerx,
+                expiry = %expiry.to_rfc3339(),
+                "too few strikes quoted to fit parity",
+            ),
+        }
+    }
+
+    // boxes between this level and every other strike of its expiry, either way round
+    fn box_checks(&self, out: &mut Trade, call: &LedgerXOptionsContract, put: &LedgerXOptionsContract, now: DateTime<Utc>) -> u64 {
+        let block = match self.opts_chain.get_expiry(call.spec.date_expires) {
+            Some(block) => block,
+            None => return 0,
+        };
+        let levels = block.iter_up().filter(|l| l.strike() != call.strike);
+        self.pair_checks(out, ArbKind::BoxSpread, (call, put), levels, now)
+    }
+
+    // rolls between this level and the same strike in every other expiry, either way round
+    fn roll_checks(&self, out: &mut Trade, call: &LedgerXOptionsContract, put: &LedgerXOptionsContract, now: DateTime<Utc>) -> u64 {
+        let levels = self.opts_chain.expiries()
+            .filter(|b| b.expiration() != call.spec.date_expires)
+            .filter_map(|b| b.level(call.strike));
+        self.pair_checks(out, ArbKind::JellyRoll, (call, put), levels, now)
+    }
+
+    // every pair of this level with the others that clears, either way round; only the best
+    // is traded, as each would take the same depth and room at this level
+    fn pair_checks<'a, I>(&self, out: &mut Trade, kind: ArbKind, level: (&LedgerXOptionsContract, &LedgerXOptionsContract), others: I, now: DateTime<Utc>) -> u64
+    where
+        I: Iterator<Item = &'a LedgerXOptionsLevel>,
+    {
+        let mut candidates = vec![];
+        for other in others {
+            let other = match (other.call, other.put) {
+                (Some(c), Some(p)) => (self.opts_chain.get(c), self.opts_chain.get(p)),
+                _ => continue,
+            };
+            for (long, short) in [(level, other), (other, level)] {
+                if let Some((synth_long, synth_short)) = self.pair_quote(long, short, now) {
+                    candidates.push((edge(synth_short, synth_long), long, short, synth_long, synth_short));
+                }
+            }
+        }
+        candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        // the best may be held back by the other level's working orders or room, the next not
+        for (_, long, short, synth_long, synth_short) in candidates {
+            if self.pair_check(out, long, short) {
+                self.log_pair(kind, long, short, synth_long, synth_short);
+                return 1;
+            }
+        }
+        0
+    }
+
+    // the long and short synthetics, if the pair is quoted fresh and clears costs
+    fn pair_quote(
+        &self,
+        long: (&LedgerXOptionsContract, &LedgerXOptionsContract),
+        short: (&LedgerXOptionsContract, &LedgerXOptionsContract),
+        now: DateTime<Utc>,
+    ) -> Option<(f64, f64)> {
+        let (long_call, long_put) = long;
+        let (short_call, short_put) = short;
+        if [long_call, long_put, short_call, short_put].iter().any(|c| !is_fresh(c.quote_time, self.config.max_opts_age_ms, now)) {
+            return None;
+        }
+        let (synth_long, synth_short) = match (long_call.ask, long_put.bid, short_call.bid, short_put.ask) {
+            (Some(call_ask), Some(put_bid), Some(call_bid), Some(put_ask)) => (
+                synthetic(self.rate(CarrySide::Long, long_call), call_ask, put_bid, long_call.strike, long_call.tte),
+                synthetic(self.rate(CarrySide::Long, short_call), call_bid, put_ask, short_call.strike, short_call.tte),
+            ),
+            _ => return None,
+        };
+        clears_pair(&self.config, synth_long, synth_short).then_some((synth_long, synth_short))
+    }
+
+    // long the synthetic at one level and short at another, LedgerX only
+    fn pair_check(
+        &self,
+        out: &mut Trade,
+        long: (&LedgerXOptionsContract, &LedgerXOptionsContract),
+        short: (&LedgerXOptionsContract, &LedgerXOptionsContract),
+    ) -> bool {
+        let (long_call, short_call) = (long.0, short.0);
+
+        // one combo in flight per level, counting what this update has already put in `out`;
+        // the next is sized once it has filled or been unwound
+        if self.positions.level_working_with(out, long_call.spec.date_expires, long_call.strike)
+            || self.positions.level_working_with(out, short_call.spec.date_expires, short_call.strike)
+        {
+            return false;
+        }
+        let held_long = self.positions.level_exposure_with(out, long_call.spec.date_expires, long_call.strike, PositionView::Committed).synthetic;
+        let held_short = self.positions.level_exposure_with(out, short_call.spec.date_expires, short_call.strike, PositionView::Committed).synthetic;
+        let room = (self.config.max_level_exposure - held_long).min(self.config.max_level_exposure + held_short);
+        let (long_rate, short_rate) = (self.rate(CarrySide::Long, long_call), self.rate(CarrySide::Long, short_call));
+        let clears = |long_call_ask, long_put_bid, short_call_bid, short_put_ask| clears_pair(
+            &self.config,
+            synthetic(long_rate, long_call_ask, long_put_bid, long_call.strike, long_call.tte),
+            synthetic(short_rate, short_call_bid, short_put_ask, short_call.strike, short_call.tte),
+        );
+        out.synthetic_pair(long, short, room, clears)
+    }
+
+    fn log_pair(
+        &self,
+        kind: ArbKind,
+        (long_call, long_put): (&LedgerXOptionsContract, &LedgerXOptionsContract),
+        (short_call, short_put): (&LedgerXOptionsContract, &LedgerXOptionsContract),
+        synth_long: f64,
+        synth_short: f64,
+    ) {
+        // both synthetics were quoted for the check to pass
+        let (long_call_ask, long_put_bid) = (long_call.ask.unwrap(), long_put.bid.unwrap());
+        let (short_call_bid, short_put_ask) = (short_call.bid.unwrap(), short_put.ask.unwrap());
+        let edge = edge(synth_short, synth_long);
+        let threshold = 2.0 * self.config.opts_tc;
+        match kind {
+            ArbKind::BoxSpread => info!(
+                kind = "box",
+                underlying = %self.config.symbols.ledgerx,
+                expiry = %long_call.spec.date_expires.to_rfc3339(),
+                long_strike = long_call.strike,
+                short_strike = short_call.strike,
+                tte = long_call.tte,
+                long_call_ask,
+                long_put_bid,
+                short_call_bid,
+                short_put_ask,
+                synth_long,
+                synth_short,
+                edge,
+                threshold,
+                "arb detected",
+            ),
+            ArbKind::JellyRoll => {
+                let long_cp = long_call_ask - long_put_bid;
+                let short_cp = short_call_bid - short_put_ask;
+                let ((near, near_cp), (far, far_cp)) = if long_call.tte < short_call.tte {
+                    ((long_call, long_cp), (short_call, short_cp))
+                } else {
+                    ((short_call, short_cp), (long_call, long_cp))
+                };
+                let (near_rate, far_rate) = (self.rate(CarrySide::Long, near), self.rate(CarrySide::Long, far));
+                // the forward rate the two expiries were priced at
+                let priced_forward_rate = (far_rate * far.tte - near_rate * near.tte) / (far.tte - near.tte);
+                info!(
+                    kind = "jelly_roll",
+                    underlying = %self.config.symbols.ledgerx,
+                    strike = long_call.strike,
+                    long_expiry = %long_call.spec.date_expires.to_rfc3339(),
+                    short_expiry = %short_call.spec.date_expires.to_rfc3339(),
+                    long_tte = long_call.tte,
+                    short_tte = short_call.tte,
+                    long_call_ask,
+                    long_put_bid,
+                    short_call_bid,
+                    short_put_ask,
+                    synth_long,
+                    synth_short,
+                    implied_forward_rate = implied_forward_rate(near_rate, long_call.strike, near.tte, near_cp, far.tte, far_cp).unwrap_or(f64::NAN),
+                    priced_forward_rate,
+                    edge,
+                    threshold,
+                    "arb detected",
+                );
+            },
+            ArbKind::Conversion | ArbKind::Reversal => unreachable!("combos are logged by log_arb"),
+        }
+    }
+
+    fn arb_check(
+        &self,
+        out: &mut Trade,
+        call: &LedgerXOptionsContract,
+        put: &LedgerXOptionsContract,
+        spot_tick: &BookTickerEvent,
+        spot_time: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> Option<ArbKind> {
+        // an old quote on any leg can pair with a fresh one to show a phantom arb
+        if !is_fresh(Some(spot_time), self.config.max_spot_age_ms, now)
+            || !is_fresh(call.quote_time, self.config.max_opts_age_ms, now)
+            || !is_fresh(put.quote_time, self.config.max_opts_age_ms, now)
+        {
+            return None;
+        }
+
+        // a conversion holds spot long, a reversal short
+        let (conv_rate, rev_rate) = (self.rate(CarrySide::Long, call), self.rate(CarrySide::Short, call));
         let conv = match (call.bid, put.ask) {
-            (Some(bid), Some(ask)) => self.is_conv_arb(&spot_tick.best_ask, bid, ask, call.strike, call.tte),
+            (Some(bid), Some(ask)) => self.is_conv_arb(conv_rate, &spot_tick.best_ask, bid, ask, call.strike, call.tte),
             _ => false,
         };
 
         let rev = match (call.ask, put.bid) {
-            (Some(ask), Some(bid)) => self.is_rev_arb(&spot_tick.best_bid, ask, bid, call.strike, call.tte),
+            (Some(ask), Some(bid)) => self.is_rev_arb(rev_rate, &spot_tick.best_bid, ask, bid, call.strike, call.tte),
             _ => false,
         };
 
         debug_assert!(!(conv && rev));
-        
-        if conv {
-            return out.conversion(&self.config, &spot_tick, &call, &put);
+
+        // the arb on a level we're still working would just be the same quotes firing again
+        if (conv || rev) && self.positions.level_working_with(out, call.spec.date_expires, call.strike) {
+            return None;
         }
-        if rev {
-            return out.reversal(&self.config, &spot_tick, &call, &put);
+
+        // don't stack more of the same synthetic on a level than the config allows;
+        // a conversion leaves us short the synthetic, a reversal long
+        let held = self.positions.level_exposure_with(out, call.spec.date_expires, call.strike, PositionView::Committed);
+
+        let kind = if conv {
+            let room = self.config.max_level_exposure + held.synthetic;
+            let spot = SpotLiquidity::new(&self.spot_book, spot_tick, true);
+            out.conversion(&self.config, conv_rate, &spot, call, put, room).then_some(ArbKind::Conversion)
+        } else if rev {
+            let room = self.config.max_level_exposure - held.synthetic;
+            let spot = SpotLiquidity::new(&self.spot_book, spot_tick, false);
+            out.reversal(&self.config, rev_rate, &spot, call, put, room).then_some(ArbKind::Reversal)
+        } else {
+            None
+        };
+        if let Some(kind) = kind {
+            let rate = if kind == ArbKind::Conversion { conv_rate } else { rev_rate };
+            self.log_arb(kind, rate, call, put, spot_tick);
         }
+        kind
+    }
 
-        false
+    // everything the decision was made on, so it can be audited afterward
+    fn log_arb(&self, kind: ArbKind, rate: f64, call: &LedgerXOptionsContract, put: &LedgerXOptionsContract, spot_tick: &BookTickerEvent) {
+        let spot_bid = f64::from_str(&spot_tick.best_bid).unwrap();
+        let spot_ask = f64::from_str(&spot_tick.best_ask).unwrap();
+        // both legs were quoted for the check to pass
+        let (call_px, put_px, kind_name) = match kind {
+            ArbKind::Conversion => (call.bid.unwrap(), put.ask.unwrap(), "conversion"),
+            ArbKind::Reversal => (call.ask.unwrap(), put.bid.unwrap(), "reversal"),
+            ArbKind::BoxSpread | ArbKind::JellyRoll => unreachable!("spreads are logged by log_pair"),
+        };
+        let synthetic = synthetic(rate, call_px, put_px, call.strike, call.tte);
+        let edge = if kind == ArbKind::Conversion { edge(synthetic, spot_ask) } else { edge(spot_bid, synthetic) };
+        info!(
+            kind = kind_name,
+            underlying = %self.config.symbols.ledgerx,
+            expiry = %call.spec.date_expires.to_rfc3339(),
+            strike = call.strike,
+            tte = call.tte,
+            rate,
+            call_px,
+            put_px,
+            spot_bid,
+            spot_ask,
+            synthetic,
+            edge,
+            threshold = self.config.opts_tc + self.config.spot_tc,
+            "arb detected",
+        );
     }
 }
 
 
 #[cfg(test)]
-mod tests {
+pub mod tests {
     use std::collections::HashMap;
     use std::rc::Rc;
 
@@ -281,11 +921,35 @@ mod tests {
     use ftx_us_derivs::ws::{WebSocketMsg, BookTop};
 
     use crate::{UniversalMsgWrapper, do_trade};
-    use crate::options_chain::LedgerXOptionsChain;
+    use crate::book::{BookLevel, BookUpdate, SpotDepthSnapshot};
+    use crate::carry::{CarryConfig, FundingKind, FundingUpdate};
+    use crate::execution::{MockVenue, TradeStatus};
+    use crate::logging::tests::capture_json;
+    use crate::parity::ParityConfig;
+    use crate::symbols::SymbolMapping;
 
-    use super::{ComboStrat, ComboStratConfig, Trade, BinanceMarketOrder};
+    use super::{ComboStrat, ComboStratConfig, Trade, BinanceMarketOrder, ArbCounts, implied_forward_rate};
 
-    fn mock_contract_table() -> ContractSpecTable {
+    // CBTC against BTCUSDT at a flat 2%, with no costs or caps; tests override what they need
+    pub fn mock_config() -> ComboStratConfig {
+        ComboStratConfig {
+            symbols: SymbolMapping::new("CBTC", "BTCUSDT"),
+            ann_borrow_rate: 0.02,
+            long_funding_rate: None,
+            short_borrow_rate: 0.0,
+            opts_tc: 0.0,
+            spot_tc: 0.0,
+            max_level_exposure: f64::INFINITY,
+            max_opts_age_ms: 10_000,
+            max_spot_age_ms: 1_000,
+            box_spreads: false,
+            jelly_rolls: false,
+            parity: ParityConfig::default(),
+            carry: CarryConfig::default(),
+        }
+    }
+
+    pub fn mock_contract_table() -> ContractSpecTable {
         let call = OptionContractSpec {
             id: 22248027,
             label: "BTC-Mini-30JUN2023-10000-Call".to_string(),
@@ -339,11 +1003,11 @@ mod tests {
             )), 
             // call update
             UniversalMsgWrapper::LedgerX(WebSocketMsg::BookTop(
-                BookTop { bid: 11070.0, bid_size: 1, ask: 11180.0, ask_size: 1, contract_id: 22248027, contract_type: 0, clock: 0 }
+                BookTop { bid: 11070.0, bid_size: 100, ask: 11180.0, ask_size: 100, contract_id: 22248027, contract_type: 0, clock: 0 }
             )),
             // put update
             UniversalMsgWrapper::LedgerX(WebSocketMsg::BookTop(
-                BookTop { bid: 500.0, bid_size: 1, ask: 580.0, ask_size: 1, contract_id: 22248028, contract_type: 0, clock: 0 }
+                BookTop { bid: 500.0, bid_size: 100, ask: 580.0, ask_size: 100, contract_id: 22248028, contract_type: 0, clock: 0 }
             )),
             // arbitrage-able spot update
             UniversalMsgWrapper::Binance(binance::websockets::WebsocketEvent::BookTicker(
@@ -355,30 +1019,600 @@ mod tests {
 
     #[test]
     fn find_arb() {
-        let table = mock_contract_table();
-        let chain = LedgerXOptionsChain::from_spec_table("CBTC", table.to_owned());
+        let mut strat = ComboStrat::from_spec_table(mock_config(), mock_contract_table());
+        let t0 = DateTime::<Utc>::MIN_UTC;
+        let mut bn_venue = MockVenue::new("binance");
+        let mut lx_venue = MockVenue::new("ledgerx");
+        let mut trades = vec![];
+        for msg in mock_msg_stream() {
+            let out = match msg {
+                UniversalMsgWrapper::Binance(bn) => strat.process_spot_update(bn, t0),
+                UniversalMsgWrapper::LedgerX(lx) => strat.process_opts_update(lx, t0),
+                _ => None,
+            };
+            if let Some(t) = out {
+                let report = do_trade(&t, &mut bn_venue, &mut lx_venue);
+                assert_eq!(report.status(), TradeStatus::Complete);
+                trades.push(t);
+            }
+        }
+
+        // only the last tick is an arb: a conversion of 0.5 coin, 50 contracts a leg
+        assert_eq!(trades.len(), 1);
+        let t = &trades[0];
+        assert_eq!(t.binance.len(), 1);
+        assert!(t.binance[0].is_buy);
+        assert!((t.binance[0].qty - 0.5).abs() < 1e-9);
+        let legs: Vec<(u64, bool, f64, u64)> = t.ledgerx.iter().map(|o| (o.contract_id, o.is_ask, o.price, o.size)).collect();
+        assert_eq!(legs, vec![(22248027, true, 11070.0, 50), (22248028, false, 580.0, 50)]);
+        assert_eq!(bn_venue.sent.len(), 1);
+        assert_eq!(lx_venue.sent.len(), 2);
+    }
+
+    // as if every leg of `t` went through in full
+    fn fill(strat: &mut ComboStrat, t: &Trade) {
+        for order in t.binance.iter() {
+            strat.positions.fill_binance(&order.symbol, order.is_buy, order.qty);
+        }
+        for order in t.ledgerx.iter() {
+            strat.positions.fill_ledgerx(order.contract_id, order.is_ask, order.size);
+        }
+    }
+
+    #[test]
+    fn working_level_not_refired() {
+        let mut strat = ComboStrat::from_spec_table(mock_config(), mock_contract_table());
+        let t0 = DateTime::<Utc>::MIN_UTC;
+        let mut msgs = mock_msg_stream();
+        let arb_tick = match msgs.pop() {
+            Some(UniversalMsgWrapper::Binance(bn)) => bn,
+            _ => unreachable!(),
+        };
+        for msg in msgs {
+            match msg {
+                UniversalMsgWrapper::Binance(bn) => { strat.process_spot_update(bn, t0); },
+                UniversalMsgWrapper::LedgerX(lx) => { strat.process_opts_update(lx, t0); },
+                _ => unreachable!(),
+            }
+        }
+
+        // no cap, but the same quotes don't fire again while the first conversion is out
+        let first = strat.process_spot_update(arb_tick.clone(), t0).unwrap();
+        assert!(strat.process_spot_update(arb_tick.clone(), t0).is_none());
+
+        // once it has filled the level is free again
+        fill(&mut strat, &first);
+        assert!(strat.process_spot_update(arb_tick, t0).is_some());
+        assert_eq!(strat.arbs, ArbCounts { conversions: 2, ..ArbCounts::default() });
+    }
+
+    #[test]
+    fn level_exposure_caps_repeat_arbs() {
         let cfg = ComboStratConfig {
+            max_level_exposure: 0.5,
+            ..mock_config()
+        };
+        let mut strat = ComboStrat::from_spec_table(cfg, mock_contract_table());
+
+        let t0 = DateTime::<Utc>::MIN_UTC;
+        let mut msgs = mock_msg_stream();
+        let arb_tick = match msgs.pop() {
+            Some(UniversalMsgWrapper::Binance(bn)) => bn,
+            _ => unreachable!(),
+        };
+        for msg in msgs {
+            match msg {
+                UniversalMsgWrapper::Binance(bn) => assert!(strat.process_spot_update(bn, t0).is_none()),
+                UniversalMsgWrapper::LedgerX(lx) => assert!(strat.process_opts_update(lx, t0).is_none()),
+                _ => unreachable!(),
+            }
+        }
+
+        // the first conversion uses up the whole level; even filled, the same tick again does nothing
+        let (logs, sub) = capture_json("combo_trading::strat=info");
+        let first = tracing::subscriber::with_default(sub, || strat.process_spot_update(arb_tick.clone(), t0));
+        fill(&mut strat, &first.unwrap());
+        assert!(strat.process_spot_update(arb_tick, t0).is_none());
+        assert_eq!(strat.arbs, ArbCounts { conversions: 1, ..ArbCounts::default() });
+
+        // with everything it was decided on
+        let logged = logs.lines();
+        assert_eq!(logged.len(), 1);
+        assert_eq!(logged[0]["message"], "arb detected");
+        assert_eq!(logged[0]["kind"], "conversion");
+        for field in ["strike", "tte", "call_px", "put_px", "spot_bid", "spot_ask", "synthetic"] {
+            assert!(logged[0][field].is_number(), "{} not logged", field);
+        }
+        assert!(logged[0]["edge"].as_f64().unwrap() > logged[0]["threshold"].as_f64().unwrap());
+    }
+
+    #[test]
+    fn stale_quotes_skipped() {
+        let mut strat = ComboStrat::from_spec_table(mock_config(), mock_contract_table());
+
+        let t0 = DateTime::<Utc>::MIN_UTC;
+        let later = t0 + chrono::Duration::seconds(20);
+        let mut msgs = mock_msg_stream();
+        let arb_tick = match msgs.pop() {
+            Some(UniversalMsgWrapper::Binance(bn)) => bn,
+            _ => unreachable!(),
+        };
+        for msg in msgs {
+            match msg {
+                UniversalMsgWrapper::Binance(bn) => assert!(strat.process_spot_update(bn, t0).is_none()),
+                UniversalMsgWrapper::LedgerX(lx) => assert!(strat.process_opts_update(lx, t0).is_none()),
+                _ => unreachable!(),
+            }
+        }
+
+        // both option quotes are 20s old by the time spot moves
+        assert!(strat.process_spot_update(arb_tick, later).is_none());
+
+        // a fresh call alone still pairs with a stale put, the fresh put completes the pair
+        let mut requotes = mock_msg_stream().into_iter().filter_map(|msg| match msg {
+            UniversalMsgWrapper::LedgerX(lx) => Some(lx),
+            _ => None,
+        });
+        assert!(strat.process_opts_update(requotes.next().unwrap(), later).is_none());
+        assert!(strat.process_opts_update(requotes.next().unwrap(), later).is_some());
+    }
+
+    #[test]
+    fn sizes_across_book_levels() {
+        let mut strat = ComboStrat::from_spec_table(mock_config(), mock_contract_table());
+        let t0 = DateTime::<Utc>::MIN_UTC;
+        let level = |is_ask, price, size| BookLevel { is_ask, price, size };
+
+        // the second level of each book still clears against a 20300 spot ask, the third doesn't
+        strat.process_book_update(&BookUpdate::Snapshot {
+            contract_id: 22248027,
+            clock: 5,
+            levels: vec![level(false, 11070.0, 40), level(false, 11040.0, 100), level(false, 11000.0, 100), level(true, 11180.0, 100)],
+        }).unwrap();
+        strat.process_book_update(&BookUpdate::Snapshot {
+            contract_id: 22248028,
+            clock: 5,
+            levels: vec![level(false, 500.0, 100), level(true, 580.0, 40), level(true, 590.0, 200), level(true, 600.0, 100)],
+        }).unwrap();
+        let tops = [
+            BookTop { bid: 11070.0, bid_size: 40, ask: 11180.0, ask_size: 100, contract_id: 22248027, contract_type: 0, clock: 5 },
+            BookTop { bid: 500.0, bid_size: 100, ask: 580.0, ask_size: 40, contract_id: 22248028, contract_type: 0, clock: 5 },
+        ];
+        for bt in tops {
+            assert!(strat.process_opts_update(WebSocketMsg::BookTop(bt), t0).is_none());
+        }
+
+        let tick = BookTickerEvent {
+            update_id: 0,
             symbol: "BTCUSDT".to_string(),
-            ann_borrow_rate: 0.02,
-            opts_tc: 0.0,
-            spot_tc: 0.0,
+            best_bid: "20299.0".to_string(),
+            best_bid_qty: "10.0".to_string(),
+            best_ask: "20300.0".to_string(),
+            best_ask_qty: "10.0".to_string(),
         };
+        let trade = strat.process_spot_update(binance::websockets::WebsocketEvent::BookTicker(tick), t0).unwrap();
 
-        let mut strat = ComboStrat {
-            opts_chain: chain,
-            spec_table: table.to_owned(),
-            last_spot_tick: None,
-            config: cfg,
+        // 140 contracts clear, half is traded: 40 at the top and 30 from the next level
+        assert_eq!(trade.binance.len(), 1);
+        assert!((trade.binance[0].qty - 0.7).abs() < 1e-9);
+        let call = trade.ledgerx.iter().find(|o| o.contract_id == 22248027).unwrap();
+        let put = trade.ledgerx.iter().find(|o| o.contract_id == 22248028).unwrap();
+        assert_eq!((call.is_ask, call.price, call.size), (true, 11040.0, 70));
+        assert_eq!((put.is_ask, put.price, put.size), (false, 590.0, 70));
+    }
+
+    #[test]
+    fn spot_leg_priced_from_depth() {
+        let mut strat = ComboStrat::from_spec_table(mock_config(), mock_contract_table());
+        let t0 = DateTime::<Utc>::MIN_UTC;
+
+        // the synthetic is worth ~20358.9, so only the first 0.5 of the offer is cheap enough
+        strat.process_depth_snapshot(&SpotDepthSnapshot {
+            symbol: "BTCUSDT".to_string(),
+            last_update_id: 1,
+            bids: vec![(20299.0, 1.0)],
+            asks: vec![(20300.0, 0.2), (20310.0, 0.3), (20400.0, 5.0)],
+        });
+        let mut msgs = mock_msg_stream();
+        let arb_tick = match msgs.pop() {
+            Some(UniversalMsgWrapper::Binance(bn)) => bn,
+            _ => unreachable!(),
+        };
+        for msg in msgs {
+            match msg {
+                UniversalMsgWrapper::Binance(bn) => assert!(strat.process_spot_update(bn, t0).is_none()),
+                UniversalMsgWrapper::LedgerX(lx) => assert!(strat.process_opts_update(lx, t0).is_none()),
+                _ => unreachable!(),
+            }
+        }
+        let trade = strat.process_spot_update(arb_tick, t0).unwrap();
+
+        // half of the 0.5 that clears, filled 0.2 @ 20300 and 0.05 @ 20310
+        assert!((trade.binance[0].qty - 0.25).abs() < 1e-9);
+        assert!((trade.binance[0].price - 20302.0).abs() < 1e-6);
+        assert!(trade.ledgerx.iter().all(|o| o.size == 25));
+    }
+
+    #[test]
+    fn thresholds_match_arb_checks() {
+        let cfg = ComboStratConfig {
+            opts_tc: 0.001,
+            spot_tc: 0.0005,
+            ..mock_config()
         };
+        let mut strat = ComboStrat::from_spec_table(cfg, mock_contract_table());
+        let t0 = DateTime::<Utc>::MIN_UTC;
         for msg in mock_msg_stream() {
-            let out = match dbg!(msg) {
-                UniversalMsgWrapper::Binance(bn) => strat.process_spot_update(bn),
-                UniversalMsgWrapper::LedgerX(lx) => strat.process_opts_update(lx),
+            if let UniversalMsgWrapper::LedgerX(lx) = msg {
+                strat.process_opts_update(lx, t0);
+            }
+        }
+
+        let call_idx = strat.opts_chain.id_map[&22248027];
+        let levels = strat.thresholds.get(call_idx).unwrap();
+        let (conv_below, rev_above) = (levels.conv_below.unwrap(), levels.rev_above.unwrap());
+        let call = strat.opts_chain.get(call_idx);
+        let put = strat.opts_chain.adjacent(call).unwrap();
+        let (strike, tte) = (call.strike, call.tte);
+
+        // either side of each threshold agrees with the direct check
+        let conv = |ask: f64| strat.is_conv_arb(strat.config.ann_borrow_rate, &ask.to_string(), call.bid.unwrap(), put.ask.unwrap(), strike, tte);
+        let rev = |bid: f64| strat.is_rev_arb(strat.config.ann_borrow_rate, &bid.to_string(), call.ask.unwrap(), put.bid.unwrap(), strike, tte);
+        assert!(conv(conv_below - 0.01) && !conv(conv_below + 0.01));
+        assert!(rev(rev_above + 0.01) && !rev(rev_above - 0.01));
+        assert_eq!(strat.thresholds.candidates(conv_below + 1.0, conv_below - 1.0), vec![call_idx]);
+        assert!(strat.thresholds.candidates(rev_above - 1.0, conv_below + 1.0).is_empty());
+
+        strat.reset_opts();
+        assert!(strat.thresholds.candidates(f64::INFINITY, 0.0).is_empty());
+    }
+
+    // the mock level plus a second one, copied from it with `edit`, under ids 22248029/30
+    fn second_level_table(edit: impl Fn(&mut OptionContractSpec)) -> ContractSpecTable {
+        let mut table = mock_contract_table();
+        for (id, from) in [(22248029, 22248027), (22248030, 22248028)] {
+            let mut spec = match &*table.id_table[&from] {
+                ContractSpec::Option(spec) => OptionContractSpec { id, ..spec.clone() },
+                _ => unreachable!(),
             };
-            if let Some(t) = out {
-                assert!(do_trade(t));
+            edit(&mut spec);
+            table.id_table.insert(id, Rc::new(ContractSpec::Option(spec)));
+        }
+        table
+    }
+
+    // feeds option quotes only, with just the given spreads enabled
+    fn run_spreads(table: ContractSpecTable, box_spreads: bool, jelly_rolls: bool, quotes: &[(u64, f64, f64)]) -> (Vec<Trade>, ArbCounts) {
+        let cfg = ComboStratConfig { opts_tc: 0.001, box_spreads, jelly_rolls, ..mock_config() };
+        let mut strat = ComboStrat::from_spec_table(cfg, table);
+        let t0 = DateTime::<Utc>::MIN_UTC;
+        let trades = quotes.iter()
+            .filter_map(|&(contract_id, bid, ask)| {
+                let bt = BookTop { bid, bid_size: 100, ask, ask_size: 100, contract_id, contract_type: 0, clock: 0 };
+                strat.process_opts_update(WebSocketMsg::BookTop(bt), t0)
+            })
+            .collect();
+        (trades, strat.arbs)
+    }
+
+    #[test]
+    fn one_combo_per_level_per_update() {
+        // the box test's quotes, with spot rich against the 10000 synthetic as well
+        let cfg = ComboStratConfig { opts_tc: 0.001, box_spreads: true, ..mock_config() };
+        let mut strat = ComboStrat::from_spec_table(cfg, second_level_table(|spec| spec.strike_price = 12000));
+        let t0 = DateTime::<Utc>::MIN_UTC;
+        let spot = BookTickerEvent {
+            update_id: 0,
+            symbol: "BTCUSDT".to_string(),
+            best_bid: "20630.0".to_string(),
+            best_bid_qty: "1.0".to_string(),
+            best_ask: "20631.0".to_string(),
+            best_ask_qty: "1.0".to_string(),
+        };
+        assert!(strat.process_spot_update(binance::websockets::WebsocketEvent::BookTicker(spot), t0).is_none());
+        let quotes = [
+            (22248029, 9300.0, 9400.0),
+            (22248030, 450.0, 500.0),
+            (22248027, 11070.0, 11180.0),
+        ];
+        for (contract_id, bid, ask) in quotes {
+            let bt = BookTop { bid, bid_size: 100, ask, ask_size: 100, contract_id, contract_type: 0, clock: 0 };
+            assert!(strat.process_opts_update(WebSocketMsg::BookTop(bt), t0).is_none());
+        }
+
+        // the 10000 put's quote completes both a reversal and a box there; the box waits
+        // on the reversal already in the update
+        let bt = BookTop { bid: 500.0, bid_size: 100, ask: 580.0, ask_size: 100, contract_id: 22248028, contract_type: 0, clock: 0 };
+        let t = strat.process_opts_update(WebSocketMsg::BookTop(bt), t0).unwrap();
+        assert_eq!(t.binance.len(), 1);
+        assert_eq!(legs(&t), vec![(22248027, false, 11180.0, 50), (22248028, true, 500.0, 50)]);
+        assert_eq!(strat.arbs, ArbCounts { reversals: 1, ..ArbCounts::default() });
+    }
+
+    #[test]
+    fn coin_borrow_only_moves_reversals() {
+        let cfg = ComboStratConfig {
+            carry: CarryConfig::Funding { coin: "BTC".to_string(), cash: "USDT".to_string() },
+            ..mock_config()
+        };
+        let mut strat = ComboStrat::from_spec_table(cfg, mock_contract_table());
+        let t0 = DateTime::<Utc>::MIN_UTC;
+        for msg in mock_msg_stream() {
+            if let UniversalMsgWrapper::LedgerX(lx) = msg {
+                strat.process_opts_update(lx, t0);
             }
         }
+        let tick = |bid: f64| binance::websockets::WebsocketEvent::BookTicker(BookTickerEvent {
+            update_id: 0,
+            symbol: "BTCUSDT".to_string(),
+            best_bid: bid.to_string(),
+            best_bid_qty: "1.0".to_string(),
+            best_ask: (bid + 1.0).to_string(),
+            best_ask_qty: "1.0".to_string(),
+        });
+
+        // spot over the synthetic's ask reverses at the flat rate
+        let rev = strat.process_spot_update(tick(20600.0), t0).unwrap();
+        assert!(!rev.binance[0].is_buy);
+        fill(&mut strat, &rev);
+
+        // ~11% a year to borrow the coin eats that edge, without touching conversions
+        strat.process_funding(&FundingUpdate { kind: FundingKind::MarginInterest, symbol: "BTC".to_string(), rate: 0.0003 });
+        assert!(strat.process_spot_update(tick(20600.0), t0).is_none());
+        assert!(strat.process_spot_update(tick(20299.0), t0).is_some_and(|t| t.binance[0].is_buy));
+        assert_eq!(strat.arbs, ArbCounts { conversions: 1, reversals: 1, ..ArbCounts::default() });
+    }
+
+    #[test]
+    fn implied_rate_reprices_expiry() {
+        // the mock 10000 level and three more, ids counting up from 22248029
+        let table = || {
+            let mut table = mock_contract_table();
+            for (i, strike) in [8000, 12000, 14000].into_iter().enumerate() {
+                for (from, id) in [(22248027, 22248029 + 2 * i as u64), (22248028, 22248030 + 2 * i as u64)] {
+                    let spec = match &*table.id_table[&from] {
+                        ContractSpec::Option(spec) => OptionContractSpec { id, strike_price: strike, ..spec.clone() },
+                        _ => unreachable!(),
+                    };
+                    table.id_table.insert(id, Rc::new(ContractSpec::Option(spec)));
+                }
+            }
+            table
+        };
+        // quoted around a 20500 forward at 10%, well above the 2% flat rate
+        let tte: f64 = 0.6600180575256285;
+        let df = (-0.10 * tte).exp();
+        let mut quotes = vec![];
+        for (call_id, strike, put_mid) in [(22248029, 8000.0, 20.0), (22248027, 10000.0, 60.0), (22248031, 12000.0, 150.0), (22248033, 14000.0, 400.0)] {
+            let call_mid = put_mid + df * (20500.0 - strike);
+            quotes.push((call_id, call_mid - 5.0, call_mid + 5.0));
+            quotes.push((call_id + 1, put_mid - 5.0, put_mid + 5.0));
+        }
+        // spot right at the forward's present value
+        let spot = BookTickerEvent {
+            update_id: 0,
+            symbol: "BTCUSDT".to_string(),
+            best_bid: (df * 20500.0 - 0.5).to_string(),
+            best_bid_qty: "1.0".to_string(),
+            best_ask: (df * 20500.0 + 0.5).to_string(),
+            best_ask_qty: "1.0".to_string(),
+        };
+
+        let t0 = DateTime::<Utc>::MIN_UTC;
+        let run = |use_implied_rates: bool| {
+            let cfg = ComboStratConfig {
+                parity: ParityConfig { use_implied_rates, ..ParityConfig::default() },
+                ..mock_config()
+            };
+            let mut strat = ComboStrat::from_spec_table(cfg, table());
+            let quote = |strat: &mut ComboStrat, &(contract_id, bid, ask): &(u64, f64, f64), now| {
+                let bt = BookTop { bid, bid_size: 100, ask, ask_size: 100, contract_id, contract_type: 0, clock: 0 };
+                assert!(strat.process_opts_update(WebSocketMsg::BookTop(bt), now).is_none());
+            };
+            for q in quotes.iter() {
+                quote(&mut strat, q, t0);
+            }
+            // only watched, the expiry was fit on its first quote and not since
+            assert_eq!(strat.implied.get(t0).is_some(), use_implied_rates);
+            let later = t0 + chrono::Duration::seconds(1);
+            quote(&mut strat, quotes.last().unwrap(), later);
+            let fit = *strat.implied.get(t0).unwrap();
+            (strat.process_spot_update(binance::websockets::WebsocketEvent::BookTicker(spot.clone()), later), fit)
+        };
+
+        // fit either way; at the flat rate every strike's synthetic looks rich against spot
+        let (trade, fit) = run(false);
+        assert!((fit.rate - 0.10).abs() < 1e-6 && (fit.forward - 20500.0).abs() < 1e-3);
+        assert_eq!(fit.strikes, 4);
+        assert!(trade.is_some_and(|t| t.binance.len() == 1 && t.binance[0].is_buy));
+        // at the rate the options imply they're fairly priced
+        assert!(run(true).0.is_none());
+    }
+
+    fn legs(t: &Trade) -> Vec<(u64, bool, f64, u64)> {
+        t.ledgerx.iter().map(|o| (o.contract_id, o.is_ask, o.price, o.size)).collect()
+    }
+
+    #[test]
+    fn box_spread_between_strikes() {
+        // a second level at 12000 in the same expiry
+        let table = || second_level_table(|spec| spec.strike_price = 12000);
+        // the 12000 synthetic bids above where the 10000 one is offered, by more than the
+        // discounted 2000 width
+        let quotes = [
+            (22248027, 11070.0, 11180.0),
+            (22248028, 500.0, 580.0),
+            (22248029, 9300.0, 9400.0),
+            (22248030, 450.0, 500.0),
+        ];
+
+        assert!(run_spreads(table(), false, true, &quotes).0.is_empty());
+
+        let (trades, arbs) = run_spreads(table(), true, false, &quotes);
+        assert_eq!(trades.len(), 1);
+        assert_eq!(arbs, ArbCounts { boxes: 1, ..ArbCounts::default() });
+        assert!(trades[0].binance.is_empty());
+        // long the 10000 synthetic, short the 12000 one, half the top of book
+        assert_eq!(legs(&trades[0]), vec![
+            (22248027, false, 11180.0, 50),
+            (22248028, true, 500.0, 50),
+            (22248029, true, 9300.0, 50),
+            (22248030, false, 500.0, 50),
+        ]);
+    }
+
+    #[test]
+    fn box_sweeps_depth() {
+        let cfg = ComboStratConfig { opts_tc: 0.001, box_spreads: true, ..mock_config() };
+        let mut strat = ComboStrat::from_spec_table(cfg, second_level_table(|spec| spec.strike_price = 12000));
+        let t0 = DateTime::<Utc>::MIN_UTC;
+        let level = |is_ask, price, size| BookLevel { is_ask, price, size };
+        // the box test's quotes; the 10000 call offer thins out after 60 and no longer
+        // clears at 11500, everything else is deep
+        let books = [
+            (22248029, vec![level(false, 9300.0, 300), level(true, 9400.0, 300)]),
+            (22248030, vec![level(false, 450.0, 300), level(true, 500.0, 300)]),
+            (22248027, vec![level(false, 11070.0, 300), level(true, 11180.0, 60), level(true, 11200.0, 100), level(true, 11500.0, 100)]),
+            (22248028, vec![level(false, 500.0, 300), level(true, 580.0, 300)]),
+        ];
+        let mut trades = vec![];
+        for (contract_id, levels) in books {
+            strat.process_book_update(&BookUpdate::Snapshot { contract_id, clock: 1, levels: levels.clone() }).unwrap();
+            let (bid, ask) = (&levels[0], &levels[1]);
+            let bt = BookTop { bid: bid.price, bid_size: bid.size, ask: ask.price, ask_size: ask.size, contract_id, contract_type: 0, clock: 1 };
+            trades.extend(strat.process_opts_update(WebSocketMsg::BookTop(bt), t0));
+        }
+
+        // 160 clear, and half that is traded; the long call reaches its second offer
+        assert_eq!(trades.len(), 1);
+        assert_eq!(legs(&trades[0]), vec![
+            (22248027, false, 11200.0, 80),
+            (22248028, true, 500.0, 80),
+            (22248029, true, 9300.0, 80),
+            (22248030, false, 500.0, 80),
+        ]);
+    }
+
+    #[test]
+    fn best_box_of_several() {
+        // strikes at 12000 and 14000 besides the mock 10000
+        let table = {
+            let mut table = mock_contract_table();
+            for (i, strike) in [12000, 14000].into_iter().enumerate() {
+                for (from, id) in [(22248027, 22248029 + 2 * i as u64), (22248028, 22248030 + 2 * i as u64)] {
+                    let spec = match &*table.id_table[&from] {
+                        ContractSpec::Option(spec) => OptionContractSpec { id, strike_price: strike, ..spec.clone() },
+                        _ => unreachable!(),
+                    };
+                    table.id_table.insert(id, Rc::new(ContractSpec::Option(spec)));
+                }
+            }
+            table
+        };
+        // the 10000 synthetic's offer boxes against the bids at both other strikes, by more
+        // at 14000; the two don't box against each other
+        let quotes = [
+            (22248029, 9300.0, 9400.0),
+            (22248030, 450.0, 500.0),
+            (22248031, 7400.0, 7500.0),
+            (22248032, 450.0, 500.0),
+            (22248027, 11070.0, 11180.0),
+            (22248028, 500.0, 580.0),
+        ];
+
+        // one box for the update, not one per strike each sized off the same 10000 quotes
+        let (trades, arbs) = run_spreads(table, true, false, &quotes);
+        assert_eq!(trades.len(), 1);
+        assert_eq!(arbs, ArbCounts { boxes: 1, ..ArbCounts::default() });
+        assert_eq!(legs(&trades[0]), vec![
+            (22248027, false, 11180.0, 50),
+            (22248028, true, 500.0, 50),
+            (22248031, true, 7400.0, 50),
+            (22248032, false, 500.0, 50),
+        ]);
+    }
+
+    #[test]
+    fn jelly_roll_between_expiries() {
+        // the same strike a quarter later
+        let far_tte = 0.6600180575256285 + 0.25;
+        let table = || second_level_table(|spec| {
+            spec.tte = far_tte;
+            spec.date_expires = DateTime::<Utc>::MIN_UTC + chrono::Duration::days(91);
+        });
+        // the far synthetic bids 100 over the near offer, where a flat 2% carries ~49
+        let quotes = [
+            (22248027, 11070.0, 11180.0),
+            (22248028, 500.0, 580.0),
+            (22248029, 11300.0, 11400.0),
+            (22248030, 480.0, 520.0),
+        ];
+
+        assert!(run_spreads(table(), true, false, &quotes).0.is_empty());
+
+        let (trades, arbs) = run_spreads(table(), false, true, &quotes);
+        assert_eq!(trades.len(), 1);
+        assert_eq!(arbs, ArbCounts { rolls: 1, ..ArbCounts::default() });
+        // long the near synthetic, short the far one
+        assert_eq!(legs(&trades[0]), vec![
+            (22248027, false, 11180.0, 50),
+            (22248028, true, 500.0, 50),
+            (22248029, true, 11300.0, 50),
+            (22248030, false, 520.0, 50),
+        ]);
+
+        // which is lending at ~4% between the expiries against a flat 2%
+        let near_tte = 0.6600180575256285;
+        let implied = implied_forward_rate(0.02, 10000, near_tte, 11180.0 - 500.0, far_tte, 11300.0 - 520.0).unwrap();
+        assert!((implied - 0.0407).abs() < 1e-3, "{}", implied);
+        // fair quotes give back the flat rate
+        let carry = 10000.0 * ((-0.02 * near_tte).exp() - (-0.02 * far_tte).exp());
+        let flat = implied_forward_rate(0.02, 10000, near_tte, 10680.0, far_tte, 10680.0 + carry).unwrap();
+        assert!((flat - 0.02).abs() < 1e-12);
+    }
+
+    #[test]
+    fn best_roll_of_several() {
+        // the same strike a quarter and half a year later
+        let near_tte = 0.6600180575256285;
+        let table = {
+            let mut table = mock_contract_table();
+            for (i, quarters) in [1, 2].into_iter().enumerate() {
+                for (from, id) in [(22248027, 22248029 + 2 * i as u64), (22248028, 22248030 + 2 * i as u64)] {
+                    let spec = match &*table.id_table[&from] {
+                        ContractSpec::Option(spec) => OptionContractSpec {
+                            id,
+                            tte: near_tte + 0.25 * quarters as f64,
+                            date_expires: DateTime::<Utc>::MIN_UTC + chrono::Duration::days(91 * quarters),
+                            ..spec.clone()
+                        },
+                        _ => unreachable!(),
+                    };
+                    table.id_table.insert(id, Rc::new(ContractSpec::Option(spec)));
+                }
+            }
+            table
+        };
+        // the near offer rolls against the bids in both later expiries, by more into the
+        // last; the two later ones don't roll against each other
+        let quotes = [
+            (22248029, 11300.0, 11400.0),
+            (22248030, 480.0, 520.0),
+            (22248031, 11450.0, 11550.0),
+            (22248032, 480.0, 520.0),
+            (22248027, 11070.0, 11180.0),
+            (22248028, 500.0, 580.0),
+        ];
+
+        let (trades, arbs) = run_spreads(table, false, true, &quotes);
+        assert_eq!(trades.len(), 1);
+        assert_eq!(arbs, ArbCounts { rolls: 1, ..ArbCounts::default() });
+        assert_eq!(legs(&trades[0]), vec![
+            (22248027, false, 11180.0, 50),
+            (22248028, true, 500.0, 50),
+            (22248031, true, 11450.0, 50),
+            (22248032, false, 520.0, 50),
+        ]);
     }
 
     #[test]
@@ -0,0 +1,109 @@
+use serde::Deserialize;
+
+// LedgerX underlying -> Binance spot pair we hedge it with
+const KNOWN_MAPPINGS: [(&str, &str); 2] = [
+    ("CBTC", "BTCUSDT"),
+    ("CETH", "ETHUSDT"),
+];
+
+// what Binance spot pairs are quoted in; FDUSD ahead of USD-suffixed ones it would otherwise
+// be mistaken for
+const QUOTE_ASSETS: [&str; 7] = ["FDUSD", "USDT", "USDC", "BUSD", "TUSD", "BTC", "ETH"];
+
+// the coin a Binance spot pair trades, e.g. BTC for BTCUSDT
+pub fn binance_base_asset(symbol: &str) -> Option<&str> {
+    QUOTE_ASSETS.iter()
+        .find_map(|quote| symbol.strip_suffix(quote))
+        .filter(|base| !base.is_empty())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "RawSymbolMapping")]
+pub struct SymbolMapping {
+    pub ledgerx: String,
+    pub binance: String,
+}
+
+// as written in the config file; `binance` may be left out for known underlyings
+#[derive(Deserialize)]
+struct RawSymbolMapping {
+    ledgerx: String,
+    binance: Option<String>,
+}
+
+impl TryFrom<RawSymbolMapping> for SymbolMapping {
+    type Error = String;
+
+    fn try_from(raw: RawSymbolMapping) -> Result<Self, Self::Error> {
+        match raw.binance {
+            Some(binance) => Ok(SymbolMapping { ledgerx: raw.ledgerx, binance: binance.to_uppercase() }),
+            None => SymbolMapping::from_ledgerx(&raw.ledgerx).ok_or_else(|| format!(
+                "no known Binance pair for LedgerX underlying {:?}; set `binance` explicitly",
+                raw.ledgerx,
+            )),
+        }
+    }
+}
+
+impl SymbolMapping {
+    pub fn new(ledgerx: &str, binance: &str) -> Self {
+        Self {
+            ledgerx: ledgerx.to_owned(),
+            binance: binance.to_uppercase(),
+        }
+    }
+
+    pub fn from_ledgerx(underlying: &str) -> Option<Self> {
+        KNOWN_MAPPINGS.iter()
+            .find(|(lx, _)| *lx == underlying)
+            .map(|(lx, bn)| Self::new(lx, bn))
+    }
+
+    pub fn from_binance(symbol: &str) -> Option<Self> {
+        KNOWN_MAPPINGS.iter()
+            .find(|(_, bn)| bn.eq_ignore_ascii_case(symbol))
+            .map(|(lx, bn)| Self::new(lx, bn))
+    }
+
+    // binance stream names use the lowercase symbol, e.g. "btcusdt@bookTicker"
+    pub fn binance_stream(&self, stream: &str) -> String {
+        format!("{}@{}", self.binance.to_lowercase(), stream)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::{binance_base_asset, SymbolMapping};
+
+    #[derive(Deserialize)]
+    struct Wrapper {
+        symbols: SymbolMapping,
+    }
+
+    #[test]
+    fn known_underlyings() {
+        let eth = SymbolMapping::from_ledgerx("CETH").unwrap();
+        assert_eq!(eth.binance, "ETHUSDT");
+        assert_eq!(eth.binance_stream("bookTicker"), "ethusdt@bookTicker");
+
+        assert_eq!(SymbolMapping::from_binance("btcusdt").unwrap().ledgerx, "CBTC");
+        assert!(SymbolMapping::from_ledgerx("CDOGE").is_none());
+
+        assert_eq!(binance_base_asset("ETHUSDT"), Some("ETH"));
+        assert_eq!(binance_base_asset("ETHBTC"), Some("ETH"));
+        assert_eq!(binance_base_asset("BTC"), None);
+    }
+
+    #[test]
+    fn from_config() {
+        let implied: Wrapper = toml::from_str("[symbols]\nledgerx = \"CBTC\"").unwrap();
+        assert_eq!(implied.symbols, SymbolMapping::new("CBTC", "BTCUSDT"));
+
+        let explicit: Wrapper = toml::from_str("[symbols]\nledgerx = \"CBTC\"\nbinance = \"btcusdc\"").unwrap();
+        assert_eq!(explicit.symbols.binance, "BTCUSDC");
+
+        assert!(toml::from_str::<Wrapper>("[symbols]\nledgerx = \"CDOGE\"").is_err());
+    }
+}
@@ -0,0 +1,133 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashMap};
+use std::ops::Bound;
+
+use crate::options_chain::ContractIdx;
+
+// f64 with a total order, so thresholds can key a BTreeSet
+#[derive(Debug, Clone, Copy)]
+struct Price(f64);
+
+impl PartialEq for Price {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+impl Eq for Price {}
+impl PartialOrd for Price {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for Price {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+// the spot prices past which a level's combos pay, given its current option quotes
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LevelThresholds {
+    // a conversion pays when the spot ask is below this
+    pub conv_below: Option<f64>,
+    // a reversal pays when the spot bid is above this
+    pub rev_above: Option<f64>,
+}
+
+// Per-level thresholds kept sorted, so a spot tick only visits the levels it puts in the
+// money instead of the whole chain. Levels are keyed by their call.
+#[derive(Debug, Default)]
+pub struct ThresholdIndex {
+    levels: HashMap<ContractIdx, LevelThresholds>,
+    conv: BTreeSet<(Price, ContractIdx)>,
+    rev: BTreeSet<(Price, ContractIdx)>,
+}
+
+impl ThresholdIndex {
+    pub fn update(&mut self, level: ContractIdx, thresholds: LevelThresholds) {
+        self.remove(level);
+        if let Some(p) = thresholds.conv_below {
+            self.conv.insert((Price(p), level));
+        }
+        if let Some(p) = thresholds.rev_above {
+            self.rev.insert((Price(p), level));
+        }
+        self.levels.insert(level, thresholds);
+    }
+
+    pub fn remove(&mut self, level: ContractIdx) {
+        if let Some(old) = self.levels.remove(&level) {
+            if let Some(p) = old.conv_below {
+                self.conv.remove(&(Price(p), level));
+            }
+            if let Some(p) = old.rev_above {
+                self.rev.remove(&(Price(p), level));
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    pub fn get(&self, level: ContractIdx) -> Option<LevelThresholds> {
+        self.levels.get(&level).cloned()
+    }
+
+    pub fn conv_candidates(&self, spot_ask: f64) -> impl Iterator<Item = ContractIdx> + '_ {
+        self.conv
+            .range((Bound::Excluded((Price(spot_ask), ContractIdx::MAX)), Bound::Unbounded))
+            .map(|&(_, level)| level)
+    }
+
+    pub fn rev_candidates(&self, spot_bid: f64) -> impl Iterator<Item = ContractIdx> + '_ {
+        self.rev
+            .range(..(Price(spot_bid), ContractIdx::MIN))
+            .map(|&(_, level)| level)
+    }
+
+    // levels either combo might pay on, each once
+    pub fn candidates(&self, spot_bid: f64, spot_ask: f64) -> Vec<ContractIdx> {
+        let mut out: Vec<ContractIdx> = self.conv_candidates(spot_ask).chain(self.rev_candidates(spot_bid)).collect();
+        out.sort_unstable();
+        out.dedup();
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LevelThresholds, ThresholdIndex};
+
+    fn both(conv_below: f64, rev_above: f64) -> LevelThresholds {
+        LevelThresholds { conv_below: Some(conv_below), rev_above: Some(rev_above) }
+    }
+
+    #[test]
+    fn candidates_are_levels_past_their_threshold() {
+        let mut index = ThresholdIndex::default();
+        index.update(0, both(20300.0, 20500.0));
+        index.update(1, both(20350.0, 20450.0));
+        index.update(2, LevelThresholds { conv_below: None, rev_above: Some(20400.0) });
+
+        assert_eq!(index.candidates(20399.0, 20400.0), Vec::<usize>::new());
+        // strictly past: an ask right at a threshold doesn't pay
+        assert_eq!(index.conv_candidates(20300.0).collect::<Vec<_>>(), vec![1]);
+        assert_eq!(index.conv_candidates(20299.0).collect::<Vec<_>>(), vec![0, 1]);
+        assert_eq!(index.rev_candidates(20460.0).collect::<Vec<_>>(), vec![2, 1]);
+        assert_eq!(index.candidates(20460.0, 20340.0), vec![1, 2]);
+    }
+
+    #[test]
+    fn updates_replace_old_thresholds() {
+        let mut index = ThresholdIndex::default();
+        index.update(0, both(20300.0, 20500.0));
+        index.update(0, both(20100.0, 20600.0));
+        assert!(index.candidates(20550.0, 20200.0).is_empty());
+        assert_eq!(index.candidates(20650.0, 20050.0), vec![0]);
+
+        index.remove(0);
+        assert_eq!(index.get(0), None);
+        assert!(index.candidates(f64::INFINITY, 0.0).is_empty());
+    }
+}